@@ -0,0 +1,346 @@
+// Anti-aliasing for IntSar-3D
+//
+// Three approaches are available, one at a time:
+//
+// - MSAA rasterizes the main pass with several samples per pixel and resolves
+//   them into the HDR target. It smooths geometry edges but not shading.
+// - FXAA finds edges in the tone-mapped frame by their contrast and blurs
+//   along them, as the first display stage post-processing pass.
+// - TAA offsets the projection by a different subpixel amount every frame and
+//   blends each frame with the previous ones, reprojected along per-pixel
+//   motion vectors. Neighborhood clamping rejects history that no longer
+//   matches the scene.
+
+use crate::deferred::RenderPath;
+use crate::postprocess::{PostStage, ShaderEffect};
+use crate::tonemap::HDR_FORMAT;
+use glam::Vec2;
+use std::fmt;
+
+/// Format of the motion vectors written by the main pass under TAA.
+pub(crate) const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+/// Number of subpixel offsets TAA cycles through.
+const JITTER_SAMPLES: u32 = 8;
+
+/// Share of each new frame in the accumulated TAA result.
+const TAA_BLEND: f32 = 0.1;
+
+/// How a [`Renderer`](crate::renderer::Renderer) smooths jagged edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    /// Multisampling with the given number of samples per pixel, which must
+    /// be one of [`Renderer::supported_sample_counts`](crate::renderer::Renderer::supported_sample_counts).
+    Msaa(u32),
+    /// Fast approximate anti-aliasing of the tone-mapped frame.
+    Fxaa,
+    /// Temporal anti-aliasing, which converges over several frames.
+    Taa,
+}
+
+impl AntiAliasing {
+    /// Samples per pixel of the main pass.
+    pub(crate) fn sample_count(self) -> u32 {
+        match self {
+            AntiAliasing::Msaa(count) => count,
+            _ => 1,
+        }
+    }
+}
+
+/// Error produced when selecting anti-aliasing the adapter or render path
+/// can't do.
+#[derive(Debug)]
+pub enum AntiAliasingError {
+    /// MSAA was requested with a sample count the adapter doesn't support.
+    UnsupportedSampleCount { requested: u32, supported: Vec<u32> },
+    /// The render path can't rasterize with several samples per pixel.
+    UnsupportedRenderPath { render_path: RenderPath },
+}
+
+impl fmt::Display for AntiAliasingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AntiAliasingError::UnsupportedSampleCount { requested, supported } => {
+                write!(f, "{requested}x MSAA is not supported (supported sample counts: {supported:?})")
+            }
+            AntiAliasingError::UnsupportedRenderPath { render_path } => {
+                write!(f, "MSAA is not supported by the {render_path:?} render path")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AntiAliasingError {}
+
+/// The FXAA pass, as a display stage effect.
+pub(crate) fn fxaa() -> ShaderEffect {
+    ShaderEffect::new("FXAA", PostStage::Display, include_str!("fxaa.wgsl")).expect("FXAA shader is valid")
+}
+
+/// Element `index` of the Halton sequence in `base`, in 0..1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Subpixel offset of the projection for a frame, in pixels within -0.5..0.5.
+pub(crate) fn jitter(frame: u32) -> Vec2 {
+    // Halton(2, 3) covers the pixel evenly; index 0 would sit in the corner
+    let index = frame % JITTER_SAMPLES + 1;
+    Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniforms {
+    blend: f32,
+    /// Nonzero when the history is stale and the frame should be taken as is.
+    reset: u32,
+    _padding: [u32; 2],
+}
+
+/// Motion vectors and accumulated history for TAA, and the pass that blends
+/// each frame into them.
+pub(crate) struct TemporalResolve {
+    velocity: wgpu::TextureView,
+    /// The result of the last frame is in `history[current]`.
+    history: [wgpu::TextureView; 2],
+    current: usize,
+    /// Whether the history holds a frame that can be reprojected.
+    valid: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl TemporalResolve {
+    pub(crate) fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture_entry = |binding, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Bind Group Layout"),
+            entries: &[
+                texture_entry(0, false),
+                texture_entry(1, true),
+                texture_entry(2, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("TAA Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("taa.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let target = Some(wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("TAA Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                // The frame for the rest of the pipeline, and the history
+                // for the next frame
+                targets: &[target.clone(), target],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("TAA Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TAA Uniform Buffer"),
+            size: std::mem::size_of::<TaaUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            velocity: Self::create_view(device, "Velocity Texture", VELOCITY_FORMAT, width, height),
+            history: [
+                Self::create_view(device, "TAA History 0", HDR_FORMAT, width, height),
+                Self::create_view(device, "TAA History 1", HDR_FORMAT, width, height),
+            ],
+            current: 0,
+            valid: false,
+            bind_group_layout,
+            pipeline,
+            sampler,
+            uniform_buffer,
+        }
+    }
+
+    fn create_view(
+        device: &wgpu::Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> wgpu::TextureView {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Target the main pass writes motion vectors into, in UV units from the
+    /// previous frame's position to the current one.
+    pub(crate) fn velocity_view(&self) -> &wgpu::TextureView {
+        &self.velocity
+    }
+
+    /// Zeroes the motion vectors, as the background doesn't move. This is a
+    /// pass of its own because clearing them alongside the HDR target in the
+    /// main pass clears the HDR target to zero on some drivers (lavapipe).
+    pub(crate) fn clear_velocity(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Velocity Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.velocity,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+
+    /// Blends the main pass's `input` into the history, drawing the result
+    /// into `output` as well.
+    pub(crate) fn resolve(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&TaaUniforms {
+            blend: TAA_BLEND,
+            reset: !self.valid as u32,
+            _padding: [0; 2],
+        }));
+
+        let next = 1 - self.current;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.history[self.current]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.velocity),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        {
+            let attachment = |view| Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            });
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("TAA Resolve Pass"),
+                color_attachments: &[attachment(output), attachment(&self.history[next])],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        self.current = next;
+        self.valid = true;
+    }
+}
@@ -0,0 +1,391 @@
+// Bloom for IntSar-3D
+//
+// Light brighter than the threshold bleeds into its surroundings, like the
+// glare a real lens scatters around emissive materials and strong
+// highlights. The bright parts of the frame are downsampled into a chain of
+// half-sized levels, each blurring a little wider, then upsampled back up
+// the chain with every level added onto the one above. The result is added
+// to the HDR frame before tone mapping.
+//
+// The downsample follows Jimenez's 13-tap filter from "Next Generation Post
+// Processing in Call of Duty: Advanced Warfare", with a Karis average on the
+// first step so single bright pixels don't flicker.
+
+use crate::postprocess::{PostEffect, PostFrame, PostStage};
+use bytemuck::{Pod, Zeroable};
+
+/// Most levels in the bloom chain.
+pub const MAX_BLOOM_LEVELS: u32 = 8;
+
+/// Settings of a [`Bloom`] effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    /// How much of the blurred light is added to the frame.
+    pub intensity: f32,
+    /// Linear brightness above which light blooms. At 1.0 only emissive
+    /// materials and highlights brighter than white do.
+    pub threshold: f32,
+    /// Width of the range below the threshold over which bloom fades in,
+    /// so it doesn't switch on abruptly.
+    pub knee: f32,
+    /// Spread of each upsampling step, in texels of the level being
+    /// upsampled. Larger values give a wider, softer glow.
+    pub radius: f32,
+    /// Number of half-sized levels in the chain, up to [`MAX_BLOOM_LEVELS`].
+    /// Each level doubles how far the glow reaches.
+    pub levels: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.3,
+            threshold: 1.0,
+            knee: 0.2,
+            radius: 1.0,
+            levels: 6,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct BloomUniforms {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+    levels: f32,
+    _padding: [f32; 3],
+}
+
+/// Post-processing effect that makes bright light glow.
+///
+/// Add it to a renderer with
+/// [`Renderer::add_post_effect`](crate::renderer::Renderer::add_post_effect).
+pub struct Bloom {
+    pub settings: BloomSettings,
+    /// Whether the effect runs; a disabled bloom costs nothing.
+    pub enabled: bool,
+    gpu: Option<GpuBloom>,
+}
+
+/// Pipelines for one target format.
+struct BloomPipelines {
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+}
+
+/// The level chain for one frame size.
+struct BloomChain {
+    width: u32,
+    height: u32,
+    levels: u32,
+    views: Vec<wgpu::TextureView>,
+    /// Binds each level for reading.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+struct GpuBloom {
+    pipelines: BloomPipelines,
+    chain: Option<BloomChain>,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self::new(BloomSettings::default())
+    }
+}
+
+impl Bloom {
+    /// Creates an enabled bloom effect.
+    pub fn new(settings: BloomSettings) -> Self {
+        Self {
+            settings,
+            enabled: true,
+            gpu: None,
+        }
+    }
+
+    /// Number of levels a `width`x`height` frame gets: as many as asked
+    /// for, stopping before a level would be under 2 texels across.
+    fn level_count(&self, width: u32, height: u32) -> u32 {
+        let smallest_side = (width / 2).min(height / 2).max(1);
+        let fit = smallest_side.ilog2().max(1);
+        self.settings.levels.clamp(1, MAX_BLOOM_LEVELS).min(fit)
+    }
+}
+
+impl BloomPipelines {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bloom.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        // The composite pass reads a level of the chain as a second group
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Composite Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, layout, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bloom Uniform Buffer"),
+            size: std::mem::size_of::<BloomUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            format,
+            prefilter: create_pipeline("Bloom Prefilter Pipeline", &layout, "fs_prefilter", None),
+            downsample: create_pipeline("Bloom Downsample Pipeline", &layout, "fs_downsample", None),
+            upsample: create_pipeline("Bloom Upsample Pipeline", &layout, "fs_upsample", Some(additive)),
+            composite: create_pipeline("Bloom Composite Pipeline", &composite_layout, "fs_composite", None),
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+        }
+    }
+
+    fn bind(&self, device: &wgpu::Device, view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+impl BloomChain {
+    /// Creates `levels` levels for a `width`x`height` frame, the first at
+    /// half its size.
+    fn new(device: &wgpu::Device, pipelines: &BloomPipelines, width: u32, height: u32, levels: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bloom Texture"),
+            size: wgpu::Extent3d {
+                width: (width / 2).max(1),
+                height: (height / 2).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: pipelines.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let views: Vec<_> = (0..levels)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Bloom Level"),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let bind_groups = views.iter().map(|view| pipelines.bind(device, view)).collect();
+        Self {
+            width,
+            height,
+            levels,
+            views,
+            bind_groups,
+        }
+    }
+}
+
+/// Draws a fullscreen triangle into `target`.
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    pass.draw(0..3, 0..1);
+}
+
+impl PostEffect for Bloom {
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn render(&mut self, frame: &mut PostFrame<'_>) {
+        let levels = self.level_count(frame.width, frame.height);
+
+        let gpu = match &mut self.gpu {
+            Some(gpu) if gpu.pipelines.format == frame.format => gpu,
+            gpu => gpu.insert(GpuBloom {
+                pipelines: BloomPipelines::new(frame.device, frame.format),
+                chain: None,
+            }),
+        };
+        let pipelines = &gpu.pipelines;
+        let chain = match &mut gpu.chain {
+            Some(chain) if (chain.width, chain.height, chain.levels) == (frame.width, frame.height, levels) => chain,
+            chain => chain.insert(BloomChain::new(frame.device, pipelines, frame.width, frame.height, levels)),
+        };
+
+        frame.queue.write_buffer(&pipelines.uniform_buffer, 0, bytemuck::bytes_of(&BloomUniforms {
+            threshold: self.settings.threshold,
+            knee: self.settings.knee.max(0.0),
+            intensity: self.settings.intensity,
+            radius: self.settings.radius,
+            levels: levels as f32,
+            _padding: [0.0; 3],
+        }));
+
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let input = pipelines.bind(frame.device, frame.input);
+        fullscreen_pass(frame.encoder, "Bloom Prefilter", &chain.views[0], clear, &pipelines.prefilter, &[&input]);
+        for level in 1..levels as usize {
+            fullscreen_pass(
+                frame.encoder,
+                "Bloom Downsample",
+                &chain.views[level],
+                clear,
+                &pipelines.downsample,
+                &[&chain.bind_groups[level - 1]],
+            );
+        }
+        for level in (1..levels as usize).rev() {
+            fullscreen_pass(
+                frame.encoder,
+                "Bloom Upsample",
+                &chain.views[level - 1],
+                wgpu::LoadOp::Load,
+                &pipelines.upsample,
+                &[&chain.bind_groups[level]],
+            );
+        }
+        fullscreen_pass(
+            frame.encoder,
+            "Bloom Composite",
+            frame.output,
+            clear,
+            &pipelines.composite,
+            &[&input, &chain.bind_groups[0]],
+        );
+    }
+}
@@ -0,0 +1,142 @@
+// Bloom shader for IntSar-3D
+
+struct BloomUniforms {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+    levels: f32,
+};
+
+// Texture being filtered: the frame for the prefilter and composite passes,
+// otherwise a level of the bloom chain
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> bloom: BloomUniforms;
+
+// Finished bloom chain, read by the composite pass
+@group(1) @binding(0)
+var bloom_texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var bloom_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    return textureSample(source, source_sampler, uv + offset * texel).rgb;
+}
+
+// Karis average: weighting by inverse luminance keeps single very bright
+// pixels from flickering into large blobs
+fn karis_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + luminance(color));
+}
+
+// Jorge Jimenez's 13-tap downsample from "Next Generation Post Processing in
+// Call of Duty: Advanced Warfare", as five overlapping 2x2 boxes
+fn downsample(uv: vec2<f32>, karis: bool) -> vec3<f32> {
+    let a = sample_source(uv, vec2<f32>(-2.0, -2.0));
+    let b = sample_source(uv, vec2<f32>(0.0, -2.0));
+    let c = sample_source(uv, vec2<f32>(2.0, -2.0));
+    let d = sample_source(uv, vec2<f32>(-2.0, 0.0));
+    let e = sample_source(uv, vec2<f32>(0.0, 0.0));
+    let f = sample_source(uv, vec2<f32>(2.0, 0.0));
+    let g = sample_source(uv, vec2<f32>(-2.0, 2.0));
+    let h = sample_source(uv, vec2<f32>(0.0, 2.0));
+    let i = sample_source(uv, vec2<f32>(2.0, 2.0));
+    let j = sample_source(uv, vec2<f32>(-1.0, -1.0));
+    let k = sample_source(uv, vec2<f32>(1.0, -1.0));
+    let l = sample_source(uv, vec2<f32>(-1.0, 1.0));
+    let m = sample_source(uv, vec2<f32>(1.0, 1.0));
+
+    var boxes = array<vec3<f32>, 5>(
+        (j + k + l + m) * 0.25,
+        (a + b + d + e) * 0.25,
+        (b + c + e + f) * 0.25,
+        (d + e + g + h) * 0.25,
+        (e + f + h + i) * 0.25,
+    );
+    var weights = array<f32, 5>(0.5, 0.125, 0.125, 0.125, 0.125);
+
+    var color = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var n = 0; n < 5; n++) {
+        var weight = weights[n];
+        if karis {
+            weight *= karis_weight(boxes[n]);
+        }
+        color += boxes[n] * weight;
+        total += weight;
+    }
+    return color / total;
+}
+
+// Keeps what is brighter than the threshold, easing in over the knee below it
+fn bright_pass(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 0.00001);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+// 3x3 tent filter, spread by the radius in texels of the filtered texture
+fn tent(filtered: texture_2d<f32>, filtered_sampler: sampler, uv: vec2<f32>) -> vec3<f32> {
+    let step = bloom.radius / vec2<f32>(textureDimensions(filtered));
+    var edges = array<vec2<f32>, 4>(vec2<f32>(0.0, -1.0), vec2<f32>(-1.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0));
+    var corners = array<vec2<f32>, 4>(vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(-1.0, 1.0), vec2<f32>(1.0, 1.0));
+    var color = textureSample(filtered, filtered_sampler, uv).rgb * 4.0;
+    for (var n = 0; n < 4; n++) {
+        color += textureSample(filtered, filtered_sampler, uv + edges[n] * step).rgb * 2.0;
+        color += textureSample(filtered, filtered_sampler, uv + corners[n] * step).rgb;
+    }
+    return color / 16.0;
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(bright_pass(downsample(in.uv, true)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv, false), 1.0);
+}
+
+// Added onto the next larger level
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(tent(source, source_sampler, in.uv), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = textureSample(source, source_sampler, in.uv);
+    // Each level of the chain adds its own copy of the bright pass
+    let glow = tent(bloom_texture, bloom_sampler, in.uv) / bloom.levels;
+    return vec4<f32>(frame.rgb + glow * bloom.intensity, frame.a);
+}
@@ -0,0 +1,47 @@
+// Camera module for IntSar-3D
+
+use glam::Mat4;
+use serde::{Deserialize, Serialize};
+
+/// Projection of a camera attached to a scene object.
+///
+/// The camera looks down the object's local -Z axis with +Y up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Camera {
+    Perspective {
+        /// Vertical field of view in radians.
+        fov_y: f32,
+        /// Width over height; `None` uses the aspect ratio of the viewport.
+        aspect_ratio: Option<f32>,
+        near: f32,
+        /// `None` for an infinite far plane.
+        far: Option<f32>,
+    },
+    Orthographic {
+        /// Half the width of the view volume.
+        x_mag: f32,
+        /// Half the height of the view volume.
+        y_mag: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Camera {
+    /// Returns the projection matrix, using `viewport_aspect` when the camera
+    /// doesn't fix its own aspect ratio.
+    pub fn projection_matrix(&self, viewport_aspect: f32) -> Mat4 {
+        match *self {
+            Camera::Perspective { fov_y, aspect_ratio, near, far } => {
+                let aspect = aspect_ratio.unwrap_or(viewport_aspect);
+                match far {
+                    Some(far) => Mat4::perspective_rh(fov_y, aspect, near, far),
+                    None => Mat4::perspective_infinite_rh(fov_y, aspect, near),
+                }
+            }
+            Camera::Orthographic { x_mag, y_mag, near, far } => {
+                Mat4::orthographic_rh(-x_mag, x_mag, -y_mag, y_mag, near, far)
+            }
+        }
+    }
+}
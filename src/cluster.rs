@@ -0,0 +1,325 @@
+// Clustered light culling for IntSar-3D
+//
+// The view frustum is cut into a grid of clusters: tiles across the screen,
+// times slices along the view direction whose depth grows exponentially, so
+// near and far clusters stay roughly as deep as they are wide. Before the
+// main pass, a compute pass tests the volume of every light against the
+// view-space bounding box of each cluster and lists the lights that reach it.
+// The forward shader looks up the cluster its fragment falls in and shades
+// only the lights in that list, so a fragment costs as much as the lights
+// near it rather than every light in the scene. `assign_lights` does the same
+// assignment on the CPU, as a reference for the compute pass.
+
+use crate::deferred;
+use crate::light::Light;
+use crate::renderer::MAX_LIGHTS;
+use crate::shadow::Frustum;
+use glam::{Mat4, UVec3, Vec2, Vec3};
+use std::sync::mpsc;
+
+/// Clusters across the screen, down it, and along the view direction.
+pub const CLUSTER_GRID: UVec3 = UVec3::new(16, 9, 24);
+
+/// Number of clusters in [`CLUSTER_GRID`].
+pub const CLUSTER_COUNT: usize = (CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z) as usize;
+
+/// Most lights one cluster lists; lights beyond these are left out of it.
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 128;
+
+/// Words per cluster in the cluster buffer: the light count, then the lights.
+const CLUSTER_STRIDE: usize = 1 + MAX_LIGHTS_PER_CLUSTER;
+
+/// Clusters each workgroup of the assignment pass handles.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Index of the cluster at a grid position, with x varying fastest and z slowest.
+pub fn cluster_index(cluster: UVec3) -> usize {
+    (cluster.x + (cluster.y + cluster.z * CLUSTER_GRID.y) * CLUSTER_GRID.x) as usize
+}
+
+/// Grid position of the cluster at an index; the inverse of [`cluster_index`].
+pub fn cluster_position(index: usize) -> UVec3 {
+    let index = index as u32;
+    UVec3::new(
+        index % CLUSTER_GRID.x,
+        index / CLUSTER_GRID.x % CLUSTER_GRID.y,
+        index / (CLUSTER_GRID.x * CLUSTER_GRID.y),
+    )
+}
+
+/// View distance at which a depth slice starts, and the one before it ends.
+fn slice_depth(frustum: &Frustum, slice: u32) -> f32 {
+    frustum.near * (frustum.far / frustum.near).powf(slice as f32 / CLUSTER_GRID.z as f32)
+}
+
+/// View-space x and y over view distance at the right and top screen edges.
+fn screen_scale(frustum: &Frustum) -> Vec2 {
+    let tan_y = (frustum.fov_y * 0.5).tan();
+    Vec2::new(tan_y * frustum.aspect_ratio, tan_y)
+}
+
+/// View-space bounding box of a cluster, as its minimum and maximum corners.
+/// Row 0 of the grid is at the top of the screen, and slice 0 at the near plane.
+pub fn cluster_bounds(frustum: &Frustum, cluster: UVec3) -> (Vec3, Vec3) {
+    let near = slice_depth(frustum, cluster.z);
+    let far = slice_depth(frustum, cluster.z + 1);
+
+    // Rows run down the screen, while NDC runs up
+    let grid = CLUSTER_GRID.truncate().as_vec2();
+    let corner = |x: u32, y: u32| Vec2::new(x as f32 / grid.x * 2.0 - 1.0, 1.0 - y as f32 / grid.y * 2.0);
+    let low = corner(cluster.x, cluster.y + 1);
+    let high = corner(cluster.x + 1, cluster.y);
+
+    // The sides of a cluster fan out from the camera, so the box spans both
+    // its near and its far face
+    let scale = screen_scale(frustum);
+    let mut min = Vec2::MAX;
+    let mut max = Vec2::MIN;
+    for depth in [near, far] {
+        for ndc in [low, high] {
+            let point = ndc * scale * depth;
+            min = min.min(point);
+            max = max.max(point);
+        }
+    }
+    (min.extend(-far), max.extend(-near))
+}
+
+/// Whether a sphere touches an axis-aligned box.
+fn sphere_touches_box(center: Vec3, radius: f32, (min, max): (Vec3, Vec3)) -> bool {
+    center.clamp(min, max).distance_squared(center) <= radius * radius
+}
+
+/// Lights that reach each cluster, by cluster index, as indices into
+/// `lights`. Each light is given with its world transform, and only the
+/// first [`MAX_LIGHTS`] count, as in the renderer.
+///
+/// Point and spot lights reach the clusters their [`deferred::light_radius`]
+/// touches, and directional lights reach every cluster. Each list keeps the
+/// lights in order, up to [`MAX_LIGHTS_PER_CLUSTER`].
+pub fn assign_lights(frustum: &Frustum, lights: &[(Light, Mat4)]) -> Vec<Vec<u32>> {
+    let volumes: Vec<Option<(Vec3, f32)>> = lights.iter()
+        .take(MAX_LIGHTS)
+        .map(|(light, world)| {
+            let center = frustum.view.transform_point3(world.transform_point3(Vec3::ZERO));
+            deferred::light_radius(light).map(|radius| (center, radius))
+        })
+        .collect();
+
+    (0..CLUSTER_COUNT)
+        .map(|index| {
+            let bounds = cluster_bounds(frustum, cluster_position(index));
+            volumes.iter()
+                .enumerate()
+                .filter(|(_, volume)| volume.is_none_or(|(center, radius)| sphere_touches_box(center, radius, bounds)))
+                .map(|(light, _)| light as u32)
+                .take(MAX_LIGHTS_PER_CLUSTER)
+                .collect()
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterUniforms {
+    view: [[f32; 4]; 4],
+    scale: [f32; 2],
+    near: f32,
+    far: f32,
+    light_count: u32,
+    _padding: [u32; 3],
+}
+
+/// The lights of a frame in a storage buffer, and the compute pass that
+/// sorts them into clusters.
+pub(crate) struct LightClusters {
+    /// The shader's `Light` of each light, up to [`MAX_LIGHTS`].
+    light_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    /// [`CLUSTER_STRIDE`] words per cluster, in cluster index order.
+    cluster_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
+    cluster_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl LightClusters {
+    /// Creates the buffers and the assignment pass. `light_size` is the size
+    /// of the shader's `Light`.
+    pub(crate) fn new(device: &wgpu::Device, light_size: usize) -> Self {
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Array Buffer"),
+            size: (light_size * MAX_LIGHTS) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Cluster Uniform Buffer"),
+            size: std::mem::size_of::<ClusterUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cluster_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Cluster Buffer"),
+            size: (CLUSTER_COUNT * CLUSTER_STRIDE * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // Only the lights of the main shader's group 0 are used
+        let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Array Bind Group Layout"),
+            entries: &[storage_entry(6, true)],
+        });
+        let cluster_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Cluster Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, false),
+            ],
+        });
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Array Bind Group"),
+            layout: &light_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 6,
+                resource: light_buffer.as_entire_binding(),
+            }],
+        });
+        let cluster_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Cluster Bind Group"),
+            layout: &cluster_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cluster_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader_code = concat!(include_str!("lighting.wgsl"), include_str!("cluster.wgsl"));
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Light Cluster Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Cluster Pipeline Layout"),
+            bind_group_layouts: &[&light_bind_group_layout, &cluster_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Light Cluster Pipeline"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: "cs_assign",
+        });
+
+        Self {
+            light_buffer,
+            uniform_buffer,
+            cluster_buffer,
+            light_bind_group,
+            cluster_bind_group,
+            pipeline,
+        }
+    }
+
+    /// Uploads the frame's lights, as the shader's `Light`, and the camera
+    /// they are sorted into clusters for. Lights beyond [`MAX_LIGHTS`] are
+    /// left out.
+    pub(crate) fn update<T: bytemuck::Pod>(&self, queue: &wgpu::Queue, frustum: &Frustum, lights: &[T]) {
+        let lights = &lights[..lights.len().min(MAX_LIGHTS)];
+        if !lights.is_empty() {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(lights));
+        }
+        let uniforms = ClusterUniforms {
+            view: frustum.view.to_cols_array_2d(),
+            scale: screen_scale(frustum).to_array(),
+            near: frustum.near,
+            far: frustum.far,
+            light_count: lights.len() as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Lists the lights that reach each cluster.
+    pub(crate) fn assign(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Cluster Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.light_bind_group, &[]);
+        pass.set_bind_group(1, &self.cluster_bind_group, &[]);
+        pass.dispatch_workgroups((CLUSTER_COUNT as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Storage buffer of every light, bound to the main shader.
+    pub(crate) fn light_buffer(&self) -> &wgpu::Buffer {
+        &self.light_buffer
+    }
+
+    /// Storage buffer of every cluster's lights, bound to the main shader.
+    pub(crate) fn cluster_buffer(&self) -> &wgpu::Buffer {
+        &self.cluster_buffer
+    }
+
+    /// Reads back the lights the last assignment listed for each cluster, in
+    /// the layout of [`assign_lights`].
+    pub(crate) fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Vec<Vec<u32>>> {
+        let size = self.cluster_buffer.size();
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Cluster Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Light Cluster Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.cluster_buffer, 0, &readback_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+
+        let words: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        readback_buffer.unmap();
+        let clusters = words.chunks_exact(CLUSTER_STRIDE)
+            .map(|cluster| {
+                let count = (cluster[0] as usize).min(MAX_LIGHTS_PER_CLUSTER);
+                cluster[1..=count].to_vec()
+            })
+            .collect();
+        Some(clusters)
+    }
+}
@@ -0,0 +1,81 @@
+// Light cluster assignment for IntSar-3D, appended to lighting.wgsl
+
+// Camera the clusters are laid out in, matching `ClusterUniforms` in cluster.rs
+struct ClusterUniforms {
+    // World-to-view transform
+    view: mat4x4<f32>,
+    // View-space x and y over view distance at the right and top screen edges
+    scale: vec2<f32>,
+    near: f32,
+    far: f32,
+    light_count: u32,
+};
+
+@group(1) @binding(0)
+var<uniform> clusters: ClusterUniforms;
+// Written in the layout `light_clusters` is read in
+@group(1) @binding(1)
+var<storage, read_write> assigned: array<u32>;
+
+// Illuminance below which a light is left out, matching `deferred::LIGHT_CUTOFF`
+const LIGHT_CUTOFF: f32 = 0.001;
+
+fn slice_depth(slice: u32) -> f32 {
+    return clusters.near * pow(clusters.far / clusters.near, f32(slice) / f32(CLUSTER_GRID.z));
+}
+
+// Distance a point or spot light reaches, matching `deferred::light_radius`
+fn light_radius(light: Light) -> f32 {
+    let brightest = max(max(max(light.color.r, light.color.g), light.color.b), 0.0);
+    let cutoff = sqrt(brightest / LIGHT_CUTOFF);
+    return select(cutoff, min(light.range, cutoff), light.range > 0.0);
+}
+
+// One invocation per cluster, testing every light against its view-space
+// bounding box like `cluster::assign_lights`
+@compute @workgroup_size(64)
+fn cs_assign(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z {
+        return;
+    }
+    let cluster = vec3<u32>(
+        index % CLUSTER_GRID.x,
+        index / CLUSTER_GRID.x % CLUSTER_GRID.y,
+        index / (CLUSTER_GRID.x * CLUSTER_GRID.y),
+    );
+    let near = slice_depth(cluster.z);
+    let far = slice_depth(cluster.z + 1u);
+
+    // Rows run down the screen, while NDC runs up
+    let grid = vec2<f32>(CLUSTER_GRID.xy);
+    let low = vec2<f32>(f32(cluster.x) / grid.x * 2.0 - 1.0, 1.0 - f32(cluster.y + 1u) / grid.y * 2.0);
+    let high = vec2<f32>(f32(cluster.x + 1u) / grid.x * 2.0 - 1.0, 1.0 - f32(cluster.y) / grid.y * 2.0);
+
+    // The sides of a cluster fan out from the camera, so the box spans both
+    // its near and its far face
+    let near_low = low * clusters.scale * near;
+    let near_high = high * clusters.scale * near;
+    let far_low = low * clusters.scale * far;
+    let far_high = high * clusters.scale * far;
+    let box_min = vec3<f32>(min(min(near_low, near_high), min(far_low, far_high)), -far);
+    let box_max = vec3<f32>(max(max(near_low, near_high), max(far_low, far_high)), -near);
+
+    let base = index * (MAX_LIGHTS_PER_CLUSTER + 1u);
+    var count = 0u;
+    for (var i = 0u; i < min(clusters.light_count, MAX_LIGHTS) && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let light = lights[i];
+        var reaches = true;
+        if light.kind != LIGHT_DIRECTIONAL {
+            let center = (clusters.view * vec4<f32>(light.position, 1.0)).xyz;
+            let offset = clamp(center, box_min, box_max) - center;
+            let radius = light_radius(light);
+            reaches = dot(offset, offset) <= radius * radius;
+        }
+        if reaches {
+            assigned[base + 1u + count] = i;
+            count += 1u;
+        }
+    }
+    assigned[base] = count;
+}
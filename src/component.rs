@@ -0,0 +1,386 @@
+// Component storage for IntSar-3D
+//
+// Every scene object can carry at most one component of each type. Components
+// of one type live together in a sparse set: a dense array that queries walk
+// in order, plus a table from object slot to dense position for O(1) lookup.
+
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::MaterialHandle;
+use crate::mesh::MeshHandle;
+use crate::scene::ObjectHandle;
+use crate::shadow::ShadowFlags;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Data that can be attached to a scene object.
+///
+/// Types opt in with an empty `impl Component for MyType {}`. Data every
+/// object already has, such as its name and [`Transform`](crate::math::Transform),
+/// is kept on the [`SceneObject`](crate::scene::SceneObject) itself and is
+/// not a component.
+pub trait Component: Any {}
+
+impl Component for MeshHandle {}
+impl Component for MaterialHandle {}
+impl Component for Camera {}
+impl Component for Light {}
+impl Component for ShadowFlags {}
+
+/// Components of a single type.
+struct Storage<T> {
+    handles: Vec<ObjectHandle>,
+    values: Vec<T>,
+    /// Dense position of each object slot's component, by slot index.
+    positions: Vec<Option<u32>>,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Self {
+            handles: Vec::new(),
+            values: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    fn position(&self, handle: ObjectHandle) -> Option<usize> {
+        let position = (*self.positions.get(handle.index as usize)?)? as usize;
+        (self.handles[position] == handle).then_some(position)
+    }
+
+    fn get(&self, handle: ObjectHandle) -> Option<&T> {
+        self.position(handle).map(|position| &self.values[position])
+    }
+
+    fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut T> {
+        self.position(handle).map(|position| &mut self.values[position])
+    }
+
+    fn insert(&mut self, handle: ObjectHandle, value: T) -> Option<T> {
+        if let Some(position) = self.position(handle) {
+            return Some(std::mem::replace(&mut self.values[position], value));
+        }
+
+        let index = handle.index as usize;
+        if index >= self.positions.len() {
+            self.positions.resize(index + 1, None);
+        }
+        self.positions[index] = Some(self.values.len() as u32);
+        self.handles.push(handle);
+        self.values.push(value);
+        None
+    }
+
+    fn remove(&mut self, handle: ObjectHandle) -> Option<T> {
+        let position = self.position(handle)?;
+        self.positions[handle.index as usize] = None;
+        self.handles.swap_remove(position);
+        let value = self.values.swap_remove(position);
+
+        // The last component moved into the gap
+        if let Some(&moved) = self.handles.get(position) {
+            self.positions[moved.index as usize] = Some(position as u32);
+        }
+        Some(value)
+    }
+}
+
+/// Type-erased operations on a [`Storage`].
+trait AnyStorage {
+    fn handles(&self) -> &[ObjectHandle];
+    fn remove_object(&mut self, handle: ObjectHandle);
+    fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> AnyStorage for Storage<T> {
+    fn handles(&self) -> &[ObjectHandle] {
+        &self.handles
+    }
+
+    fn remove_object(&mut self, handle: ObjectHandle) {
+        self.remove(handle);
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Storages of every component type used in a scene.
+#[derive(Default)]
+pub struct Components {
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl fmt::Debug for Components {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.storages.values().map(|storage| (storage.type_name(), storage.handles().len())))
+            .finish()
+    }
+}
+
+impl Components {
+    fn storage<T: Component>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<T: Component>(&mut self) -> Option<&mut Storage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+
+    pub(crate) fn get<T: Component>(&self, handle: ObjectHandle) -> Option<&T> {
+        self.storage::<T>()?.get(handle)
+    }
+
+    pub(crate) fn get_mut<T: Component>(&mut self, handle: ObjectHandle) -> Option<&mut T> {
+        self.storage_mut::<T>()?.get_mut(handle)
+    }
+
+    pub(crate) fn insert<T: Component>(&mut self, handle: ObjectHandle, value: T) -> Option<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .expect("component storage registered under the wrong type")
+            .insert(handle, value)
+    }
+
+    pub(crate) fn remove<T: Component>(&mut self, handle: ObjectHandle) -> Option<T> {
+        self.storage_mut::<T>()?.remove(handle)
+    }
+
+    /// Drops every component of an object.
+    pub(crate) fn remove_object(&mut self, handle: ObjectHandle) {
+        for storage in self.storages.values_mut() {
+            storage.remove_object(handle);
+        }
+    }
+
+    /// Objects that have a component of the given type, in storage order.
+    fn handles(&self, type_id: TypeId) -> &[ObjectHandle] {
+        self.storages.get(&type_id).map(|storage| storage.handles()).unwrap_or_default()
+    }
+
+    /// Objects with a `T`, paired with it.
+    pub(crate) fn iter<T: Component>(&self) -> impl Iterator<Item = (ObjectHandle, &T)> + '_ {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.handles.iter().copied().zip(&storage.values))
+    }
+
+    /// Objects that may match a query of `types`: those in its smallest storage.
+    pub(crate) fn candidates(&self, types: &[TypeId]) -> &[ObjectHandle] {
+        types.iter()
+            .map(|&type_id| self.handles(type_id))
+            .min_by_key(|handles| handles.len())
+            .unwrap_or_default()
+    }
+}
+
+/// A set of component types fetched together by [`Scene::query`](crate::scene::Scene::query).
+///
+/// Implemented for `&T` and for tuples of up to four `&T`s.
+pub trait Query<'a> {
+    type Item;
+
+    /// Types an object must have components of to match.
+    fn component_types() -> Vec<TypeId>;
+
+    /// Fetches the components of one object, or `None` if any is missing.
+    fn fetch(components: &'a Components, handle: ObjectHandle) -> Option<Self::Item>;
+}
+
+impl<'a, T: Component> Query<'a> for &'a T {
+    type Item = &'a T;
+
+    fn component_types() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+
+    fn fetch(components: &'a Components, handle: ObjectHandle) -> Option<&'a T> {
+        components.get::<T>(handle)
+    }
+}
+
+macro_rules! impl_query_for_tuple {
+    ($($name:ident),+) => {
+        impl<'a, $($name: Query<'a>),+> Query<'a> for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+
+            fn component_types() -> Vec<TypeId> {
+                let mut types = Vec::new();
+                $(types.extend($name::component_types());)+
+                types
+            }
+
+            fn fetch(components: &'a Components, handle: ObjectHandle) -> Option<Self::Item> {
+                Some(($($name::fetch(components, handle)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_for_tuple!(A);
+impl_query_for_tuple!(A, B);
+impl_query_for_tuple!(A, B, C);
+impl_query_for_tuple!(A, B, C, D);
+
+/// A set of component types fetched together by
+/// [`Scene::query_mut`](crate::scene::Scene::query_mut), each either shared
+/// or mutable.
+///
+/// Implemented for `&T`, `&mut T` and for tuples of up to four of them. No
+/// component type may appear twice in one query.
+pub trait QueryMut<'a> {
+    type Item;
+    /// Storages the query fetches from, looked up once per query.
+    #[doc(hidden)]
+    type Access;
+
+    /// Types an object must have components of to match.
+    fn component_types() -> Vec<TypeId>;
+
+    /// Looks up the storages of the query's types, or `None` if any has none.
+    #[doc(hidden)]
+    fn access(components: &mut Components) -> Option<Self::Access>;
+
+    /// Fetches the components of one object, or `None` if any is missing.
+    ///
+    /// # Safety
+    ///
+    /// `access` must come from components borrowed mutably for `'a`, the
+    /// query's types must be distinct, and each object may be fetched only
+    /// once.
+    #[doc(hidden)]
+    unsafe fn fetch(access: &Self::Access, handle: ObjectHandle) -> Option<Self::Item>;
+}
+
+/// Pointer to the storage of one component type, valid while the scene's
+/// components are borrowed by a [`QueryMut`].
+#[doc(hidden)]
+pub struct StorageAccess<T>(*mut Storage<T>);
+
+impl<T: Component> StorageAccess<T> {
+    fn new(components: &mut Components) -> Option<Self> {
+        components.storage_mut::<T>().map(|storage| StorageAccess(storage))
+    }
+
+    /// # Safety
+    ///
+    /// As for [`QueryMut::fetch`]. Only the one component is borrowed
+    /// mutably, never the storage's whole array, so components of other
+    /// objects fetched earlier stay valid.
+    unsafe fn get(&self, handle: ObjectHandle) -> Option<*mut T> {
+        let position = (*self.0).position(handle)?;
+        Some((*self.0).values.as_mut_ptr().add(position))
+    }
+}
+
+impl<'a, T: Component> QueryMut<'a> for &'a T {
+    type Item = &'a T;
+    type Access = StorageAccess<T>;
+
+    fn component_types() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+
+    fn access(components: &mut Components) -> Option<Self::Access> {
+        StorageAccess::new(components)
+    }
+
+    unsafe fn fetch(access: &Self::Access, handle: ObjectHandle) -> Option<&'a T> {
+        access.get(handle).map(|component| &*component)
+    }
+}
+
+impl<'a, T: Component> QueryMut<'a> for &'a mut T {
+    type Item = &'a mut T;
+    type Access = StorageAccess<T>;
+
+    fn component_types() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+
+    fn access(components: &mut Components) -> Option<Self::Access> {
+        StorageAccess::new(components)
+    }
+
+    unsafe fn fetch(access: &Self::Access, handle: ObjectHandle) -> Option<&'a mut T> {
+        access.get(handle).map(|component| &mut *component)
+    }
+}
+
+macro_rules! impl_query_mut_for_tuple {
+    ($($name:ident),+) => {
+        impl<'a, $($name: QueryMut<'a>),+> QueryMut<'a> for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+            type Access = ($($name::Access,)+);
+
+            fn component_types() -> Vec<TypeId> {
+                let mut types = Vec::new();
+                $(types.extend($name::component_types());)+
+                types
+            }
+
+            fn access(components: &mut Components) -> Option<Self::Access> {
+                Some(($($name::access(components)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(access: &Self::Access, handle: ObjectHandle) -> Option<Self::Item> {
+                let ($($name,)+) = access;
+                Some(($($name::fetch($name, handle)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_mut_for_tuple!(A);
+impl_query_mut_for_tuple!(A, B);
+impl_query_mut_for_tuple!(A, B, C);
+impl_query_mut_for_tuple!(A, B, C, D);
+
+/// Components given to a [`SceneObject`](crate::scene::SceneObject) before it
+/// is added to a scene, stored once the object has a handle.
+#[derive(Default)]
+pub(crate) struct PendingComponents(Vec<PendingInsert>);
+
+type PendingInsert = Box<dyn FnOnce(&mut Components, ObjectHandle)>;
+
+impl PendingComponents {
+    pub(crate) fn push<T: Component>(&mut self, value: T) {
+        self.0.push(Box::new(move |components, handle| {
+            components.insert(handle, value);
+        }));
+    }
+
+    pub(crate) fn insert_into(self, components: &mut Components, handle: ObjectHandle) {
+        for insert in self.0 {
+            insert(components, handle);
+        }
+    }
+}
+
+impl fmt::Debug for PendingComponents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pending", self.0.len())
+    }
+}
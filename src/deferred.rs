@@ -0,0 +1,444 @@
+// Deferred shading for IntSar-3D
+//
+// Instead of lighting every opaque fragment with every light as it is drawn,
+// the deferred path first draws opaque meshes into a G-buffer: base color,
+// world-space normal, material parameters and depth, plus their ambient and
+// emitted light straight into the HDR target. A lighting pass then adds each
+// light on top, reading the surface back from the G-buffer. Each light only
+// touches the pixels inside the screen-space bounds of its light volume, the
+// sphere beyond which it adds next to nothing, so many small lights cost
+// little more than a few large ones. Blended meshes are drawn afterwards with
+// the forward shader, over the lit frame.
+
+use crate::light::{Light, LightKind};
+use crate::renderer::{DEPTH_FORMAT, MAX_LIGHTS};
+use crate::tonemap::HDR_FORMAT;
+use glam::{Mat4, Vec2, Vec3};
+
+/// Format of the G-buffer's base color target.
+pub(crate) const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Format of the G-buffer's normal target.
+pub(crate) const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Format of the G-buffer's metallic, roughness, occlusion and shadow
+/// receiving target.
+pub(crate) const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Illuminance, in lux, below which the deferred path stops drawing a light
+/// that has no range.
+pub const LIGHT_CUTOFF: f32 = 0.001;
+
+/// How a [`Renderer`](crate::renderer::Renderer) lights opaque meshes,
+/// chosen when it is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    /// Every mesh is shaded with every light as it is drawn.
+    #[default]
+    Forward,
+    /// Opaque meshes are drawn into a G-buffer, which each light then shades
+    /// within its light volume. Scales to many lights, but can't use MSAA.
+    Deferred,
+}
+
+/// A channel of the G-buffer shown by the deferred path's debug view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferChannel {
+    /// Base color.
+    Albedo,
+    /// World-space normal, mapped from -1..1 to 0..1.
+    Normal,
+    Metallic,
+    Roughness,
+    /// Ambient occlusion of the material.
+    Occlusion,
+    /// Distance from the camera, white up close and fading to black.
+    Depth,
+}
+
+impl GBufferChannel {
+    /// Index of the channel in the debug shader.
+    fn index(self) -> u32 {
+        match self {
+            GBufferChannel::Albedo => 0,
+            GBufferChannel::Normal => 1,
+            GBufferChannel::Metallic => 2,
+            GBufferChannel::Roughness => 3,
+            GBufferChannel::Occlusion => 4,
+            GBufferChannel::Depth => 5,
+        }
+    }
+}
+
+/// Radius of the light volume of a point or spot light: its range, or where
+/// its illuminance drops below [`LIGHT_CUTOFF`] if that is closer. `None`
+/// for directional lights, which reach everywhere.
+pub fn light_radius(light: &Light) -> Option<f32> {
+    if light.kind == LightKind::Directional {
+        return None;
+    }
+    let brightest = (light.color * light.intensity).max_element().max(0.0);
+    let cutoff = (brightest / LIGHT_CUTOFF).sqrt();
+    Some(light.range.map_or(cutoff, |range| range.min(cutoff)))
+}
+
+/// Pixels a sphere can cover when drawn with `view_proj`, as a scissor
+/// rectangle `[x, y, width, height]` in a `width` by `height` target, or
+/// `None` if it is entirely off screen.
+///
+/// The rectangle bounds the sphere's bounding box, padded by a pixel to
+/// allow for TAA jitter. Spheres reaching behind the camera cover the whole
+/// target.
+pub fn scissor_rect(view_proj: Mat4, center: Vec3, radius: f32, width: u32, height: u32) -> Option<[u32; 4]> {
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    let mut behind = 0;
+    for corner in 0..8 {
+        let sign = |bit: i32| if corner & bit != 0 { 1.0 } else { -1.0 };
+        let point = center + Vec3::new(sign(1), sign(2), sign(4)) * radius;
+        let clip = view_proj * point.extend(1.0);
+        if clip.w <= 0.0 {
+            behind += 1;
+            continue;
+        }
+        let ndc = clip.truncate().truncate() / clip.w;
+        min = min.min(ndc);
+        max = max.max(ndc);
+    }
+    if behind == 8 {
+        return None;
+    }
+    if behind > 0 {
+        min = Vec2::NEG_ONE;
+        max = Vec2::ONE;
+    }
+    let (min, max) = (min.max(Vec2::NEG_ONE), max.min(Vec2::ONE));
+    if min.x >= max.x || min.y >= max.y {
+        return None;
+    }
+
+    // Rows run down the target, while NDC runs up
+    let size = Vec2::new(width as f32, height as f32);
+    let top_left = (Vec2::new(min.x, -max.y) * 0.5 + 0.5) * size;
+    let bottom_right = (Vec2::new(max.x, -min.y) * 0.5 + 0.5) * size;
+    let top_left = (top_left.floor() - 1.0).max(Vec2::ZERO);
+    let bottom_right = (bottom_right.ceil() + 1.0).min(size);
+    let extent = bottom_right - top_left;
+    Some([top_left.x as u32, top_left.y as u32, extent.x as u32, extent.y as u32])
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DeferredUniforms {
+    light: u32,
+    channel: u32,
+    _padding: [u32; 2],
+}
+
+/// A light to add in the lighting pass, as its index in the light uniforms
+/// and the scissor rectangle of its light volume.
+pub(crate) struct LightVolume {
+    pub(crate) light: usize,
+    pub(crate) scissor: [u32; 4],
+}
+
+/// The G-buffer targets and the lighting and debug passes that read them.
+pub(crate) struct GBuffer {
+    debug: Option<GBufferChannel>,
+    /// Albedo, normal, material and depth targets.
+    targets: [wgpu::TextureView; 4],
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    light_pipeline: wgpu::RenderPipeline,
+    debug_pipeline: wgpu::RenderPipeline,
+    /// One slot of `DeferredUniforms` per light, picked by dynamic offset.
+    uniform_buffer: wgpu::Buffer,
+    uniform_stride: wgpu::BufferAddress,
+}
+
+impl GBuffer {
+    /// Creates the G-buffer, with lighting passes that bind the renderer's
+    /// camera and lights through `uniform_layout`.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // Everything is loaded by texel; depth is read as plain floats, as
+        // some backends can't load texels of depth textures
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<DeferredUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Deferred Lighting Shader"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("lighting.wgsl"), include_str!("deferred.wgsl")).into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Lighting Pipeline Layout"),
+            bind_group_layouts: &[uniform_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        // Lights add up, leaving alpha alone
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let light_pipeline = create_pipeline("Deferred Lighting Pipeline", "fs_light", Some(additive));
+        let debug_pipeline = create_pipeline("G-Buffer Debug Pipeline", "fs_debug", None);
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let uniform_stride = (std::mem::size_of::<DeferredUniforms>() as wgpu::BufferAddress)
+            .div_ceil(alignment) * alignment;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Deferred Uniform Buffer"),
+            size: uniform_stride * MAX_LIGHTS as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (targets, bind_group) =
+            Self::create_targets(device, &bind_group_layout, &uniform_buffer, width, height);
+        let gbuffer = Self {
+            debug: None,
+            targets,
+            bind_group_layout,
+            bind_group,
+            light_pipeline,
+            debug_pipeline,
+            uniform_buffer,
+            uniform_stride,
+        };
+        gbuffer.write_uniforms(queue);
+        gbuffer
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+    ) -> ([wgpu::TextureView; 4], wgpu::BindGroup) {
+        let create_view = |label, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let albedo = create_view("G-Buffer Albedo", ALBEDO_FORMAT);
+        let normal = create_view("G-Buffer Normal", NORMAL_FORMAT);
+        let material = create_view("G-Buffer Material", MATERIAL_FORMAT);
+        let depth = create_view("G-Buffer Depth", DEPTH_FORMAT);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&material),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<DeferredUniforms>() as u64),
+                    }),
+                },
+            ],
+        });
+        ([albedo, normal, material, depth], bind_group)
+    }
+
+    /// Recreates the targets at a new size.
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.targets, self.bind_group) =
+            Self::create_targets(device, &self.bind_group_layout, &self.uniform_buffer, width, height);
+    }
+
+    /// Fills every light's slot with its index and the debug channel.
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let stride = self.uniform_stride as usize;
+        let mut data = vec![0u8; stride * MAX_LIGHTS];
+        for (light, slot) in data.chunks_exact_mut(stride).enumerate() {
+            let uniforms = DeferredUniforms {
+                light: light as u32,
+                channel: self.debug.map_or(0, GBufferChannel::index),
+                _padding: [0; 2],
+            };
+            slot[..std::mem::size_of::<DeferredUniforms>()].copy_from_slice(bytemuck::bytes_of(&uniforms));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, &data);
+    }
+
+    pub(crate) fn debug(&self) -> Option<GBufferChannel> {
+        self.debug
+    }
+
+    pub(crate) fn set_debug(&mut self, queue: &wgpu::Queue, channel: Option<GBufferChannel>) {
+        self.debug = channel;
+        self.write_uniforms(queue);
+    }
+
+    /// Zeroes the albedo, normal and material targets, which marks every
+    /// pixel unlit. Each target gets a pass of its own because clearing any
+    /// but the first target of a pass clears the first one instead on the GL
+    /// backend, like TAA's motion vectors.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        for view in self.views() {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("G-Buffer Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+        }
+    }
+
+    /// Albedo, normal and material targets, in the order the G-buffer pass
+    /// writes them.
+    pub(crate) fn views(&self) -> [&wgpu::TextureView; 3] {
+        [&self.targets[0], &self.targets[1], &self.targets[2]]
+    }
+
+    /// Depth buffer of the G-buffer pass, which blended meshes are tested
+    /// against afterwards.
+    pub(crate) fn depth_view(&self) -> &wgpu::TextureView {
+        &self.targets[3]
+    }
+
+    /// Adds each light within its light volume to `output`, which holds the
+    /// ambient and emitted light; with a debug channel selected, shows that
+    /// channel instead.
+    pub(crate) fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform_bind_group: &wgpu::BindGroup,
+        output: &wgpu::TextureView,
+        lights: &[LightVolume],
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(if self.debug.is_some() { "G-Buffer Debug Pass" } else { "Deferred Lighting Pass" }),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, uniform_bind_group, &[]);
+
+        if self.debug.is_some() {
+            pass.set_pipeline(&self.debug_pipeline);
+            pass.set_bind_group(1, &self.bind_group, &[0]);
+            pass.draw(0..3, 0..1);
+            return;
+        }
+
+        pass.set_pipeline(&self.light_pipeline);
+        for volume in lights {
+            let offset = (volume.light as wgpu::BufferAddress * self.uniform_stride) as wgpu::DynamicOffset;
+            let [x, y, width, height] = volume.scissor;
+            pass.set_bind_group(1, &self.bind_group, &[offset]);
+            pass.set_scissor_rect(x, y, width, height);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
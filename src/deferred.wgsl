@@ -0,0 +1,118 @@
+// Deferred lighting shaders for IntSar-3D, appended to lighting.wgsl
+
+// G-buffer written by `fs_gbuffer` in shader.wgsl
+@group(1) @binding(0)
+var albedo_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var material_texture: texture_2d<f32>;
+@group(1) @binding(3)
+var depth_texture: texture_2d<f32>;
+
+// What one draw of the lighting pass shades
+struct DeferredUniforms {
+    // Light added by the draw
+    light: u32,
+    // G-buffer channel shown by the debug view, matching `GBufferChannel`
+    channel: u32,
+};
+
+@group(1) @binding(4)
+var<uniform> deferred: DeferredUniforms;
+
+const CHANNEL_ALBEDO: u32 = 0u;
+const CHANNEL_NORMAL: u32 = 1u;
+const CHANNEL_METALLIC: u32 = 2u;
+const CHANNEL_ROUGHNESS: u32 = 3u;
+const CHANNEL_OCCLUSION: u32 = 4u;
+const CHANNEL_DEPTH: u32 = 5u;
+
+// One triangle covering the whole target; scissor rectangles keep each light
+// to the pixels it can reach
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// World-space position of the surface stored at a pixel
+fn world_position(pixel: vec2<f32>, depth: f32) -> vec3<f32> {
+    let uv = pixel / vec2<f32>(textureDimensions(depth_texture));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+    return world.xyz / world.w;
+}
+
+@fragment
+fn fs_light(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let normal = textureLoad(normal_texture, pixel, 0);
+    // Nothing to light in the background or on meshes without normals
+    if normal.w == 0.0 {
+        discard;
+    }
+    let albedo = textureLoad(albedo_texture, pixel, 0);
+    let material = textureLoad(material_texture, pixel, 0);
+    let depth = textureLoad(depth_texture, pixel, 0).r;
+
+    let world_position = world_position(position.xy, depth);
+    let surface = surface(
+        world_position,
+        normal.xyz,
+        normal.xyz,
+        albedo.rgb,
+        material.r,
+        material.g,
+        material.a > 0.5,
+    );
+    var color = reflected(lights[deferred.light], surface);
+    if lighting.debug_cascades != 0u {
+        color *= cascade_tint(world_position);
+    }
+    return vec4<f32>(color, 0.0);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// One G-buffer channel, as it should look on screen once the frame is
+// encoded to sRGB
+@fragment
+fn fs_debug(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let normal = textureLoad(normal_texture, pixel, 0);
+    let material = textureLoad(material_texture, pixel, 0);
+    var shown = vec3<f32>(0.0);
+    switch deferred.channel {
+        case CHANNEL_ALBEDO: {
+            // Already linear
+            return vec4<f32>(textureLoad(albedo_texture, pixel, 0).rgb, 1.0);
+        }
+        case CHANNEL_NORMAL: {
+            shown = select(vec3<f32>(0.0), normal.xyz * 0.5 + 0.5, normal.w > 0.0);
+        }
+        case CHANNEL_METALLIC: {
+            shown = vec3<f32>(material.r);
+        }
+        case CHANNEL_ROUGHNESS: {
+            shown = vec3<f32>(material.g);
+        }
+        case CHANNEL_OCCLUSION: {
+            shown = vec3<f32>(material.b);
+        }
+        case CHANNEL_DEPTH: {
+            // Near surfaces bright, fading out with distance
+            let depth = textureLoad(depth_texture, pixel, 0).r;
+            if depth < 1.0 {
+                let distance = length(world_position(position.xy, depth) - camera.position.xyz);
+                shown = vec3<f32>(exp(-distance * 0.2));
+            }
+        }
+        default: {}
+    }
+    return vec4<f32>(srgb_to_linear(shown), 1.0);
+}
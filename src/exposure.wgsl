@@ -0,0 +1,106 @@
+// Auto exposure shader for IntSar-3D
+
+struct AutoExposureUniforms {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    compensation: f32,
+    speed_brighten: f32,
+    speed_darken: f32,
+    delta_time: f32,
+    reset: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> settings: AutoExposureUniforms;
+
+@group(0) @binding(1)
+var hdr: texture_2d<f32>;
+
+// Pixels per bin, cleared again by the average pass
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+
+struct ExposureState {
+    exposure: f32,
+};
+
+@group(0) @binding(3)
+var<storage, read_write> state: ExposureState;
+
+const BINS: u32 = 256u;
+const MIDDLE_GREY: f32 = 0.18;
+
+var<workgroup> tile_histogram: array<atomic<u32>, 256>;
+var<workgroup> counts: array<u32, 256>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Counts a 16x16 tile in workgroup memory before touching the shared histogram
+@compute @workgroup_size(16, 16)
+fn cs_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&tile_histogram[index], 0u);
+    workgroupBarrier();
+
+    if all(id.xy < textureDimensions(hdr)) {
+        let l = luminance(textureLoad(hdr, vec2<i32>(id.xy), 0).rgb);
+        let position = (log2(max(l, 1e-10)) - settings.min_log_luminance) / settings.log_luminance_range;
+        let bin = u32(clamp(position, 0.0, 1.0) * f32(BINS - 1u) + 0.5);
+        atomicAdd(&tile_histogram[bin], 1u);
+    }
+    workgroupBarrier();
+
+    let count = atomicLoad(&tile_histogram[index]);
+    if count > 0u {
+        atomicAdd(&histogram[index], count);
+    }
+}
+
+@compute @workgroup_size(256)
+fn cs_average(@builtin(local_invocation_index) index: u32) {
+    counts[index] = atomicLoad(&histogram[index]);
+    atomicStore(&histogram[index], 0u);
+    workgroupBarrier();
+    if index != 0u {
+        return;
+    }
+
+    var total = 0u;
+    for (var i = 0u; i < BINS; i++) {
+        total += counts[i];
+    }
+
+    // Average log luminance of the pixels between the two percentiles
+    let low = f32(total) * settings.low_percentile;
+    let high = f32(total) * settings.high_percentile;
+    var below = 0.0;
+    var weight = 0.0;
+    var sum = 0.0;
+    for (var i = 0u; i < BINS; i++) {
+        let count = f32(counts[i]);
+        let kept = clamp(below + count, low, high) - clamp(below, low, high);
+        let log_luminance = settings.min_log_luminance + f32(i) / f32(BINS - 1u) * settings.log_luminance_range;
+        sum += kept * log_luminance;
+        weight += kept;
+        below += count;
+    }
+    if weight <= 0.0 {
+        return;
+    }
+
+    let target_stops = log2(MIDDLE_GREY) - sum / weight + settings.compensation;
+    var stops = target_stops;
+    if settings.reset == 0u {
+        let current = log2(state.exposure);
+        let speed = select(settings.speed_darken, settings.speed_brighten, target_stops > current);
+        let step = speed * settings.delta_time;
+        stops = current + clamp(target_stops - current, -step, step);
+    }
+    state.exposure = exp2(stops);
+}
@@ -0,0 +1,67 @@
+// Fast approximate anti-aliasing for IntSar-3D
+//
+// Timothy Lottes' FXAA, in the form of his original (FXAA 2) algorithm:
+// the luma gradient of the four diagonal neighbours gives the direction of
+// the edge, and the pixel is blurred along it. Appended to post.wgsl.
+
+// Skip pixels whose neighbourhood contrast is below this share of its
+// brightest luma, or below the absolute minimum in dark areas
+const EDGE_THRESHOLD: f32 = 0.125;
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+// Keep short or noisy edges from blurring too far
+const REDUCE_MUL: f32 = 0.125;
+const REDUCE_MIN: f32 = 0.0078125;
+// Longest blur in pixels
+const SPAN_MAX: f32 = 8.0;
+
+// Perceptual luma of the linear color sampled from the display target
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample_color(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(post_input, post_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let center = textureSampleLevel(post_input, post_sampler, in.uv, 0.0);
+    let texel = post.texel_size;
+    let luma_nw = luma(sample_color(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_color(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_color(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_color(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(center.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if luma_max - luma_min < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD) {
+        return center;
+    }
+
+    // Perpendicular to the luma gradient, scaled so its shorter axis is one pixel
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    // Two taps close to the pixel, and two more further out along the edge
+    let inner = 0.5 * (
+        sample_color(in.uv + direction * (1.0 / 3.0 - 0.5))
+        + sample_color(in.uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    let outer = inner * 0.5 + 0.25 * (
+        sample_color(in.uv - direction * 0.5)
+        + sample_color(in.uv + direction * 0.5)
+    );
+
+    // The outer taps overshot the edge if they left the local luma range
+    let luma_outer = luma(outer);
+    if luma_outer < luma_min || luma_outer > luma_max {
+        return vec4<f32>(inner, center.a);
+    }
+    return vec4<f32>(outer, center.a);
+}
@@ -0,0 +1,4 @@
+// Asset importers for IntSar-3D
+
+pub mod gltf;
+pub mod obj;
@@ -0,0 +1,440 @@
+// glTF 2.0 importer for IntSar-3D
+//
+// Nodes of the default scene become scene objects with the same hierarchy and
+// local transforms, and only the root nodes are placed by the import
+// transform. Every primitive becomes its own mesh, and nodes whose mesh has
+// several primitives get one extra child object per additional primitive.
+
+use crate::camera::Camera;
+use crate::light::{Light, LightKind};
+use crate::material::{AlphaMode, Material, MaterialHandle, TextureSource};
+use crate::math::Transform;
+use crate::mesh::{Indices, Mesh, MeshHandle, Vertex};
+use crate::scene::{ObjectHandle, Scene, SceneObject};
+use crate::texture::{AddressMode, Filter, Sampler};
+use ::gltf::mesh::Mode;
+use glam::{Mat4, Vec3, Vec4};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Error produced while importing a glTF file.
+#[derive(Debug)]
+pub enum GltfError {
+    /// The file, or a buffer or image it references, could not be read or parsed.
+    Gltf { path: PathBuf, source: ::gltf::Error },
+    /// The document is valid glTF but can't be represented in a scene.
+    Unsupported { path: PathBuf, message: String },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Gltf { path, source } => write!(f, "failed to load {}: {source}", path.display()),
+            GltfError::Unsupported { path, message } => write!(f, "{}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Gltf { source, .. } => Some(source),
+            GltfError::Unsupported { .. } => None,
+        }
+    }
+}
+
+/// Loads the default scene of a `.gltf` or `.glb` file, or its first scene if
+/// no default is set.
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<Scene, GltfError> {
+    let mut scene = Scene::new();
+    import_gltf(path, &mut scene, Transform::identity())?;
+    Ok(scene)
+}
+
+/// Adds the default scene of a `.gltf` or `.glb` file to `scene`, placing its
+/// root nodes relative to `transform`.
+pub fn import_gltf(path: impl AsRef<Path>, scene: &mut Scene, transform: Transform) -> Result<(), GltfError> {
+    let path = path.as_ref();
+    let gltf_error = |source| GltfError::Gltf {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::open(path).map_err(gltf_error)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let buffers = ::gltf::import_buffers(&document, Some(directory), blob).map_err(gltf_error)?;
+
+    let mut importer = Importer {
+        path,
+        directory,
+        buffers: &buffers,
+        scene,
+        materials: Vec::new(),
+        meshes: Vec::new(),
+    };
+
+    for material in document.materials() {
+        let material = importer.material(&material).map_err(gltf_error)?;
+        importer.materials.push(importer.scene.add_material(material));
+    }
+    for mesh in document.meshes() {
+        let primitives = importer.mesh(&mesh)?;
+        importer.meshes.push(primitives);
+    }
+
+    let Some(gltf_scene) = document.default_scene().or_else(|| document.scenes().next()) else {
+        log::warn!("{} contains no scenes", path.display());
+        return Ok(());
+    };
+    for node in gltf_scene.nodes() {
+        importer.node(&node, None, transform.matrix());
+    }
+    Ok(())
+}
+
+struct Importer<'a> {
+    path: &'a Path,
+    directory: &'a Path,
+    buffers: &'a [::gltf::buffer::Data],
+    scene: &'a mut Scene,
+    /// Scene handles of the document's materials, by glTF index.
+    materials: Vec<MaterialHandle>,
+    /// Scene meshes and materials of each glTF mesh's primitives, by glTF index.
+    meshes: Vec<Vec<(MeshHandle, Option<MaterialHandle>)>>,
+}
+
+impl Importer<'_> {
+    fn material(&self, material: &::gltf::Material) -> Result<Material, ::gltf::Error> {
+        let name = match (material.name(), material.index()) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(index)) => format!("Material {index}"),
+            (None, None) => "Default".to_string(),
+        };
+
+        let pbr = material.pbr_metallic_roughness();
+        let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+        let alpha_mode = match material.alpha_mode() {
+            ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            ::gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+        Ok(Material {
+            name,
+            base_color: Vec4::from(pbr.base_color_factor()),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            emissive: Vec3::from(material.emissive_factor()) * emissive_strength,
+            alpha_mode,
+            normal_scale: material.normal_texture().map_or(1.0, |info| info.scale()),
+            occlusion_strength: material.occlusion_texture().map_or(1.0, |info| info.strength()),
+            base_color_texture: self.texture(pbr.base_color_texture().map(|info| info.texture()))?,
+            metallic_roughness_texture: self.texture(pbr.metallic_roughness_texture().map(|info| info.texture()))?,
+            normal_texture: self.texture(material.normal_texture().map(|info| info.texture()))?,
+            occlusion_texture: self.texture(material.occlusion_texture().map(|info| info.texture()))?,
+            emissive_texture: self.texture(material.emissive_texture().map(|info| info.texture()))?,
+            sampler: sampler(material),
+        })
+    }
+
+    /// Resolves a texture's image to a file path, or to the encoded bytes if
+    /// it is embedded in a buffer or data URI.
+    fn texture(&self, texture: Option<::gltf::Texture>) -> Result<Option<TextureSource>, ::gltf::Error> {
+        let Some(texture) = texture else {
+            return Ok(None);
+        };
+
+        let source = match texture.source().source() {
+            ::gltf::image::Source::View { view, mime_type } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                TextureSource::Embedded {
+                    mime_type: Some(mime_type.to_string()),
+                    data: Arc::from(&buffer[view.offset()..view.offset() + view.length()]),
+                }
+            }
+            ::gltf::image::Source::Uri { uri, mime_type } if uri.starts_with("data:") => {
+                let data = ::gltf::buffer::Data::from_source(::gltf::buffer::Source::Uri(uri), None)?;
+                let mime_type = mime_type
+                    .or_else(|| uri["data:".len()..].split(';').next())
+                    .filter(|mime_type| !mime_type.is_empty());
+                TextureSource::Embedded {
+                    mime_type: mime_type.map(str::to_string),
+                    data: Arc::from(data.0),
+                }
+            }
+            ::gltf::image::Source::Uri { uri, .. } => TextureSource::File(self.directory.join(percent_decode(uri))),
+        };
+        Ok(Some(source))
+    }
+
+    /// Converts the primitives of a mesh, adding them to the scene.
+    fn mesh(&mut self, mesh: &::gltf::Mesh) -> Result<Vec<(MeshHandle, Option<MaterialHandle>)>, GltfError> {
+        let name = mesh.name().map(str::to_string).unwrap_or_else(|| format!("Mesh {}", mesh.index()));
+        let count = mesh.primitives().len();
+
+        let mut primitives = Vec::with_capacity(count);
+        for primitive in mesh.primitives() {
+            let name = if count == 1 { name.clone() } else { format!("{name}.{}", primitive.index()) };
+            let Some(converted) = self.primitive(&primitive, name)? else {
+                continue;
+            };
+            let material = primitive.material().index().map(|index| self.materials[index]);
+            primitives.push((self.scene.add_mesh(converted), material));
+        }
+        Ok(primitives)
+    }
+
+    fn primitive(&self, primitive: &::gltf::Primitive, name: String) -> Result<Option<Mesh>, GltfError> {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            return Err(GltfError::Unsupported {
+                path: self.path.to_path_buf(),
+                message: format!("primitive {} of mesh `{name}` has no positions", primitive.index()),
+            });
+        };
+
+        let mut vertices: Vec<Vertex> = positions.map(|position| Vertex::new(position, [1.0; 3])).collect();
+        if let Some(colors) = reader.read_colors(0) {
+            for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+                vertex.color = color;
+            }
+        }
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                vertex.uv = uv;
+            }
+        }
+        let has_normals = match reader.read_normals() {
+            Some(normals) => {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+                true
+            }
+            None => false,
+        };
+        let has_tangents = match reader.read_tangents() {
+            Some(tangents) => {
+                for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent;
+                }
+                true
+            }
+            None => false,
+        };
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let Some(mut indices) = triangle_list(primitive.mode(), indices) else {
+            log::warn!(
+                "{}: skipping {:?} primitive {} of mesh `{name}`",
+                self.path.display(),
+                primitive.mode(),
+                primitive.index()
+            );
+            return Ok(None);
+        };
+        if let Some(&index) = indices.iter().find(|&&index| index as usize >= vertices.len()) {
+            return Err(GltfError::Unsupported {
+                path: self.path.to_path_buf(),
+                message: format!("index {index} out of range in mesh `{name}`"),
+            });
+        }
+
+        // Primitives without normals are flat shaded, so every triangle gets
+        // its own corners
+        if !has_normals {
+            let mut flat = Vec::with_capacity(indices.len());
+            for triangle in indices.chunks_exact(3) {
+                let corners = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+                let [a, b, c] = corners.map(|vertex| Vec3::from(vertex.position));
+                let normal = (b - a).cross(c - a).normalize_or_zero();
+                flat.extend(corners.map(|vertex| Vertex {
+                    normal: normal.into(),
+                    ..vertex
+                }));
+            }
+            indices = (0..flat.len() as u32).collect();
+            vertices = flat;
+        }
+
+        let mut mesh = Mesh::new(name, vertices, Indices::compact(indices));
+        if !has_tangents {
+            mesh.generate_tangents();
+        }
+        Ok(Some(mesh))
+    }
+
+    /// Adds a node and its descendants under `parent`. `placement` is applied
+    /// on top of the node's own transform, and places root nodes.
+    fn node(&mut self, node: &::gltf::Node, parent: Option<ObjectHandle>, placement: Mat4) {
+        let local = placement * Mat4::from_cols_array_2d(&node.transform().matrix());
+        let (scale, rotation, position) = local.to_scale_rotation_translation();
+        let transform = Transform::new(position, rotation.normalize(), scale);
+
+        let name = node.name().map(str::to_string).unwrap_or_else(|| format!("Node {}", node.index()));
+        let mut object = SceneObject::new(name.clone(), transform);
+        if let Some(parent) = parent {
+            object = object.with_parent(parent);
+        }
+        if let Some(camera) = node.camera() {
+            object = object.with_camera(convert_camera(&camera));
+        }
+        if let Some(light) = node.light() {
+            object = object.with_light(convert_light(&light));
+        }
+
+        let primitives = node.mesh().map(|mesh| self.meshes[mesh.index()].clone()).unwrap_or_default();
+        let mut primitives = primitives.into_iter();
+        if let Some((mesh, material)) = primitives.next() {
+            object = with_mesh_and_material(object, mesh, material);
+        }
+        let handle = self.scene.add_object(object);
+
+        for (index, (mesh, material)) in primitives.enumerate() {
+            let object = SceneObject::new(format!("{name}.{}", index + 1), Transform::identity()).with_parent(handle);
+            self.scene.add_object(with_mesh_and_material(object, mesh, material));
+        }
+
+        for child in node.children() {
+            self.node(&child, Some(handle), Mat4::IDENTITY);
+        }
+    }
+}
+
+/// Converts the sampler of the material's base color texture, or of its first
+/// other texture, since materials share one sampler.
+fn sampler(material: &::gltf::Material) -> Sampler {
+    use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let pbr = material.pbr_metallic_roughness();
+    let texture = pbr.base_color_texture().map(|info| info.texture())
+        .or_else(|| pbr.metallic_roughness_texture().map(|info| info.texture()))
+        .or_else(|| material.normal_texture().map(|info| info.texture()))
+        .or_else(|| material.occlusion_texture().map(|info| info.texture()))
+        .or_else(|| material.emissive_texture().map(|info| info.texture()));
+    let Some(texture) = texture else {
+        return Sampler::default();
+    };
+
+    let sampler = texture.sampler();
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => AddressMode::Repeat,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => (Filter::Nearest, Filter::Nearest),
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => (Filter::Linear, Filter::Nearest),
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Filter::Linear),
+        Some(MinFilter::LinearMipmapLinear) | None => (Filter::Linear, Filter::Linear),
+    };
+    Sampler {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => Filter::Nearest,
+            Some(MagFilter::Linear) | None => Filter::Linear,
+        },
+        min_filter,
+        mipmap_filter,
+        address_u: address_mode(sampler.wrap_s()),
+        address_v: address_mode(sampler.wrap_t()),
+        ..Sampler::default()
+    }
+}
+
+fn with_mesh_and_material(object: SceneObject, mesh: MeshHandle, material: Option<MaterialHandle>) -> SceneObject {
+    let object = object.with_mesh(mesh);
+    match material {
+        Some(material) => object.with_material(material),
+        None => object,
+    }
+}
+
+/// Converts the indices of a triangle strip or fan to a triangle list.
+/// Returns `None` for point and line primitives.
+fn triangle_list(mode: Mode, indices: Vec<u32>) -> Option<Vec<u32>> {
+    match mode {
+        Mode::Triangles => Some(indices),
+        Mode::TriangleStrip => Some(
+            indices
+                .windows(3)
+                .enumerate()
+                .flat_map(|(i, w)| if i % 2 == 0 { [w[0], w[1], w[2]] } else { [w[1], w[0], w[2]] })
+                .collect(),
+        ),
+        Mode::TriangleFan => Some(
+            indices
+                .windows(2)
+                .skip(1)
+                .flat_map(|w| [indices[0], w[0], w[1]])
+                .collect(),
+        ),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => None,
+    }
+}
+
+fn convert_camera(camera: &::gltf::Camera) -> Camera {
+    match camera.projection() {
+        ::gltf::camera::Projection::Perspective(perspective) => Camera::Perspective {
+            fov_y: perspective.yfov(),
+            aspect_ratio: perspective.aspect_ratio(),
+            near: perspective.znear(),
+            far: perspective.zfar(),
+        },
+        ::gltf::camera::Projection::Orthographic(orthographic) => Camera::Orthographic {
+            x_mag: orthographic.xmag(),
+            y_mag: orthographic.ymag(),
+            near: orthographic.znear(),
+            far: orthographic.zfar(),
+        },
+    }
+}
+
+fn convert_light(light: &::gltf::khr_lights_punctual::Light) -> Light {
+    let kind = match light.kind() {
+        ::gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+        ::gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+        ::gltf::khr_lights_punctual::Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    };
+    Light {
+        kind,
+        color: Vec3::from(light.color()),
+        intensity: light.intensity(),
+        range: light.range(),
+        shadows: None,
+    }
+}
+
+/// Decodes `%XX` escapes in a relative URI.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escape) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
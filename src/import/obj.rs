@@ -0,0 +1,722 @@
+// Wavefront OBJ and MTL importer for IntSar-3D
+//
+// Polygons are triangulated by ear clipping, identical corners are shared,
+// and faces without normals get flat or smoothed normals according to their
+// smoothing group. A new mesh starts at every `o`, `g` or `usemtl` statement.
+
+use crate::material::{AlphaMode, Material, TextureSource};
+use crate::math::Transform;
+use crate::mesh::{Indices, Mesh, Vertex};
+use crate::scene::{Scene, SceneObject};
+use glam::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Error produced while importing an OBJ file or one of its material libraries.
+#[derive(Debug)]
+pub enum ObjError {
+    /// A file could not be read.
+    Io { path: PathBuf, source: std::io::Error },
+    /// A statement could not be parsed.
+    Parse { file: String, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            ObjError::Parse { file, line, message } => write!(f, "{file}:{line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// A mesh read from an OBJ file, with the index of the material it uses.
+#[derive(Debug)]
+pub struct ObjMesh {
+    pub mesh: Mesh,
+    pub material: Option<usize>,
+}
+
+/// Meshes and materials read from an OBJ file.
+#[derive(Debug, Default)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<Material>,
+}
+
+impl ObjModel {
+    /// Adds the meshes and materials to `scene`, creating one object per mesh.
+    pub fn add_to_scene(self, scene: &mut Scene, transform: Transform) {
+        let materials: Vec<_> = self.materials
+            .into_iter()
+            .map(|material| scene.add_material(material))
+            .collect();
+
+        for obj_mesh in self.meshes {
+            let name = obj_mesh.mesh.name.clone();
+            let mut object = SceneObject::new(name, transform).with_mesh(scene.add_mesh(obj_mesh.mesh));
+            if let Some(material) = obj_mesh.material {
+                object = object.with_material(materials[material]);
+            }
+            scene.add_object(object);
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Loads an OBJ file together with the material libraries it references.
+///
+/// Missing material libraries and unknown material names are logged and the
+/// affected meshes get no material; malformed statements are errors.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut parser = ObjParser::new(path.display().to_string(), default_mesh_name(path));
+    parser.parse(&source, |library| {
+        let library_path = directory.join(library);
+        if !library_path.is_file() {
+            log::warn!("Material library {} not found", library_path.display());
+            return Ok(Vec::new());
+        }
+        let library_source = read_file(&library_path)?;
+        let library_directory = library_path.parent().unwrap_or(Path::new(""));
+        parse_mtl(&library_source, &library_path.display().to_string(), library_directory)
+    })?;
+    Ok(parser.finish())
+}
+
+/// Parses OBJ source text. `mtllib` statements are ignored because there is
+/// no directory to resolve them against.
+pub fn parse_obj(source: &str) -> Result<ObjModel, ObjError> {
+    let mut parser = ObjParser::new("<obj>".to_string(), "Mesh".to_string());
+    parser.parse(source, |_| Ok(Vec::new()))?;
+    Ok(parser.finish())
+}
+
+fn default_mesh_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Mesh".to_string())
+}
+
+/// Splits source text into statements, joining `\` continuations and
+/// dropping comments. Returns the (1-based) line each statement starts on.
+fn statements(source: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let (start, mut statement) = pending.take().unwrap_or((index + 1, String::new()));
+        if let Some(head) = line.strip_suffix('\\') {
+            statement.push_str(head);
+            statement.push(' ');
+            pending = Some((start, statement));
+            continue;
+        }
+        statement.push_str(line);
+
+        let statement = match statement.find('#') {
+            Some(comment) => &statement[..comment],
+            None => &statement,
+        };
+        if !statement.trim().is_empty() {
+            statements.push((start, statement.trim().to_string()));
+        }
+    }
+
+    if let Some((start, statement)) = pending {
+        if !statement.trim().is_empty() {
+            statements.push((start, statement.trim().to_string()));
+        }
+    }
+    statements
+}
+
+/// Parses the floats of a statement, requiring between `min` and `max` of them.
+fn parse_floats(
+    file: &str,
+    line: usize,
+    keyword: &str,
+    args: &[&str],
+    min: usize,
+    max: usize,
+) -> Result<Vec<f32>, ObjError> {
+    if args.len() < min || args.len() > max {
+        let expected = if min == max { min.to_string() } else { format!("{min} to {max}") };
+        return Err(ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message: format!("expected {expected} numbers in `{keyword}` statement, found {}", args.len()),
+        });
+    }
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f32>().map_err(|_| ObjError::Parse {
+                file: file.to_string(),
+                line,
+                message: format!("invalid number `{arg}` in `{keyword}` statement"),
+            })
+        })
+        .collect()
+}
+
+/// Where a vertex's normal comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    /// An explicit `vn` index.
+    Explicit(usize),
+    /// Averaged over all faces of a smoothing group.
+    Smooth(u32),
+    /// The normal of a single face (smoothing off).
+    Flat(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: NormalSource,
+}
+
+/// Geometry accumulated for the mesh currently being read.
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    lookup: HashMap<VertexKey, u32>,
+    /// Vertices whose normal is accumulated from face normals.
+    generated_normals: Vec<bool>,
+}
+
+impl MeshBuilder {
+    fn new(name: String, material: Option<usize>) -> Self {
+        Self {
+            name,
+            material,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            lookup: HashMap::new(),
+            generated_normals: Vec::new(),
+        }
+    }
+
+    fn build(mut self) -> ObjMesh {
+        for (vertex, generated) in self.vertices.iter_mut().zip(&self.generated_normals) {
+            if *generated {
+                vertex.normal = Vec3::from(vertex.normal).normalize_or_zero().into();
+            }
+        }
+        let mut mesh = Mesh::new(self.name, self.vertices, Indices::compact(self.indices));
+        mesh.generate_tangents();
+        ObjMesh {
+            mesh,
+            material: self.material,
+        }
+    }
+}
+
+struct ObjParser {
+    file: String,
+    default_name: String,
+    positions: Vec<Vec3>,
+    colors: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    normals: Vec<Vec3>,
+    materials: Vec<Material>,
+    material_names: HashMap<String, usize>,
+    meshes: Vec<ObjMesh>,
+    current: MeshBuilder,
+    smoothing_group: u32,
+    face_count: usize,
+}
+
+impl ObjParser {
+    fn new(file: String, default_name: String) -> Self {
+        Self {
+            file,
+            current: MeshBuilder::new(default_name.clone(), None),
+            default_name,
+            positions: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            materials: Vec::new(),
+            material_names: HashMap::new(),
+            meshes: Vec::new(),
+            smoothing_group: 0,
+            face_count: 0,
+        }
+    }
+
+    fn error(&self, line: usize, message: String) -> ObjError {
+        ObjError::Parse {
+            file: self.file.clone(),
+            line,
+            message,
+        }
+    }
+
+    fn parse(
+        &mut self,
+        source: &str,
+        mut load_library: impl FnMut(&str) -> Result<Vec<Material>, ObjError>,
+    ) -> Result<(), ObjError> {
+        for (line, statement) in statements(source) {
+            let mut tokens = statement.split_whitespace();
+            let keyword = tokens.next().unwrap_or_default();
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => {
+                    let values = parse_floats(&self.file, line, keyword, &args, 3, 7)?;
+                    self.positions.push(Vec3::new(values[0], values[1], values[2]));
+                    // Six or seven values carry a vertex color after the position
+                    let color = if values.len() >= 6 {
+                        Vec3::new(values[3], values[4], values[5])
+                    } else {
+                        Vec3::ONE
+                    };
+                    self.colors.push(color);
+                }
+                "vt" => {
+                    let values = parse_floats(&self.file, line, keyword, &args, 1, 3)?;
+                    // OBJ puts v = 0 at the bottom of the image; textures here use the top
+                    let v = values.get(1).copied().unwrap_or(0.0);
+                    self.uvs.push(Vec2::new(values[0], 1.0 - v));
+                }
+                "vn" => {
+                    let values = parse_floats(&self.file, line, keyword, &args, 3, 3)?;
+                    self.normals.push(Vec3::new(values[0], values[1], values[2]).normalize_or_zero());
+                }
+                "f" => self.face(line, &args)?,
+                "o" | "g" => {
+                    let name = if args.is_empty() { self.default_name.clone() } else { args.join(" ") };
+                    self.start_mesh(name, self.current.material);
+                }
+                "usemtl" => {
+                    let name = args.join(" ");
+                    let material = self.material_names.get(&name).copied();
+                    if material.is_none() {
+                        log::warn!("{}:{line}: unknown material `{name}`", self.file);
+                    }
+                    self.start_mesh(self.current.name.clone(), material);
+                }
+                "mtllib" => {
+                    if args.is_empty() {
+                        return Err(self.error(line, "`mtllib` statement without a file name".to_string()));
+                    }
+                    for library in &args {
+                        for material in load_library(library)? {
+                            self.material_names.insert(material.name.clone(), self.materials.len());
+                            self.materials.push(material);
+                        }
+                    }
+                }
+                "s" => {
+                    self.smoothing_group = match args.first().copied() {
+                        Some("off") => 0,
+                        // Exporters that don't number their groups put
+                        // everything smooth in one
+                        Some("on") => 1,
+                        Some(group) => group.parse().map_err(|_| {
+                            self.error(line, format!("invalid smoothing group `{group}`"))
+                        })?,
+                        None => return Err(self.error(line, "`s` statement without a group".to_string())),
+                    };
+                }
+                // Free-form geometry, lines and points aren't supported
+                "l" | "p" | "vp" | "cstype" | "deg" | "curv" | "curv2" | "surf" | "parm" | "trim"
+                | "hole" | "scrv" | "sp" | "end" | "con" | "mg" | "bevel" | "c_interp" | "d_interp"
+                | "lod" | "shadow_obj" | "trace_obj" | "usemap" | "maplib" => {
+                    log::debug!("{}:{line}: ignoring unsupported `{keyword}` statement", self.file);
+                }
+                _ => {
+                    log::warn!("{}:{line}: ignoring unknown statement `{keyword}`", self.file);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the current mesh and starts a new one.
+    fn start_mesh(&mut self, name: String, material: Option<usize>) {
+        let next = MeshBuilder::new(name, material);
+        let finished = std::mem::replace(&mut self.current, next);
+        if !finished.indices.is_empty() {
+            self.meshes.push(finished.build());
+        }
+    }
+
+    /// Resolves a 1-based (or negative, relative) OBJ index into `count` elements.
+    fn resolve_index(&self, line: usize, token: &str, kind: &str, count: usize) -> Result<usize, ObjError> {
+        let index: i64 = token.parse().map_err(|_| {
+            self.error(line, format!("invalid {kind} index `{token}`"))
+        })?;
+        let resolved = match index {
+            0 => None,
+            i if i > 0 => Some(i as usize - 1),
+            i => count.checked_sub(i.unsigned_abs() as usize),
+        };
+        match resolved {
+            Some(resolved) if resolved < count => Ok(resolved),
+            _ => Err(self.error(
+                line,
+                format!("{kind} index {index} out of range ({count} defined so far)"),
+            )),
+        }
+    }
+
+    fn face(&mut self, line: usize, args: &[&str]) -> Result<(), ObjError> {
+        if args.len() < 3 {
+            return Err(self.error(line, format!("face needs at least 3 vertices, found {}", args.len())));
+        }
+        let face = self.face_count;
+        self.face_count += 1;
+
+        // Resolve `v`, `v/vt`, `v//vn` and `v/vt/vn` corners
+        let mut keys = Vec::with_capacity(args.len());
+        for corner in args {
+            if corner.split('/').count() > 3 {
+                return Err(self.error(line, format!("malformed face corner `{corner}`")));
+            }
+            let mut parts = corner.split('/');
+            let position = self.resolve_index(line, parts.next().unwrap_or_default(), "vertex", self.positions.len())?;
+            let uv = match parts.next() {
+                Some("") | None => None,
+                Some(token) => Some(self.resolve_index(line, token, "texture coordinate", self.uvs.len())?),
+            };
+            let normal = match parts.next() {
+                Some("") | None => None,
+                Some(token) => Some(self.resolve_index(line, token, "normal", self.normals.len())?),
+            };
+            let normal = match normal {
+                Some(normal) => NormalSource::Explicit(normal),
+                None if self.smoothing_group != 0 => NormalSource::Smooth(self.smoothing_group),
+                None => NormalSource::Flat(face),
+            };
+            keys.push(VertexKey { position, uv, normal });
+        }
+
+        // Generated normals accumulate the area-weighted face normal once per corner
+        let polygon: Vec<Vec3> = keys.iter().map(|key| self.positions[key.position]).collect();
+        let face_normal = polygon_normal(&polygon);
+        let mut indices = Vec::with_capacity(keys.len());
+        for key in keys {
+            let index = self.vertex(key);
+            if self.current.generated_normals[index as usize] {
+                let vertex = &mut self.current.vertices[index as usize];
+                vertex.normal = (Vec3::from(vertex.normal) + face_normal).into();
+            }
+            indices.push(index);
+        }
+        for triangle in triangulate(&polygon) {
+            self.current.indices.extend(triangle.map(|corner| indices[corner]));
+        }
+        Ok(())
+    }
+
+    /// Finds or creates the vertex for a corner.
+    fn vertex(&mut self, key: VertexKey) -> u32 {
+        if let Some(&index) = self.current.lookup.get(&key) {
+            return index;
+        }
+
+        let (normal, generated) = match key.normal {
+            NormalSource::Explicit(normal) => (self.normals[normal], false),
+            NormalSource::Smooth(_) | NormalSource::Flat(_) => (Vec3::ZERO, true),
+        };
+        let builder = &mut self.current;
+        builder.vertices.push(Vertex {
+            position: self.positions[key.position].into(),
+            color: self.colors[key.position].into(),
+            normal: normal.into(),
+            uv: key.uv.map(|uv| self.uvs[uv]).unwrap_or_default().into(),
+            tangent: [0.0; 4],
+        });
+        builder.generated_normals.push(generated);
+
+        let index = builder.vertices.len() as u32 - 1;
+        builder.lookup.insert(key, index);
+        index
+    }
+
+    fn finish(mut self) -> ObjModel {
+        self.start_mesh(String::new(), None);
+        ObjModel {
+            meshes: self.meshes,
+            materials: self.materials,
+        }
+    }
+}
+
+/// Area-weighted normal of a polygon by Newell's method, which stays robust
+/// for concave and slightly non-planar polygons.
+fn polygon_normal(polygon: &[Vec3]) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    for (index, current) in polygon.iter().enumerate() {
+        let next = polygon[(index + 1) % polygon.len()];
+        normal += Vec3::new(
+            (current.y - next.y) * (current.z + next.z),
+            (current.z - next.z) * (current.x + next.x),
+            (current.x - next.x) * (current.y + next.y),
+        );
+    }
+    normal * 0.5
+}
+
+/// Triangulates a planar polygon by ear clipping, returning corner indices.
+///
+/// The polygon is projected onto the plane it faces most, so concave
+/// polygons work as long as they don't self-intersect. If no ear can be
+/// found (degenerate input) the rest is fan-triangulated.
+fn triangulate(polygon: &[Vec3]) -> Vec<[usize; 3]> {
+    if polygon.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let normal = polygon_normal(polygon);
+
+    // Drop the dominant axis; the polygon then winds counter-clockwise in 2D
+    let abs = normal.abs();
+    let project = |point: Vec3| -> Vec2 {
+        if abs.x >= abs.y && abs.x >= abs.z {
+            if normal.x > 0.0 { Vec2::new(point.y, point.z) } else { Vec2::new(point.z, point.y) }
+        } else if abs.y >= abs.z {
+            if normal.y > 0.0 { Vec2::new(point.z, point.x) } else { Vec2::new(point.x, point.z) }
+        } else if normal.z > 0.0 {
+            Vec2::new(point.x, point.y)
+        } else {
+            Vec2::new(point.y, point.x)
+        }
+    };
+    let points: Vec<Vec2> = polygon.iter().map(|&point| project(point)).collect();
+
+    let cross = |a: Vec2, b: Vec2, c: Vec2| (b - a).perp_dot(c - a);
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    let mut triangles = Vec::with_capacity(polygon.len() - 2);
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let (a, b, c) = (remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            if cross(pa, pb, pc) <= 0.0 {
+                return false; // reflex or degenerate corner
+            }
+            // No other corner may lie inside the candidate ear
+            remaining.iter().all(|&other| {
+                if other == a || other == b || other == c {
+                    return true;
+                }
+                let p = points[other];
+                !(cross(pa, pb, p) >= 0.0 && cross(pb, pc, p) >= 0.0 && cross(pc, pa, p) >= 0.0)
+            })
+        });
+
+        match ear {
+            Some(i) => {
+                let a = remaining[(i + count - 1) % count];
+                let c = remaining[(i + 1) % count];
+                triangles.push([a, remaining[i], c]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+/// Parses the color of a `Kd` or `Ke` statement: either red, green and blue,
+/// or a single grey level.
+fn parse_color(file: &str, line: usize, keyword: &str, args: &[&str]) -> Result<Vec3, ObjError> {
+    if args.len() == 2 {
+        return Err(ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message: format!("expected 1 or 3 numbers in `{keyword}` statement, found 2"),
+        });
+    }
+    let values = parse_floats(file, line, keyword, args, 1, 3)?;
+    Ok(match values[..] {
+        [grey] => Vec3::splat(grey),
+        _ => Vec3::new(values[0], values[1], values[2]),
+    })
+}
+
+/// The parts of a texture map statement the importer uses.
+struct TextureMap<'a> {
+    /// File name, which may contain spaces.
+    path: &'a str,
+    /// Scale of a bump or normal map, from `-bm`.
+    bump_multiplier: Option<f32>,
+}
+
+fn texture_file(directory: &Path, path: &str) -> TextureSource {
+    TextureSource::File(directory.join(path))
+}
+
+/// Splits the first whitespace-separated token off `text`.
+fn split_token(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (end > 0).then(|| (&text[..end], &text[end..]))
+}
+
+/// Parses the arguments of a texture map statement: options such as
+/// `-bm 1.0` or `-s 2 2 1`, then a file name that runs to the end of the
+/// statement.
+fn parse_texture_map<'a>(file: &str, line: usize, keyword: &str, arguments: &'a str) -> Result<TextureMap<'a>, ObjError> {
+    let error = |message: String| ObjError::Parse {
+        file: file.to_string(),
+        line,
+        message,
+    };
+    let mut map = TextureMap {
+        path: "",
+        bump_multiplier: None,
+    };
+    let mut rest = arguments;
+    while let Some((option, after)) = split_token(rest) {
+        // Options take a fixed number of values, except the offset, scale and
+        // turbulence vectors, which take one to three numbers
+        let (min, max) = match option {
+            "-blendu" | "-blendv" | "-boost" | "-cc" | "-clamp" | "-texres" | "-imfchan" | "-bm" | "-type" => (1, 1),
+            "-mm" => (2, 2),
+            "-o" | "-s" | "-t" => (1, 3),
+            _ => break,
+        };
+        rest = after;
+        let mut values = Vec::new();
+        while values.len() < max {
+            match split_token(rest) {
+                Some((value, after)) if values.len() < min || value.parse::<f32>().is_ok() => {
+                    values.push(value);
+                    rest = after;
+                }
+                _ => break,
+            }
+        }
+        if values.len() < min {
+            return Err(error(format!("`{option}` option of `{keyword}` statement is missing its value")));
+        }
+        if option == "-bm" {
+            let value = values[0].parse().map_err(|_| {
+                error(format!("invalid number `{}` in `{keyword}` statement", values[0]))
+            })?;
+            map.bump_multiplier = Some(value);
+        }
+    }
+
+    map.path = rest.trim();
+    if map.path.is_empty() {
+        return Err(error(format!("`{keyword}` statement without a file name")));
+    }
+    Ok(map)
+}
+
+/// Parses an MTL material library. Texture paths are resolved against `directory`.
+pub fn parse_mtl(source: &str, file: &str, directory: &Path) -> Result<Vec<Material>, ObjError> {
+    let mut materials: Vec<Material> = Vec::new();
+    // Whether the current material set roughness explicitly with `Pr`
+    let mut explicit_roughness = false;
+
+    for (line, statement) in statements(source) {
+        let mut tokens = statement.split_whitespace();
+        let keyword = tokens.next().unwrap_or_default();
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(ObjError::Parse {
+                    file: file.to_string(),
+                    line,
+                    message: "`newmtl` statement without a name".to_string(),
+                });
+            }
+            materials.push(Material::new(args.join(" ")));
+            explicit_roughness = false;
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(ObjError::Parse {
+                file: file.to_string(),
+                line,
+                message: format!("`{keyword}` statement before any `newmtl`"),
+            });
+        };
+
+        let texture = || -> Result<TextureMap, ObjError> {
+            let arguments = statement[keyword.len()..].trim_start();
+            parse_texture_map(file, line, keyword, arguments)
+        };
+
+        match keyword {
+            "Kd" => {
+                let color = parse_color(file, line, keyword, &args)?;
+                material.base_color = color.extend(material.base_color.w);
+            }
+            "d" | "Tr" => {
+                let value = parse_floats(file, line, keyword, &args, 1, 1)?[0];
+                material.base_color.w = if keyword == "d" { value } else { 1.0 - value };
+                material.alpha_mode = if material.base_color.w < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque };
+            }
+            "Ke" => material.emissive = parse_color(file, line, keyword, &args)?,
+            "Ns" => {
+                // Map the Phong exponent onto roughness unless it was given directly
+                let exponent = parse_floats(file, line, keyword, &args, 1, 1)?[0].max(0.0);
+                if !explicit_roughness {
+                    material.roughness = (2.0 / (exponent + 2.0)).sqrt();
+                }
+            }
+            "Pr" => {
+                material.roughness = parse_floats(file, line, keyword, &args, 1, 1)?[0];
+                explicit_roughness = true;
+            }
+            "Pm" => material.metallic = parse_floats(file, line, keyword, &args, 1, 1)?[0],
+            "map_Kd" => material.base_color_texture = Some(texture_file(directory, texture()?.path)),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                let map = texture()?;
+                material.normal_texture = Some(texture_file(directory, map.path));
+                if let Some(scale) = map.bump_multiplier {
+                    material.normal_scale = scale;
+                }
+            }
+            "map_Ke" => material.emissive_texture = Some(texture_file(directory, texture()?.path)),
+            // Legacy Phong terms and maps without a metallic-roughness equivalent
+            "Ka" | "Ks" | "Tf" | "Ni" | "illum" | "sharpness" | "map_Ka" | "map_Ks" | "map_Ns"
+            | "map_d" | "map_Pr" | "map_Pm" | "disp" | "decal" | "refl" | "Ps" | "Pc" | "Pcr"
+            | "aniso" | "anisor" => {
+                log::debug!("{file}:{line}: ignoring unsupported `{keyword}` statement");
+            }
+            _ => {
+                log::warn!("{file}:{line}: ignoring unknown statement `{keyword}`");
+            }
+        }
+    }
+
+    Ok(materials)
+}
@@ -0,0 +1,23 @@
+// IntSar-3D: A Simple 3D Engine in Rust
+
+// Module declarations
+pub mod antialias;
+pub mod bloom;
+pub mod camera;
+pub mod cluster;
+pub mod component;
+pub mod deferred;
+pub mod import;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod mesh;
+pub mod postprocess;
+pub mod primitives;
+pub mod renderer;
+pub mod scene;
+pub mod scene_file;
+pub mod shadow;
+pub mod ssao;
+pub mod texture;
+pub mod tonemap;
@@ -0,0 +1,48 @@
+// Light module for IntSar-3D
+
+use crate::shadow::Shadows;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Shape of the light emitted by a [`Light`].
+///
+/// Directional and spot lights shine down the object's local -Z axis.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        /// Angle from the axis, in radians, where the falloff begins.
+        inner_cone_angle: f32,
+        /// Angle from the axis, in radians, where the light reaches zero.
+        outer_cone_angle: f32,
+    },
+}
+
+/// Light source attached to a scene object.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB color.
+    pub color: Vec3,
+    /// Lux for directional lights, candela for point and spot lights.
+    pub intensity: f32,
+    /// Distance at which the light is cut off; `None` for no cutoff.
+    pub range: Option<f32>,
+    /// Shadow settings, or `None` for a light that casts no shadows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadows: Option<Shadows>,
+}
+
+impl Light {
+    /// Creates a white light of the given kind and intensity.
+    pub fn new(kind: LightKind, intensity: f32) -> Self {
+        Self {
+            kind,
+            color: Vec3::ONE,
+            intensity,
+            range: None,
+            shadows: None,
+        }
+    }
+}
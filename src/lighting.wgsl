@@ -0,0 +1,367 @@
+// Lighting shared by the forward and deferred shaders of IntSar-3D
+//
+// Declares the camera, lights, light clusters, shadow maps and screen-space
+// occlusion in bind group 0, and the BRDF that shades a surface with one
+// light. The shaders that use them are appended to this file.
+
+// Uniform buffer for the camera
+struct CameraUniforms {
+    // Jittered under TAA
+    view_proj: mat4x4<f32>,
+    // Inverse of the jittered one, for rebuilding positions from depth
+    inverse_view_proj: mat4x4<f32>,
+    // Without jitter, this frame and the previous one, for motion vectors
+    unjittered_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    // World-space position in xyz
+    position: vec4<f32>,
+    // View direction in xyz
+    forward: vec4<f32>,
+    // Size of the render target in pixels
+    viewport: vec2<f32>,
+    // Distances to the near and far planes
+    depth_range: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniforms;
+
+// Lights, matching `LightUniform` and `LightUniforms` in renderer.rs
+const MAX_LIGHTS: u32 = 1024u;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    // Direction the light travels in
+    direction: vec3<f32>,
+    // Cutoff distance, or 0 for none
+    range: f32,
+    // Color premultiplied by intensity
+    color: vec3<f32>,
+    cos_inner: f32,
+    cos_outer: f32,
+    // Layer of the first map in `shadow_maps`, or -1 for none
+    shadow_index: i32,
+    depth_bias: f32,
+    slope_bias: f32,
+    normal_bias: f32,
+    // Maps in consecutive layers, one per cascade
+    cascade_count: u32,
+    cascade_blend: f32,
+    // Distance stored as depth 1 in a point light's cube faces
+    shadow_far: f32,
+    // View distance at which each cascade ends
+    cascade_splits: vec4<f32>,
+};
+
+const MAX_SHADOW_MAPS: u32 = 8u;
+const MAX_POINT_SHADOWS: u32 = 4u;
+
+struct LightUniforms {
+    ambient: vec3<f32>,
+    count: u32,
+    // Nonzero to tint surfaces by shadow cascade
+    debug_cascades: u32,
+    shadow_view_projs: array<mat4x4<f32>, MAX_SHADOW_MAPS>,
+    // Six cube faces per point light: +X, -X, +Y, -Y, +Z, -Z
+    point_shadow_view_projs: array<mat4x4<f32>, 24>,
+};
+
+@group(0) @binding(1)
+var<uniform> lighting: LightUniforms;
+
+@group(0) @binding(2)
+var shadow_maps: texture_depth_2d_array;
+@group(0) @binding(3)
+var shadow_sampler: sampler_comparison;
+// Linear distance from point lights over `shadow_far`, one layer per light
+// holding its six cube faces in a 3x2 atlas
+@group(0) @binding(4)
+var point_shadow_maps: texture_depth_2d_array;
+// Screen-space ambient occlusion of each pixel, all 1 while SSAO is off
+@group(0) @binding(5)
+var ambient_occlusion_texture: texture_2d<f32>;
+// Every light, `lighting.count` of them
+@group(0) @binding(6)
+var<storage, read> lights: array<Light>;
+
+// Light clusters, matching cluster.rs
+const CLUSTER_GRID: vec3<u32> = vec3<u32>(16u, 9u, 24u);
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128u;
+
+// For each cluster, the number of lights that reach it and then their
+// indices, padded to `MAX_LIGHTS_PER_CLUSTER`
+@group(0) @binding(7)
+var<storage, read> light_clusters: array<u32>;
+
+const PI: f32 = 3.14159265359;
+
+// Light arriving at `position` from one light, and the direction towards it
+fn incoming(light: Light, position: vec3<f32>) -> vec4<f32> {
+    if light.kind == LIGHT_DIRECTIONAL {
+        return vec4<f32>(-light.direction, 1.0);
+    }
+
+    let offset = light.position - position;
+    let distance_squared = max(dot(offset, offset), 0.0001);
+    let to_light = offset * inverseSqrt(distance_squared);
+    var attenuation = 1.0 / distance_squared;
+
+    // Smooth cutoff at the range, as recommended by KHR_lights_punctual
+    if light.range > 0.0 {
+        let ratio = sqrt(distance_squared) / light.range;
+        let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+        attenuation *= window * window;
+    }
+
+    if light.kind == LIGHT_SPOT {
+        let cos_angle = dot(light.direction, -to_light);
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+    }
+    return vec4<f32>(to_light, attenuation);
+}
+
+// Fraction of one shadow map that sees `position`, filtered with 3x3 PCF
+fn sample_shadow(light: Light, layer: i32, position: vec3<f32>, n_dot_l: f32) -> f32 {
+    let clip = lighting.shadow_view_projs[layer] * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    // Nothing casts shadows outside the map or behind its far plane
+    if clip.w <= 0.0 || ndc.z > 1.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return 1.0;
+    }
+
+    // Steeper surfaces cover a larger depth range within one texel
+    let slope = min(sqrt(1.0 - n_dot_l * n_dot_l) / n_dot_l, 10.0);
+    let depth = ndc.z - light.depth_bias - light.slope_bias * slope;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_maps));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, layer, depth);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Compares a distance against the cube face that `direction` from a point light points into
+fn sample_point_face(light: Light, direction: vec3<f32>, depth: f32) -> f32 {
+    let size = abs(direction);
+    var face = 0;
+    if size.x >= size.y && size.x >= size.z {
+        face = select(1, 0, direction.x > 0.0);
+    } else if size.y >= size.z {
+        face = select(3, 2, direction.y > 0.0);
+    } else {
+        face = select(5, 4, direction.z > 0.0);
+    }
+    let clip = lighting.point_shadow_view_projs[light.shadow_index * 6 + face] * vec4<f32>(light.position + direction, 1.0);
+
+    // Stay half a texel inside the face's tile so filtering can't reach the next one
+    let atlas_size = vec2<f32>(textureDimensions(point_shadow_maps));
+    let half_texel = 1.5 / atlas_size.x;
+    let uv = clamp(clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5, vec2<f32>(half_texel), vec2<f32>(1.0 - half_texel));
+    let tile = vec2<f32>(f32(face % 3), f32(face / 3));
+    return textureSampleCompareLevel(point_shadow_maps, shadow_sampler, (tile + uv) / vec2<f32>(3.0, 2.0), light.shadow_index, depth);
+}
+
+// Fraction of a point light's cube that sees `position`, filtered with 3x3
+// PCF across the surface so samples near a face edge pick the next face
+fn point_shadow(light: Light, position: vec3<f32>, n_dot_l: f32) -> f32 {
+    let offset = position - light.position;
+    let distance = length(offset);
+    if distance >= light.shadow_far {
+        return 1.0;
+    }
+
+    let slope = min(sqrt(1.0 - n_dot_l * n_dot_l) / n_dot_l, 10.0);
+    let depth = distance / light.shadow_far - light.depth_bias - light.slope_bias * slope;
+
+    // A texel spans about 2 / size at unit distance along a face axis
+    let spread = 6.0 / f32(textureDimensions(point_shadow_maps).x) * distance;
+    let axis = offset / distance;
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(axis.y) > 0.9 {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, axis)) * spread;
+    let bitangent = cross(axis, tangent);
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            lit += sample_point_face(light, offset + tangent * f32(x) + bitangent * f32(y), depth);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Distance of `position` in front of the camera
+fn view_depth(position: vec3<f32>) -> f32 {
+    return dot(position - camera.position.xyz, camera.forward.xyz);
+}
+
+// Offset in `light_clusters` of the cluster a fragment falls in, from its
+// framebuffer and world positions
+fn light_cluster(pixel: vec2<f32>, position: vec3<f32>) -> u32 {
+    let tile = min(vec2<u32>(pixel / camera.viewport * vec2<f32>(CLUSTER_GRID.xy)), CLUSTER_GRID.xy - 1u);
+    // Slices are spaced evenly in log depth
+    let near = camera.depth_range.x;
+    let far = camera.depth_range.y;
+    let depth = max(view_depth(position), near);
+    let slice = min(u32(log(depth / near) / log(far / near) * f32(CLUSTER_GRID.z)), CLUSTER_GRID.z - 1u);
+    let index = tile.x + (tile.y + slice * CLUSTER_GRID.y) * CLUSTER_GRID.x;
+    return index * (MAX_LIGHTS_PER_CLUSTER + 1u);
+}
+
+// Cascade of a light that covers a view depth, or -1 past the last one
+fn cascade_index(light: Light, depth: f32) -> i32 {
+    for (var i = 0; i < i32(light.cascade_count); i++) {
+        if depth < light.cascade_splits[i] {
+            return i;
+        }
+    }
+    return -1;
+}
+
+// Fraction of a light that reaches `position` past its shadow casters
+fn shadow_factor(light: Light, position: vec3<f32>, normal: vec3<f32>, n_dot_l: f32) -> f32 {
+    if light.shadow_index < 0 {
+        return 1.0;
+    }
+    let biased = position + normal * light.normal_bias;
+    if light.kind == LIGHT_POINT {
+        return point_shadow(light, biased, n_dot_l);
+    }
+
+    let depth = view_depth(position);
+    let cascade = cascade_index(light, depth);
+    if cascade < 0 {
+        return 1.0;
+    }
+
+    let lit = sample_shadow(light, light.shadow_index + cascade, biased, n_dot_l);
+
+    // Fade into the next cascade over the end of this one; past the last
+    // cascade, fade out of shadow entirely
+    let end = light.cascade_splits[cascade];
+    var start = 0.0;
+    if cascade > 0 {
+        start = light.cascade_splits[cascade - 1];
+    }
+    let fade_start = end - (end - start) * light.cascade_blend;
+    if light.cascade_blend <= 0.0 || depth <= fade_start {
+        return lit;
+    }
+    var next = 1.0;
+    if cascade + 1 < i32(light.cascade_count) {
+        next = sample_shadow(light, light.shadow_index + cascade + 1, biased, n_dot_l);
+    }
+    return mix(lit, next, (depth - fade_start) / (end - fade_start));
+}
+
+// Tint for the cascade `position` falls in, of the first light with cascades
+fn cascade_tint(position: vec3<f32>) -> vec3<f32> {
+    var tints = array<vec3<f32>, 4>(
+        vec3<f32>(1.0, 0.3, 0.3),
+        vec3<f32>(0.3, 1.0, 0.3),
+        vec3<f32>(0.3, 0.3, 1.0),
+        vec3<f32>(1.0, 1.0, 0.3),
+    );
+    for (var i = 0u; i < min(lighting.count, MAX_LIGHTS); i++) {
+        let light = lights[i];
+        if light.shadow_index >= 0 && light.cascade_count > 1u {
+            let cascade = cascade_index(light, view_depth(position));
+            if cascade >= 0 {
+                return tints[cascade];
+            }
+            break;
+        }
+    }
+    return vec3<f32>(1.0);
+}
+
+// Trowbridge-Reitz (GGX) normal distribution
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha_squared = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    return alpha_squared / (PI * denominator * denominator);
+}
+
+// Height-correlated Smith visibility, which folds in the 4 n.l n.v denominator
+fn visibility_smith_ggx(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha_squared = alpha * alpha;
+    let view = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha_squared) + alpha_squared);
+    let light = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha_squared) + alpha_squared);
+    return 0.5 / max(view + light, 0.0001);
+}
+
+fn fresnel_schlick(v_dot_h: f32, f0: vec3<f32>) -> vec3<f32> {
+    // Rounding can leave v.h a hair above 1, and pow() of a negative is NaN
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+// A point on a surface, as the BRDF sees it
+struct Surface {
+    position: vec3<f32>,
+    // Shading normal, and the normal shadow lookups are offset along
+    normal: vec3<f32>,
+    geometric_normal: vec3<f32>,
+    to_camera: vec3<f32>,
+    n_dot_v: f32,
+    diffuse_color: vec3<f32>,
+    f0: vec3<f32>,
+    alpha: f32,
+    receive_shadows: bool,
+};
+
+// Cook-Torrance BRDF inputs with a Lambertian diffuse lobe for dielectrics
+fn surface(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    geometric_normal: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    receive_shadows: bool,
+) -> Surface {
+    var out: Surface;
+    out.position = position;
+    out.normal = normal;
+    out.geometric_normal = geometric_normal;
+    out.to_camera = normalize(camera.position.xyz - position);
+    out.n_dot_v = max(dot(normal, out.to_camera), 0.0001);
+    out.diffuse_color = base_color * (1.0 - metallic);
+    out.f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    out.alpha = roughness * roughness;
+    out.receive_shadows = receive_shadows;
+    return out;
+}
+
+// Light reflected towards the camera from one light
+fn reflected(light: Light, surface: Surface) -> vec3<f32> {
+    let arriving = incoming(light, surface.position);
+    let to_light = arriving.xyz;
+    let n_dot_l = dot(surface.normal, to_light);
+    if n_dot_l <= 0.0 || arriving.w <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    var visibility = 1.0;
+    if surface.receive_shadows {
+        visibility = shadow_factor(light, surface.position, surface.geometric_normal, n_dot_l);
+    }
+
+    let half_vector = normalize(to_light + surface.to_camera);
+    let n_dot_h = max(dot(surface.normal, half_vector), 0.0);
+    let v_dot_h = max(dot(surface.to_camera, half_vector), 0.0);
+
+    let fresnel = fresnel_schlick(v_dot_h, surface.f0);
+    let specular = fresnel * distribution_ggx(n_dot_h, surface.alpha)
+        * visibility_smith_ggx(n_dot_l, surface.n_dot_v, surface.alpha);
+    let diffuse = (1.0 - fresnel) * surface.diffuse_color / PI;
+    return (diffuse + specular) * light.color * arriving.w * n_dot_l * visibility;
+}
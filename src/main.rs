@@ -1,10 +1,11 @@
 // IntSar-3D: A Simple 3D Engine in Rust
 
-// Module declarations
-mod math;
-mod renderer;
-mod scene;
-
+use intsar_3d::import::{gltf, obj};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::Mesh;
+use intsar_3d::renderer;
+use intsar_3d::scene::{Scene, SceneObject};
+use intsar_3d::scene_file;
 use winit::event_loop::EventLoop;
 
 #[tokio::main]
@@ -16,7 +17,34 @@ async fn main() {
     let event_loop = EventLoop::new().expect("Failed to create event loop");
 
     // Create renderer
-    let renderer = renderer::Renderer::new(&event_loop).await;
+    let mut renderer = renderer::Renderer::new(&event_loop).await;
+
+    // Build the scene from the scene, OBJ or glTF file given on the command line, or the default cube
+    let mut scene = Scene::new();
+    match std::env::args().nth(1) {
+        Some(path) => {
+            let result = if path.ends_with(".ron") {
+                scene_file::load_scene(&path)
+                    .map(|loaded| scene = loaded)
+                    .map_err(|err| err.to_string())
+            } else if path.ends_with(".gltf") || path.ends_with(".glb") {
+                gltf::import_gltf(&path, &mut scene, Transform::identity()).map_err(|err| err.to_string())
+            } else {
+                obj::load_obj(&path)
+                    .map(|model| model.add_to_scene(&mut scene, Transform::identity()))
+                    .map_err(|err| err.to_string())
+            };
+            if let Err(err) = result {
+                eprintln!("Failed to load {path}: {err}");
+                std::process::exit(1);
+            }
+        }
+        None => {
+            let cube = scene.add_mesh(Mesh::cube());
+            scene.add_object(SceneObject::new("Cube".to_string(), Transform::identity()).with_mesh(cube));
+        }
+    }
+    renderer.set_scene(scene);
 
     // Run the renderer
     renderer.run(event_loop);
@@ -0,0 +1,102 @@
+// Material module for IntSar-3D
+
+use crate::texture::Sampler;
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where the image data of a material texture comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextureSource {
+    /// An image file on disk.
+    File(PathBuf),
+    /// An encoded image (PNG, JPEG, ...) held in memory, such as one embedded
+    /// in a glTF file.
+    Embedded {
+        mime_type: Option<String>,
+        data: Arc<[u8]>,
+    },
+}
+
+/// How the alpha channel of a material's base color is used.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// Alpha is ignored and the surface is fully opaque.
+    #[default]
+    Opaque,
+    /// Fragments with alpha below `cutoff` are discarded; the rest are opaque.
+    Mask { cutoff: f32 },
+    /// The surface is blended over whatever is behind it.
+    Blend,
+}
+
+/// Surface parameters of a mesh, in metallic-roughness terms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub name: String,
+    /// Linear RGB color with opacity in `w`.
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB emitted light.
+    pub emissive: Vec3,
+    #[serde(default)]
+    pub alpha_mode: AlphaMode,
+    /// Multiplier for the X and Y components of the normal texture.
+    #[serde(default = "one")]
+    pub normal_scale: f32,
+    /// How strongly the occlusion texture darkens ambient light, from 0 to 1.
+    #[serde(default = "one")]
+    pub occlusion_strength: f32,
+    pub base_color_texture: Option<TextureSource>,
+    /// Roughness in the green channel, metalness in the blue channel.
+    pub metallic_roughness_texture: Option<TextureSource>,
+    pub normal_texture: Option<TextureSource>,
+    /// Ambient occlusion in the red channel.
+    pub occlusion_texture: Option<TextureSource>,
+    pub emissive_texture: Option<TextureSource>,
+    /// Sampler shared by all of the material's textures.
+    #[serde(default)]
+    pub sampler: Sampler,
+}
+
+impl Material {
+    /// Creates a white, non-metallic material with the given name.
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: Vec4::ONE,
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: Vec3::ZERO,
+            alpha_mode: AlphaMode::Opaque,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            sampler: Sampler::default(),
+        }
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// Handle to a material stored in a [`Scene`](crate::scene::Scene).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MaterialHandle(pub(crate) usize);
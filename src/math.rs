@@ -1,9 +1,10 @@
 // Math utilities for 3D engine
 
 use glam::{Vec3, Mat4, Quat};
+use serde::{Deserialize, Serialize};
 
 /// Represents a 3D transformation
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
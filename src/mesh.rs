@@ -0,0 +1,275 @@
+// Mesh module for IntSar-3D
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+// Define Vertex struct for vertex data
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// Tangent in `xyz`, bitangent sign (handedness) in `w`.
+    pub tangent: [f32; 4],
+}
+
+impl Vertex {
+    /// Creates a vertex from a position and color, with no normal, UV or tangent.
+    pub fn new(position: [f32; 3], color: [f32; 3]) -> Self {
+        Self {
+            position,
+            color,
+            normal: [0.0; 3],
+            uv: [0.0; 2],
+            tangent: [0.0; 4],
+        }
+    }
+
+    pub(crate) fn layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Float32x3,
+            3 => Float32x2,
+            4 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Index data of a mesh, in 16-bit or 32-bit form.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    /// Picks the smallest index type able to address `indices`.
+    pub fn compact(indices: Vec<u32>) -> Self {
+        if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        }
+    }
+
+    /// Number of indices.
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    /// Returns `true` if there are no indices.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the indices widened to `u32`.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Indices::U16(indices) => Box::new(indices.iter().map(|&i| i as u32)),
+            Indices::U32(indices) => Box::new(indices.iter().copied()),
+        }
+    }
+
+    fn format(&self) -> wgpu::IndexFormat {
+        match self {
+            Indices::U16(_) => wgpu::IndexFormat::Uint16,
+            Indices::U32(_) => wgpu::IndexFormat::Uint32,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Indices::U16(indices) => bytemuck::cast_slice(indices),
+            Indices::U32(indices) => bytemuck::cast_slice(indices),
+        }
+    }
+}
+
+/// GPU buffers of an uploaded mesh.
+#[derive(Debug)]
+pub(crate) struct GpuMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
+    /// Minimum and maximum corners of the vertex positions.
+    pub bounds: (Vec3, Vec3),
+}
+
+/// Handle to a mesh stored in a [`Scene`](crate::scene::Scene).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MeshHandle(pub(crate) usize);
+
+/// Triangle geometry kept on the CPU and uploaded to the GPU on first use.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Mesh {
+    pub name: String,
+    vertices: Vec<Vertex>,
+    indices: Indices,
+    #[serde(skip)]
+    gpu: Option<GpuMesh>,
+}
+
+impl Mesh {
+    /// Creates a mesh from triangle-list vertex and index data.
+    pub fn new(name: String, vertices: Vec<Vertex>, indices: Indices) -> Self {
+        Self {
+            name,
+            vertices,
+            indices,
+            gpu: None,
+        }
+    }
+
+    /// Vertex data of the mesh.
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    /// Index data of the mesh.
+    pub fn indices(&self) -> &Indices {
+        &self.indices
+    }
+
+    /// Mutable vertex data. The GPU copy is re-uploaded on next use.
+    pub fn vertices_mut(&mut self) -> &mut [Vertex] {
+        self.gpu = None;
+        &mut self.vertices
+    }
+
+    /// Replaces the geometry. The GPU copy is re-uploaded on next use.
+    pub fn set_geometry(&mut self, vertices: Vec<Vertex>, indices: Indices) {
+        self.vertices = vertices;
+        self.indices = indices;
+        self.gpu = None;
+    }
+
+    /// Recomputes per-vertex tangents from positions, normals and UVs.
+    ///
+    /// Triangle tangents are accumulated per vertex, then orthogonalized
+    /// against the normal. Vertices without usable UVs get an arbitrary
+    /// tangent perpendicular to their normal. As in glTF, the handedness is
+    /// chosen so that `cross(normal, tangent) * w` points towards decreasing
+    /// V, which is up in the texture image.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+
+        let indices: Vec<u32> = self.indices.iter().collect();
+        for triangle in indices.chunks_exact(3) {
+            let [i0, i1, i2] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (v0, v1, v2) = (&self.vertices[i0], &self.vertices[i1], &self.vertices[i2]);
+
+            let edge1 = Vec3::from(v1.position) - Vec3::from(v0.position);
+            let edge2 = Vec3::from(v2.position) - Vec3::from(v0.position);
+            let duv1 = Vec2::from(v1.uv) - Vec2::from(v0.uv);
+            let duv2 = Vec2::from(v2.uv) - Vec2::from(v0.uv);
+
+            let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+
+            for index in [i0, i1, i2] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+
+        for (vertex, (tangent, bitangent)) in self.vertices.iter_mut().zip(tangents.into_iter().zip(bitangents)) {
+            let normal = Vec3::from(vertex.normal).normalize_or_zero();
+
+            // Gram-Schmidt orthogonalize against the normal
+            let mut t = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+            if t == Vec3::ZERO {
+                t = normal.any_orthonormal_vector();
+            }
+            let handedness = if normal.cross(t).dot(bitangent) > 0.0 { -1.0 } else { 1.0 };
+            vertex.tangent = [t.x, t.y, t.z, handedness];
+        }
+
+        self.gpu = None;
+    }
+
+    /// Minimum and maximum corners of the vertex positions, or `None` for a
+    /// mesh without vertices.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut positions = self.vertices.iter().map(|vertex| Vec3::from(vertex.position));
+        let first = positions.next()?;
+        Some(positions.fold((first, first), |(min, max), position| (min.min(position), max.max(position))))
+    }
+
+    /// Returns `true` once the mesh has GPU buffers.
+    pub fn is_uploaded(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    /// Creates the GPU buffers if they don't exist yet.
+    pub(crate) fn upload(&mut self, device: &wgpu::Device) {
+        if self.gpu.is_some() {
+            return;
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", self.name)),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", self.name)),
+            contents: self.indices.as_bytes(),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        self.gpu = Some(GpuMesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: self.indices.len() as u32,
+            index_format: self.indices.format(),
+            bounds: self.bounds().unwrap_or_default(),
+        });
+    }
+
+    /// GPU buffers, if the mesh has been uploaded.
+    pub(crate) fn gpu(&self) -> Option<&GpuMesh> {
+        self.gpu.as_ref()
+    }
+
+    /// Built-in unit cube with a different solid color on each face.
+    pub fn cube() -> Self {
+        // Faces come out of the generator as front, back, top, bottom, right, left
+        const FACE_COLORS: [[f32; 3]; 6] = [
+            [1.0, 0.0, 0.0], // red
+            [0.0, 1.0, 0.0], // green
+            [0.0, 0.0, 1.0], // blue
+            [1.0, 1.0, 0.0], // yellow
+            [1.0, 0.0, 1.0], // magenta
+            [0.0, 1.0, 1.0], // cyan
+        ];
+
+        let mut mesh = crate::primitives::cube(1.0, 1);
+        for (face, color) in mesh.vertices_mut().chunks_mut(4).zip(FACE_COLORS) {
+            for vertex in face {
+                vertex.color = color;
+            }
+        }
+        mesh
+    }
+}
@@ -0,0 +1,39 @@
+// Declarations shared by every post-processing shader effect in IntSar-3D
+//
+// An effect's own source is appended to this. It provides the fragment entry
+// point `fs_main(in: FullscreenOutput) -> @location(0) vec4<f32>` and may
+// declare its parameters as a uniform at `@group(1) @binding(0)`.
+
+struct PostUniforms {
+    // Frame size in pixels, and the size of one pixel in UV units
+    size: vec2<f32>,
+    texel_size: vec2<f32>,
+    // Seconds since the renderer was created
+    time: f32,
+};
+
+// The frame so far, sampled with linear filtering and clamped to the edge
+@group(0) @binding(0)
+var post_input: texture_2d<f32>;
+
+@group(0) @binding(1)
+var post_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> post: PostUniforms;
+
+struct FullscreenOutput {
+    @builtin(position) position: vec4<f32>,
+    // Zero at the top left corner of the frame, one at the bottom right
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the whole frame
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: FullscreenOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
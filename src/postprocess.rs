@@ -0,0 +1,619 @@
+// Post-processing for IntSar-3D
+//
+// After the main pass, the frame runs through a chain of effects. Each effect
+// reads the frame so far and draws the next version of it into another
+// target; two targets per stage are swapped back and forth ("ping-ponged").
+// Effects in the HDR stage see linear scene color before tone mapping, which
+// suits effects like bloom that care about light above 1.0. Effects in the
+// display stage see the tone-mapped frame, and the last of them draws
+// straight into the surface.
+//
+// Effects implement [`PostEffect`] and manage their own GPU resources.
+// [`ShaderEffect`] covers the common case of a single WGSL fragment shader
+// with a block of parameters.
+
+use bytemuck::{Pod, Zeroable};
+use std::any::Any;
+use std::fmt;
+use wgpu::naga;
+
+/// Declarations prepended to every [`ShaderEffect`]'s source.
+pub const SHADER_PRELUDE: &str = include_str!("post.wgsl");
+
+/// Part of the frame a [`PostEffect`] works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostStage {
+    /// Linear HDR color in [`HDR_FORMAT`](crate::tonemap::HDR_FORMAT),
+    /// before exposure and tone mapping.
+    Hdr,
+    /// Tone-mapped color in the surface's format, ready for display.
+    Display,
+}
+
+/// A fullscreen pass run after the main pass.
+///
+/// Effects run in the order they were added, within their stage. They create
+/// whatever GPU resources they need on their first frame, and should expect
+/// the frame size and format to change between frames.
+pub trait PostEffect: Any {
+    /// Stage of the frame the effect works on.
+    fn stage(&self) -> PostStage;
+
+    /// Whether the effect runs this frame. Disabled effects are skipped
+    /// without costing a pass.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Draws the next version of the frame from `frame.input` into
+    /// `frame.output`, covering every pixel.
+    fn render(&mut self, frame: &mut PostFrame<'_>);
+}
+
+/// What a [`PostEffect`] draws with.
+pub struct PostFrame<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The frame so far.
+    pub input: &'a wgpu::TextureView,
+    /// Target of the effect, the same size as `input`.
+    pub output: &'a wgpu::TextureView,
+    /// Format of both `input` and `output`.
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Seconds since the renderer was created.
+    pub time: f32,
+}
+
+/// Identifies a post-processing effect added to a
+/// [`Renderer`](crate::renderer::Renderer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PostEffectHandle(pub(crate) usize);
+
+/// Error produced while creating a post-processing effect.
+#[derive(Debug)]
+pub enum PostEffectError {
+    /// The effect's WGSL doesn't parse or validate.
+    Shader { name: String, message: String },
+    /// The effect's WGSL has no `fs_main` fragment entry point.
+    MissingEntryPoint { name: String },
+}
+
+impl fmt::Display for PostEffectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostEffectError::Shader { name, message } => write!(f, "invalid shader in effect {name}: {message}"),
+            PostEffectError::MissingEntryPoint { name } => {
+                write!(f, "effect {name} has no `fs_main` fragment entry point")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PostEffectError {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct PostUniforms {
+    size: [f32; 2],
+    texel_size: [f32; 2],
+    time: f32,
+    _padding: [f32; 3],
+}
+
+/// A post-processing effect made of one WGSL fragment shader.
+///
+/// The shader is appended to [`SHADER_PRELUDE`], which binds the frame so
+/// far and provides the vertex stage. Parameters are plain bytes laid out as
+/// the shader's uniform at `@group(1) @binding(0)` expects them; any part of
+/// that uniform they don't cover reads as zero.
+pub struct ShaderEffect {
+    name: String,
+    stage: PostStage,
+    source: String,
+    parameters: Vec<u8>,
+    /// Size of the uniform the shader declares for its parameters, or 0.
+    parameter_size: wgpu::BufferAddress,
+    enabled: bool,
+    gpu: Option<GpuShaderEffect>,
+}
+
+struct GpuShaderEffect {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    input_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    parameter_bind_group_layout: wgpu::BindGroupLayout,
+    parameter_buffer: wgpu::Buffer,
+    parameter_bind_group: wgpu::BindGroup,
+}
+
+impl ShaderEffect {
+    /// Creates an effect from the WGSL `source` of its fragment shader,
+    /// checking that it compiles together with [`SHADER_PRELUDE`].
+    pub fn new(name: impl Into<String>, stage: PostStage, source: impl Into<String>) -> Result<Self, PostEffectError> {
+        let name = name.into();
+        let source = format!("{SHADER_PRELUDE}\n{}", source.into());
+
+        let module = naga::front::wgsl::parse_str(&source).map_err(|err| PostEffectError::Shader {
+            name: name.clone(),
+            message: err.emit_to_string(&source),
+        })?;
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .map_err(|err| PostEffectError::Shader {
+                name: name.clone(),
+                message: err.emit_to_string(&source),
+            })?;
+        let has_entry_point = module.entry_points.iter()
+            .any(|entry| entry.name == "fs_main" && entry.stage == naga::ShaderStage::Fragment);
+        if !has_entry_point {
+            return Err(PostEffectError::MissingEntryPoint { name });
+        }
+        let parameter_size = module.global_variables.iter()
+            .find(|(_, global)| {
+                global.space == naga::AddressSpace::Uniform
+                    && global.binding == Some(naga::ResourceBinding { group: 1, binding: 0 })
+            })
+            .map_or(0, |(_, global)| module.types[global.ty].inner.size(module.to_ctx()));
+
+        Ok(Self {
+            name,
+            stage,
+            source,
+            parameters: Vec::new(),
+            parameter_size: parameter_size as wgpu::BufferAddress,
+            enabled: true,
+            gpu: None,
+        })
+    }
+
+    /// Sets the effect's initial parameters.
+    pub fn with_parameters<T: Pod>(mut self, parameters: &T) -> Self {
+        self.set_parameters(parameters);
+        self
+    }
+
+    /// Name the effect was created with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Parameters as bound to the shader.
+    pub fn parameters(&self) -> &[u8] {
+        &self.parameters
+    }
+
+    /// Replaces the parameters, taking effect from the next frame.
+    pub fn set_parameters<T: Pod>(&mut self, parameters: &T) {
+        self.parameters = bytemuck::bytes_of(parameters).to_vec();
+    }
+
+    /// Turns the effect on or off.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Size of the parameter buffer: enough for both the parameters and the
+    /// shader's uniform, and as uniforms need, at least 16 bytes in multiples
+    /// of 16.
+    fn parameter_buffer_size(&self) -> wgpu::BufferAddress {
+        let size = (self.parameters.len() as wgpu::BufferAddress).max(self.parameter_size);
+        size.max(1).div_ceil(16) * 16
+    }
+
+    fn create_parameter_buffer(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Parameter Buffer", self.name)),
+            size: self.parameter_buffer_size(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Parameter Bind Group", self.name)),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    fn create_gpu(&self, device: &wgpu::Device, format: wgpu::TextureFormat) -> GpuShaderEffect {
+        let input_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Input Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let parameter_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Parameter Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(self.parameter_size),
+                },
+                count: None,
+            }],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.name),
+            source: wgpu::ShaderSource::Wgsl(self.source.as_str().into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Pipeline Layout", self.name)),
+            bind_group_layouts: &[&input_bind_group_layout, &parameter_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{} Pipeline", self.name)),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Uniform Buffer"),
+            size: std::mem::size_of::<PostUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (parameter_buffer, parameter_bind_group) =
+            self.create_parameter_buffer(device, &parameter_bind_group_layout);
+
+        GpuShaderEffect {
+            format,
+            pipeline,
+            input_bind_group_layout,
+            sampler,
+            uniform_buffer,
+            parameter_bind_group_layout,
+            parameter_buffer,
+            parameter_bind_group,
+        }
+    }
+}
+
+impl PostEffect for ShaderEffect {
+    fn stage(&self) -> PostStage {
+        self.stage
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn render(&mut self, frame: &mut PostFrame<'_>) {
+        // Pipelines are built for one target format
+        let mut gpu = match self.gpu.take() {
+            Some(gpu) if gpu.format == frame.format => gpu,
+            _ => self.create_gpu(frame.device, frame.format),
+        };
+        if gpu.parameter_buffer.size() != self.parameter_buffer_size() {
+            (gpu.parameter_buffer, gpu.parameter_bind_group) =
+                self.create_parameter_buffer(frame.device, &gpu.parameter_bind_group_layout);
+        }
+
+        let (width, height) = (frame.width as f32, frame.height as f32);
+        frame.queue.write_buffer(&gpu.uniform_buffer, 0, bytemuck::bytes_of(&PostUniforms {
+            size: [width, height],
+            texel_size: [1.0 / width, 1.0 / height],
+            time: frame.time,
+            _padding: [0.0; 3],
+        }));
+        if !self.parameters.is_empty() {
+            let mut parameters = self.parameters.clone();
+            parameters.resize(gpu.parameter_buffer.size() as usize, 0);
+            frame.queue.write_buffer(&gpu.parameter_buffer, 0, &parameters);
+        }
+
+        // The input alternates between targets, so it's bound afresh each frame
+        let input_bind_group = frame.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Input Bind Group"),
+            layout: &gpu.input_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(frame.input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&gpu.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: gpu.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        {
+            let mut pass = frame.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&self.name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: frame.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&gpu.pipeline);
+            pass.set_bind_group(0, &input_bind_group, &[]);
+            pass.set_bind_group(1, &gpu.parameter_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        self.gpu = Some(gpu);
+    }
+}
+
+/// Lets the renderer hand effects back as their concrete type.
+pub(crate) trait AnyPostEffect: PostEffect {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: PostEffect> AnyPostEffect for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A pair of same-sized targets that effects draw back and forth between.
+struct PingPong {
+    format: wgpu::TextureFormat,
+    views: [wgpu::TextureView; 2],
+}
+
+impl PingPong {
+    fn new(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let create_view = |index| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("{label} {index}")),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        Self {
+            format,
+            views: [create_view(0), create_view(1)],
+        }
+    }
+}
+
+/// The renderer's post-processing effects and the targets they draw into.
+pub(crate) struct PostProcess {
+    effects: Vec<Option<Box<dyn AnyPostEffect>>>,
+    width: u32,
+    height: u32,
+    hdr: PingPong,
+    display_format: wgpu::TextureFormat,
+    /// Created once a display stage effect first runs.
+    display: Option<PingPong>,
+    /// Runs ahead of the display stage effects when FXAA is selected.
+    fxaa: Option<ShaderEffect>,
+}
+
+impl PostProcess {
+    /// Creates `width`x`height` HDR targets; display targets will use
+    /// `display_format`.
+    pub(crate) fn new(device: &wgpu::Device, display_format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        Self {
+            effects: Vec::new(),
+            width,
+            height,
+            hdr: PingPong::new(device, "HDR Texture", crate::tonemap::HDR_FORMAT, width, height),
+            display_format,
+            display: None,
+            fxaa: None,
+        }
+    }
+
+    /// Recreates the targets at a new size.
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.hdr = PingPong::new(device, "HDR Texture", self.hdr.format, width, height);
+        self.display = None;
+    }
+
+    /// Both HDR targets; the main pass draws into the first.
+    pub(crate) fn hdr_views(&self) -> [&wgpu::TextureView; 2] {
+        [&self.hdr.views[0], &self.hdr.views[1]]
+    }
+
+    pub(crate) fn add(&mut self, effect: Box<dyn AnyPostEffect>) -> PostEffectHandle {
+        self.effects.push(Some(effect));
+        PostEffectHandle(self.effects.len() - 1)
+    }
+
+    pub(crate) fn get(&self, handle: PostEffectHandle) -> Option<&dyn AnyPostEffect> {
+        self.effects.get(handle.0)?.as_deref()
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: PostEffectHandle) -> Option<&mut (dyn AnyPostEffect + 'static)> {
+        self.effects.get_mut(handle.0)?.as_deref_mut()
+    }
+
+    pub(crate) fn remove(&mut self, handle: PostEffectHandle) -> Option<Box<dyn AnyPostEffect>> {
+        self.effects.get_mut(handle.0)?.take()
+    }
+
+    fn enabled(&self, stage: PostStage) -> impl Iterator<Item = usize> + '_ {
+        self.effects.iter()
+            .enumerate()
+            .filter(move |(_, effect)| effect.as_ref().is_some_and(|effect| effect.stage() == stage && effect.enabled()))
+            .map(|(index, _)| index)
+    }
+
+    /// Turns the built-in FXAA pass on or off.
+    pub(crate) fn set_fxaa(&mut self, enabled: bool) {
+        self.fxaa = enabled.then(crate::antialias::fxaa);
+    }
+
+    /// Whether any effect runs in the display stage, in which case tone
+    /// mapping draws into [`PostProcess::display_input`] instead of the surface.
+    pub(crate) fn has_display_effects(&self) -> bool {
+        self.fxaa.is_some() || self.enabled(PostStage::Display).next().is_some()
+    }
+
+    /// The target tone mapping draws into when there are display effects.
+    pub(crate) fn display_input(&mut self, device: &wgpu::Device) -> &wgpu::TextureView {
+        let (format, width, height) = (self.display_format, self.width, self.height);
+        &self.display
+            .get_or_insert_with(|| PingPong::new(device, "Display Texture", format, width, height))
+            .views[0]
+    }
+
+    /// Runs the HDR stage on the frame in HDR target `input`, returning the
+    /// index of the HDR target holding the result.
+    pub(crate) fn run_hdr(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        mut input: usize,
+        time: f32,
+    ) -> usize {
+        let order: Vec<usize> = self.enabled(PostStage::Hdr).collect();
+        for index in order {
+            let Some(effect) = self.effects[index].as_mut() else {
+                continue;
+            };
+            effect.render(&mut PostFrame {
+                device,
+                queue,
+                encoder,
+                input: &self.hdr.views[input],
+                output: &self.hdr.views[1 - input],
+                format: self.hdr.format,
+                width: self.width,
+                height: self.height,
+                time,
+            });
+            input = 1 - input;
+        }
+        input
+    }
+
+    /// Runs the display stage on the tone-mapped frame in
+    /// [`PostProcess::display_input`], FXAA first, with the last effect
+    /// drawing into `output`.
+    pub(crate) fn run_display(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        time: f32,
+    ) {
+        let Some(targets) = &self.display else {
+            return;
+        };
+        let effects = self.effects.iter_mut()
+            .flatten()
+            .filter(|effect| effect.stage() == PostStage::Display && effect.enabled())
+            .map(|effect| effect.as_mut() as &mut dyn PostEffect);
+        let order: Vec<&mut dyn PostEffect> = self.fxaa.iter_mut()
+            .map(|fxaa| fxaa as &mut dyn PostEffect)
+            .chain(effects)
+            .collect();
+        let count = order.len();
+        let mut input = 0;
+        for (position, effect) in order.into_iter().enumerate() {
+            let last = position + 1 == count;
+            effect.render(&mut PostFrame {
+                device,
+                queue,
+                encoder,
+                input: &targets.views[input],
+                output: if last { output } else { &targets.views[1 - input] },
+                format: targets.format,
+                width: self.width,
+                height: self.height,
+                time,
+            });
+            input = 1 - input;
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// Procedural primitive meshes for IntSar-3D
+//
+// Every generator produces positions, normals, UVs and tangents, with
+// counter-clockwise front faces pointing outwards. Shapes are centered on the
+// origin with Y up, and vertex colors are white.
+
+use crate::mesh::{Indices, Mesh, Vertex};
+use glam::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Accumulates vertices and triangles for a generated mesh.
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        self.vertices.push(Vertex {
+            position: position.into(),
+            color: [1.0; 3],
+            normal: normal.into(),
+            uv: uv.into(),
+            tangent: [0.0; 4],
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    fn position(&self, index: u32) -> Vec3 {
+        Vec3::from(self.vertices[index as usize].position)
+    }
+
+    fn normal(&self, index: u32) -> Vec3 {
+        Vec3::from(self.vertices[index as usize].normal)
+    }
+
+    /// Adds a triangle wound counter-clockwise around `facing`.
+    /// Zero-area triangles (e.g. at sphere poles) are dropped.
+    fn triangle(&mut self, a: u32, b: u32, c: u32, facing: Vec3) {
+        let (pa, pb, pc) = (self.position(a), self.position(b), self.position(c));
+        let cross = (pb - pa).cross(pc - pa);
+        if cross.length_squared() < 1e-12 {
+            return;
+        }
+        if cross.dot(facing) >= 0.0 {
+            self.indices.extend_from_slice(&[a, b, c]);
+        } else {
+            self.indices.extend_from_slice(&[a, c, b]);
+        }
+    }
+
+    /// Adds a `columns` x `rows` grid of quads. `sample(column, row)` returns
+    /// the position, normal and UV of each of the `(columns + 1) * (rows + 1)`
+    /// grid points.
+    fn surface(&mut self, columns: u32, rows: u32, sample: impl Fn(u32, u32) -> (Vec3, Vec3, Vec2)) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal, uv) = sample(column, row);
+                self.vertex(position, normal, uv);
+            }
+        }
+
+        let stride = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * stride + column;
+                let b = a + 1;
+                let c = a + stride + 1;
+                let d = a + stride;
+                let facing = self.normal(a) + self.normal(b) + self.normal(c) + self.normal(d);
+                self.triangle(a, b, c, facing);
+                self.triangle(a, c, d, facing);
+            }
+        }
+    }
+
+    /// Adds a flat disc cap at height `y`, facing up or down.
+    fn cap(&mut self, radius: f32, y: f32, segments: u32, up: bool) {
+        let normal = if up { Vec3::Y } else { Vec3::NEG_Y };
+        let flip = if up { 1.0 } else { -1.0 };
+        let center = self.vertex(Vec3::new(0.0, y, 0.0), normal, Vec2::splat(0.5));
+
+        let first = self.vertices.len() as u32;
+        for segment in 0..=segments {
+            let angle = segment as f32 / segments as f32 * TAU;
+            let (sin, cos) = angle.sin_cos();
+            let uv = Vec2::new(0.5 + 0.5 * sin, 0.5 + 0.5 * cos * flip);
+            self.vertex(Vec3::new(radius * sin, y, radius * cos), normal, uv);
+        }
+        for segment in 0..segments {
+            self.triangle(center, first + segment, first + segment + 1, normal);
+        }
+    }
+
+    fn build(self, name: &str) -> Mesh {
+        let mut mesh = Mesh::new(name.to_string(), self.vertices, Indices::compact(self.indices));
+        mesh.generate_tangents();
+        mesh
+    }
+}
+
+/// Direction on the unit sphere at polar angle `theta` (0 at +Y) and azimuth
+/// `phi` (0 at +Z, increasing towards +X).
+fn sphere_direction(theta: f32, phi: f32) -> Vec3 {
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    Vec3::new(sin_theta * sin_phi, cos_theta, sin_theta * cos_phi)
+}
+
+/// Axis-aligned cube with `subdivisions` quads along each edge of each face.
+///
+/// Faces are emitted in the order front (+Z), back, top, bottom, right (+X),
+/// left, each as a contiguous block of vertices.
+pub fn cube(size: f32, subdivisions: u32) -> Mesh {
+    let subdivisions = subdivisions.max(1);
+    let half = size * 0.5;
+
+    // (normal, right, down) as seen from outside the face
+    let faces = [
+        (Vec3::Z, Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::X, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::X, Vec3::NEG_Z, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+    ];
+
+    let mut builder = MeshBuilder::new();
+    for (normal, right, down) in faces {
+        builder.surface(subdivisions, subdivisions, |column, row| {
+            let uv = Vec2::new(column as f32, row as f32) / subdivisions as f32;
+            let position = normal * half + right * (uv.x - 0.5) * size + down * (uv.y - 0.5) * size;
+            (position, normal, uv)
+        });
+    }
+    builder.build("Cube")
+}
+
+/// Flat grid in the XZ plane facing +Y.
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> Mesh {
+    let (columns, rows) = (subdivisions_x.max(1), subdivisions_z.max(1));
+
+    let mut builder = MeshBuilder::new();
+    builder.surface(columns, rows, |column, row| {
+        let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+        let position = Vec3::new((uv.x - 0.5) * width, 0.0, (uv.y - 0.5) * depth);
+        (position, Vec3::Y, uv)
+    });
+    builder.build("Plane")
+}
+
+/// Latitude/longitude sphere with `sectors` slices around Y and `stacks` rings.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Mesh {
+    let (sectors, stacks) = (sectors.max(3), stacks.max(2));
+
+    let mut builder = MeshBuilder::new();
+    builder.surface(sectors, stacks, |column, row| {
+        let uv = Vec2::new(column as f32 / sectors as f32, row as f32 / stacks as f32);
+        let normal = sphere_direction(uv.y * PI, uv.x * TAU);
+        (normal * radius, normal, uv)
+    });
+    builder.build("UV Sphere")
+}
+
+/// Sphere built by repeatedly subdividing an icosahedron.
+///
+/// Each subdivision level splits every triangle into four; UVs use a
+/// spherical mapping with vertices duplicated along the seam.
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    // Icosahedron from three orthogonal golden rectangles
+    let t = (1.0 + 5.0_f32.sqrt()) * 0.5;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalize())
+    .collect();
+
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push(((positions[a as usize] + positions[b as usize]) * 0.5).normalize());
+                positions.len() as u32 - 1
+            })
+        };
+
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for [a, b, c] in triangles {
+            let ab = midpoint(a, b, &mut positions);
+            let bc = midpoint(b, c, &mut positions);
+            let ca = midpoint(c, a, &mut positions);
+            subdivided.extend_from_slice(&[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        triangles = subdivided;
+    }
+
+    let spherical_uv = |direction: Vec3| {
+        Vec2::new(
+            0.5 + direction.x.atan2(direction.z) / TAU,
+            direction.y.clamp(-1.0, 1.0).acos() / PI,
+        )
+    };
+
+    let mut builder = MeshBuilder::new();
+    for triangle in triangles {
+        let directions = triangle.map(|index| positions[index as usize]);
+        let mut uvs = directions.map(spherical_uv);
+
+        // Triangles straddling the seam get their low-u corners wrapped past 1
+        let max_u = uvs.iter().map(|uv| uv.x).fold(f32::MIN, f32::max);
+        let min_u = uvs.iter().map(|uv| uv.x).fold(f32::MAX, f32::min);
+        if max_u - min_u > 0.5 {
+            for uv in &mut uvs {
+                if uv.x < 0.5 {
+                    uv.x += 1.0;
+                }
+            }
+        }
+
+        let corners: Vec<u32> = (0..3)
+            .map(|corner| builder.vertex(directions[corner] * radius, directions[corner], uvs[corner]))
+            .collect();
+        let centroid = directions[0] + directions[1] + directions[2];
+        builder.triangle(corners[0], corners[1], corners[2], centroid);
+    }
+    builder.build("Icosphere")
+}
+
+/// Capped cylinder along Y with `segments` slices and `rings` bands along its height.
+pub fn cylinder(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let half = height * 0.5;
+
+    let mut builder = MeshBuilder::new();
+    builder.surface(segments, rings, |column, row| {
+        let uv = Vec2::new(column as f32 / segments as f32, row as f32 / rings as f32);
+        let normal = sphere_direction(FRAC_PI_2, uv.x * TAU);
+        let position = normal * radius + Vec3::Y * (half - uv.y * height);
+        (position, normal, uv)
+    });
+    builder.cap(radius, half, segments, true);
+    builder.cap(radius, -half, segments, false);
+    builder.build("Cylinder")
+}
+
+/// Cone along Y with its apex at the top and a capped base.
+pub fn cone(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let half = height * 0.5;
+
+    let mut builder = MeshBuilder::new();
+    builder.surface(segments, rings, |column, row| {
+        let uv = Vec2::new(column as f32 / segments as f32, row as f32 / rings as f32);
+        let outward = sphere_direction(FRAC_PI_2, uv.x * TAU);
+        // The side normal tilts up by the slope of the cone
+        let normal = (outward * height + Vec3::Y * radius).normalize();
+        let position = outward * radius * uv.y + Vec3::Y * (half - uv.y * height);
+        (position, normal, uv)
+    });
+    builder.cap(radius, -half, segments, false);
+    builder.build("Cone")
+}
+
+/// Torus around Y. `major_radius` is the distance from the center to the
+/// middle of the tube, `minor_radius` the radius of the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Mesh {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+
+    let mut builder = MeshBuilder::new();
+    builder.surface(major_segments, minor_segments, |column, row| {
+        let uv = Vec2::new(column as f32 / major_segments as f32, row as f32 / minor_segments as f32);
+        let outward = sphere_direction(FRAC_PI_2, uv.x * TAU);
+        let (sin, cos) = (uv.y * TAU).sin_cos();
+        let normal = outward * cos + Vec3::Y * sin;
+        let position = outward * major_radius + normal * minor_radius;
+        (position, normal, uv)
+    });
+    builder.build("Torus")
+}
+
+/// Capsule along Y: a cylinder of length `height` between two hemispheres.
+///
+/// `rings` is the number of bands per hemisphere. V runs along the profile
+/// proportionally to arc length, so textures don't stretch on the caps.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let half = height * 0.5;
+    let cap_length = FRAC_PI_2 * radius;
+    let total_length = 2.0 * cap_length + height;
+
+    // Rows 0..=rings are the top hemisphere, the rest the bottom one; the band
+    // between the two equator rows is the cylinder
+    let mut builder = MeshBuilder::new();
+    builder.surface(segments, 2 * rings + 1, |column, row| {
+        let (theta, offset, v) = if row <= rings {
+            let fraction = row as f32 / rings as f32;
+            (fraction * FRAC_PI_2, half, fraction * cap_length / total_length)
+        } else {
+            let fraction = (row - rings - 1) as f32 / rings as f32;
+            let v = (cap_length + height + fraction * cap_length) / total_length;
+            (FRAC_PI_2 + fraction * FRAC_PI_2, -half, v)
+        };
+        let u = column as f32 / segments as f32;
+        let normal = sphere_direction(theta, u * TAU);
+        (normal * radius + Vec3::Y * offset, normal, Vec2::new(u, v))
+    });
+    builder.build("Capsule")
+}
@@ -1,35 +1,167 @@
 // Renderer module for IntSar-3D
 
 use winit::{
+    dpi::PhysicalSize,
     event::{Event, WindowEvent, KeyEvent, ElementState},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
     keyboard::{PhysicalKey, KeyCode},
 };
-use wgpu::{Adapter, Instance, RenderPipeline, Buffer}; // Import necessary types
-use std::sync::Arc;
-use glam::{Mat4, Vec3, Quat};
+use wgpu::{Adapter, RenderPipeline, Buffer}; // Import necessary types
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{mpsc, Arc};
+use glam::{Mat4, Quat, Vec3};
 use std::time::Instant;
+use crate::antialias::{self, AntiAliasing, AntiAliasingError, TemporalResolve, VELOCITY_FORMAT};
+use crate::cluster::LightClusters;
+use crate::deferred::{self, GBuffer, GBufferChannel, LightVolume, RenderPath, ALBEDO_FORMAT, MATERIAL_FORMAT, NORMAL_FORMAT};
+use crate::light::{Light, LightKind};
+use crate::material::{AlphaMode, Material, MaterialHandle};
+use crate::mesh::{Mesh, MeshHandle, Vertex};
+use crate::scene::{ObjectHandle, Scene};
+use crate::shadow::{
+    self, Caster, Frustum, PointFace, ShadowFlags, ShadowMaps, ShadowViews, MAX_POINT_SHADOWS, MAX_SHADOW_MAPS,
+};
+use crate::ssao::{AmbientOcclusion, SsaoSettings};
+use crate::texture::{ColorSpace, TextureCache, TextureData};
+use crate::postprocess::{AnyPostEffect, PostEffect, PostEffectHandle, PostProcess};
+use crate::tonemap::{Exposure, ToneMapper, ToneMapping, HDR_FORMAT};
+
+/// Color format used by offscreen render targets.
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Format of the depth buffer used by the main pass.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Most lights a frame is shaded with; lights beyond this are ignored.
+pub const MAX_LIGHTS: usize = 1024;
+
+/// Depth the depth buffer is cleared to every frame: the far plane.
+const CLEAR_DEPTH: f32 = 1.0;
+
+/// Error produced when the main pass can't use a depth comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthCompareError {
+    /// No fragment in front of the far plane passes the comparison against
+    /// the cleared depth buffer, so nothing would be drawn.
+    DrawsNothing(wgpu::CompareFunction),
+}
+
+impl fmt::Display for DepthCompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DepthCompareError::DrawsNothing(compare) => {
+                write!(f, "depth comparison {compare:?} draws nothing against a depth buffer cleared to the far plane")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DepthCompareError {}
+
+/// HDR color of pixels no mesh covers.
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
 
 pub struct Renderer {
-    instance: Instance,
     adapter: Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    surface: wgpu::Surface<'static>,
-    window: Arc<winit::window::Window>,
+    target: RenderTarget,
+    size: PhysicalSize<u32>,
+    shader_module: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
     pipeline: RenderPipeline,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    blend_pipeline: RenderPipeline,
+    /// Draws opaque meshes' depth for SSAO.
+    prepass_pipeline: RenderPipeline,
+    /// Draws opaque meshes into the G-buffer on the deferred path.
+    gbuffer_pipeline: Option<RenderPipeline>,
+    depth_compare: wgpu::CompareFunction,
+    depth_view: wgpu::TextureView,
+    anti_aliasing: AntiAliasing,
+    /// Multisampled color target resolved into the HDR target under MSAA.
+    msaa_view: Option<wgpu::TextureView>,
+    /// Motion vectors and history under TAA.
+    taa: Option<TemporalResolve>,
+    /// Frames rendered so far, which picks the TAA jitter.
+    frame_index: u32,
+    /// Unjittered view-projection and world matrices of the previous frame,
+    /// for motion vectors.
+    previous_view_proj: Option<Mat4>,
+    previous_world: HashMap<ObjectHandle, Mat4>,
     uniform_buffer: Buffer,
+    light_buffer: Buffer,
+    /// Every light, and the lights that reach each cluster of the view.
+    light_clusters: LightClusters,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
+    shadow_maps: ShadowMaps,
+    ambient_occlusion: AmbientOcclusion,
+    /// G-buffer and lighting passes, only on the deferred path.
+    gbuffer: Option<GBuffer>,
+    tone_mapper: ToneMapper,
+    post_process: PostProcess,
+    tone_mapping: ToneMapping,
+    exposure: Exposure,
+    object_bind_group_layout: wgpu::BindGroupLayout,
+    object_buffer: Buffer,
+    object_bind_group: wgpu::BindGroup,
+    object_capacity: usize,
+    object_stride: wgpu::BufferAddress,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    /// Opaque white and flat-normal textures standing in for missing maps.
+    fallback_textures: FallbackTextures,
+    textures: TextureCache,
+    /// Bind groups of the scene's materials, by material handle.
+    gpu_materials: Vec<Option<GpuMaterial>>,
+    /// Bind group for objects without a material.
+    default_material_bind_group: wgpu::BindGroup,
     surface_format: wgpu::TextureFormat,
+    scene: Scene,
     camera_position: Vec3,
-    cube_rotation: Vec3,
+    ambient_light: Vec3,
+    /// Whether surfaces are tinted by the shadow cascade they fall in.
+    debug_cascades: bool,
+    camera_yaw: f32,
+    camera_pitch: f32,
     start_time: Instant,
+    /// When the windowed loop last drew a frame, for the auto-orbit.
+    last_frame: Instant,
     keys_pressed: KeyboardState,
 }
 
+/// Textures bound in place of a material's missing maps.
+struct FallbackTextures {
+    white: Arc<wgpu::TextureView>,
+    flat_normal: Arc<wgpu::TextureView>,
+}
+
+/// A material's textures and sampler as bound on the GPU.
+struct GpuMaterial {
+    /// Texture sources and sampler the bind group was built from.
+    material: Material,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Where rendered frames end up.
+enum RenderTarget {
+    /// A window surface that is presented on screen.
+    Window {
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+    },
+    /// An offscreen texture whose pixels can be read back to the CPU.
+    Offscreen {
+        texture: wgpu::Texture,
+    },
+}
+
 #[derive(Default)]
 struct KeyboardState {
     w: bool,
@@ -38,35 +170,257 @@ struct KeyboardState {
     d: bool,
 }
 
-// Define Vertex struct for vertex data
+// Uniform buffer structure for the camera
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniforms {
+    /// Jittered under TAA.
+    view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    unjittered_view_proj: [[f32; 4]; 4],
+    previous_view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+    /// View direction in xyz, for measuring view depth.
+    forward: [f32; 4],
+    /// Size of the render target in pixels, for finding light clusters.
+    viewport: [f32; 2],
+    /// Distances to the near and far planes.
+    depth_range: [f32; 2],
+}
+
+impl CameraUniforms {
+    fn new() -> Self {
+        Self {
+            view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            inverse_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            unjittered_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            previous_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            position: [0.0, 0.0, 0.0, 1.0],
+            forward: [0.0, 0.0, -1.0, 0.0],
+            viewport: [1.0, 1.0],
+            depth_range: [0.1, 100.0],
+        }
+    }
+
+    fn update_view_proj(&mut self, view_proj: Mat4) {
+        self.view_proj = view_proj.to_cols_array_2d();
+        self.inverse_view_proj = view_proj.inverse().to_cols_array_2d();
+    }
+
+    fn update_motion(&mut self, unjittered_view_proj: Mat4, previous_view_proj: Mat4) {
+        self.unjittered_view_proj = unjittered_view_proj.to_cols_array_2d();
+        self.previous_view_proj = previous_view_proj.to_cols_array_2d();
+    }
+
+    fn update_position(&mut self, position: Vec3) {
+        self.position = position.extend(1.0).to_array();
+    }
+
+    fn update_forward(&mut self, forward: Vec3) {
+        self.forward = forward.extend(0.0).to_array();
+    }
+
+    fn update_viewport(&mut self, size: PhysicalSize<u32>, near: f32, far: f32) {
+        self.viewport = [size.width as f32, size.height as f32];
+        self.depth_range = [near, far];
+    }
+}
+
+// A single light as the shader sees it
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
+struct LightUniform {
     position: [f32; 3],
+    kind: u32,
+    /// Direction the light travels in.
+    direction: [f32; 3],
+    /// Cutoff distance, or 0 for none.
+    range: f32,
+    /// Color premultiplied by intensity.
     color: [f32; 3],
+    cos_inner: f32,
+    cos_outer: f32,
+    /// Layer of the light's first shadow map, or -1 for none.
+    shadow_index: i32,
+    depth_bias: f32,
+    slope_bias: f32,
+    normal_bias: f32,
+    /// Shadow maps in consecutive layers, one per cascade.
+    cascade_count: u32,
+    cascade_blend: f32,
+    /// Distance stored as depth 1 in a point light's cube faces.
+    shadow_far: f32,
+    /// View distance at which each cascade ends.
+    cascade_splits: [f32; 4],
+}
+
+impl LightUniform {
+    fn new(light: &Light, world: Mat4) -> Self {
+        let (kind, cos_inner, cos_outer) = match light.kind {
+            LightKind::Directional => (0, 1.0, 1.0),
+            LightKind::Point => (1, 1.0, 1.0),
+            LightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                (2, inner_cone_angle.cos(), outer_cone_angle.cos())
+            }
+        };
+        Self {
+            position: world.transform_point3(Vec3::ZERO).to_array(),
+            kind,
+            direction: world.transform_vector3(Vec3::NEG_Z).normalize_or_zero().to_array(),
+            range: light.range.unwrap_or(0.0),
+            color: (light.color * light.intensity).to_array(),
+            cos_inner,
+            cos_outer,
+            shadow_index: -1,
+            depth_bias: 0.0,
+            slope_bias: 0.0,
+            normal_bias: 0.0,
+            cascade_count: 0,
+            cascade_blend: 0.0,
+            shadow_far: 0.0,
+            cascade_splits: [0.0; 4],
+        }
+    }
+
+    /// Makes the light look up its shadows in the layers of the shadow maps
+    /// starting at `index`, one per cascade ending at the given view distance.
+    fn set_shadow(&mut self, index: usize, shadows: &shadow::Shadows, cascade_ends: &[f32], cascade_blend: f32) {
+        self.shadow_index = index as i32;
+        self.depth_bias = shadows.depth_bias;
+        self.slope_bias = shadows.slope_bias;
+        self.normal_bias = shadows.normal_bias;
+        self.cascade_count = cascade_ends.len() as u32;
+        self.cascade_blend = cascade_blend;
+        self.cascade_splits[..cascade_ends.len()].copy_from_slice(cascade_ends);
+    }
+
+    /// Makes a point light look up its shadows in the six cube faces of slot
+    /// `index`, which store distances up to `far`.
+    fn set_point_shadow(&mut self, index: usize, shadows: &shadow::Shadows, far: f32) {
+        self.set_shadow(index, shadows, &[], 0.0);
+        self.shadow_far = far;
+    }
 }
 
-// Uniform buffer structure for MVP matrix
+// The ambient term and shadow maps of the scene's lights, which themselves
+// are in a storage buffer
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    mvp: [[f32; 4]; 4],
+struct LightUniforms {
+    ambient: [f32; 3],
+    count: u32,
+    /// Nonzero to tint surfaces by shadow cascade.
+    debug_cascades: u32,
+    _padding: [u32; 3],
+    shadow_view_projs: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+    point_shadow_view_projs: [[[f32; 4]; 4]; 6 * MAX_POINT_SHADOWS],
 }
 
-impl Uniforms {
-    fn new() -> Self {
+impl LightUniforms {
+    fn new(ambient: Vec3, count: usize, shadow_views: &ShadowViews, debug_cascades: bool) -> Self {
+        let mut uniforms = Self {
+            ambient: ambient.to_array(),
+            count: count as u32,
+            debug_cascades: debug_cascades as u32,
+            _padding: [0; 3],
+            shadow_view_projs: [Mat4::IDENTITY.to_cols_array_2d(); MAX_SHADOW_MAPS],
+            point_shadow_view_projs: [Mat4::IDENTITY.to_cols_array_2d(); 6 * MAX_POINT_SHADOWS],
+        };
+        for (slot, view_proj) in uniforms.shadow_view_projs.iter_mut().zip(&shadow_views.maps) {
+            *slot = view_proj.to_cols_array_2d();
+        }
+        for (slot, face) in uniforms.point_shadow_view_projs.iter_mut().zip(&shadow_views.point_faces) {
+            *slot = face.view_proj.to_cols_array_2d();
+        }
+        uniforms
+    }
+}
+
+// Metallic-roughness factors of an object's material
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniforms {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    /// Fragments with less alpha are discarded.
+    alpha_cutoff: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+
+impl MaterialUniforms {
+    fn new(material: &Material) -> Self {
+        let mut base_color = material.base_color;
+        let alpha_cutoff = match material.alpha_mode {
+            AlphaMode::Opaque => {
+                base_color.w = 1.0;
+                0.0
+            }
+            AlphaMode::Mask { cutoff } => cutoff,
+            AlphaMode::Blend => 0.0,
+        };
         Self {
-            mvp: Mat4::IDENTITY.to_cols_array_2d(),
+            base_color: base_color.to_array(),
+            emissive: material.emissive.to_array(),
+            metallic: material.metallic,
+            roughness: material.roughness,
+            alpha_cutoff,
+            // Zero leaves the flat fallback normal texture exactly flat
+            normal_scale: if material.normal_texture.is_some() { material.normal_scale } else { 0.0 },
+            occlusion_strength: material.occlusion_strength,
         }
     }
+}
+
+// Per-object uniforms, one slot per scene object selected with a dynamic offset
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ObjectUniforms {
+    model: [[f32; 4]; 4],
+    normal: [[f32; 4]; 4],
+    material: MaterialUniforms,
+    receive_shadows: u32,
+    _padding: [u32; 3],
+    previous_model: [[f32; 4]; 4],
+}
 
-    fn update_mvp(&mut self, mvp: Mat4) {
-        self.mvp = mvp.to_cols_array_2d();
+impl ObjectUniforms {
+    fn new(model: Mat4, previous_model: Mat4, material: &Material, receive_shadows: bool) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: model.inverse().transpose().to_cols_array_2d(),
+            material: MaterialUniforms::new(material),
+            receive_shadows: receive_shadows as u32,
+            _padding: [0; 3],
+            previous_model: previous_model.to_cols_array_2d(),
+        }
     }
 }
 
+/// A mesh to draw with the uniforms in an object slot.
+struct Draw {
+    slot: usize,
+    mesh: MeshHandle,
+    material: Option<MaterialHandle>,
+    /// Whether the mesh is alpha blended rather than opaque.
+    blend: bool,
+    /// Whether the mesh is drawn into shadow maps.
+    cast_shadows: bool,
+    /// Squared distance from the camera, used to sort blended meshes.
+    distance: f32,
+}
+
 impl Renderer {
+    /// Creates a renderer that draws into a new window, on the forward path.
     pub async fn new(event_loop: &EventLoop<()>) -> Self {
+        Self::new_with_render_path(event_loop, RenderPath::Forward).await
+    }
+
+    /// Creates a renderer that draws into a new window, lighting opaque
+    /// meshes on the given render path.
+    pub async fn new_with_render_path(event_loop: &EventLoop<()>, render_path: RenderPath) -> Self {
         // Create window with Arc for shared ownership
         let window = Arc::new(WindowBuilder::new()
             .with_title("IntSar-3D")
@@ -89,14 +443,7 @@ impl Renderer {
             force_fallback_adapter: false,
         }).await.unwrap();
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("Renderer Device"),
-                required_features: wgpu::Features::default(),
-                required_limits: wgpu::Limits::default(),
-            },
-            None, // Trace path
-        ).await.unwrap();
+        let (device, queue) = Self::request_device(&adapter).await.unwrap();
 
         // Get surface capabilities
         let surface_caps = surface.get_capabilities(&adapter);
@@ -118,38 +465,113 @@ impl Renderer {
             desired_maximum_frame_latency: 2,
         });
 
+        let target = RenderTarget::Window { window, surface };
+        Self::from_parts(adapter, device, queue, target, surface_format, size, render_path)
+    }
+
+    /// Creates a renderer that draws into an offscreen texture instead of a window.
+    ///
+    /// A fallback (software) adapter is preferred so this works on machines
+    /// without a GPU or display. Returns `None` if no adapter is available.
+    pub async fn new_headless(width: u32, height: u32) -> Option<Self> {
+        Self::new_headless_with_render_path(width, height, RenderPath::Forward).await
+    }
+
+    /// Creates a headless renderer like [`Renderer::new_headless`], lighting
+    /// opaque meshes on the given render path.
+    pub async fn new_headless_with_render_path(width: u32, height: u32, render_path: RenderPath) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        // Prefer a software adapter, but take whatever is there otherwise
+        let mut adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }).await;
+        if adapter.is_none() {
+            adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            }).await;
+        }
+        let adapter = adapter?;
+        log::info!("Headless renderer using adapter {:?}", adapter.get_info());
+
+        let (device, queue) = Self::request_device(&adapter).await.ok()?;
+
+        let size = PhysicalSize::new(width.max(1), height.max(1));
+        let texture = Self::create_offscreen_texture(&device, size);
+        let target = RenderTarget::Offscreen { texture };
+        Some(Self::from_parts(adapter, device, queue, target, OFFSCREEN_FORMAT, size, render_path))
+    }
+
+    async fn request_device(adapter: &Adapter) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Renderer Device"),
+                // Lets MSAA use every sample count the adapter supports
+                required_features: adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            },
+            None, // Trace path
+        ).await
+    }
+
+    fn create_offscreen_texture(device: &wgpu::Device, size: PhysicalSize<u32>) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OFFSCREEN_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Builds the pipeline and buffers shared by windowed and headless renderers.
+    fn from_parts(
+        adapter: Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget,
+        surface_format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+        render_path: RenderPath,
+    ) -> Self {
         // Load shader
-        let shader_code = include_str!("shader.wgsl");
+        let shader_code = concat!(include_str!("lighting.wgsl"), include_str!("shader.wgsl"));
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
             source: wgpu::ShaderSource::Wgsl(shader_code.into()),
         });
 
-        // Define vertex buffer layout
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
-        };
-
-        // Create bind group layout for uniforms
+        // Create bind group layouts for camera and light uniforms, and per-object uniforms
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Uniform Bind Group Layout"),
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -157,201 +579,648 @@ impl Renderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let object_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Object Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ObjectUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // Material textures in the order of the shader's bindings, then their sampler
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let material_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
             ],
         });
 
         // Create render pipeline
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&bind_group_layout, &object_bind_group_layout, &material_bind_group_layout],
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                buffers: &[vertex_buffer_layout],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+        let depth_compare = wgpu::CompareFunction::Less;
+        let anti_aliasing = AntiAliasing::default();
+        let pipeline = Self::create_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader_module,
+            depth_compare,
+            anti_aliasing,
+            false,
+        );
+        let blend_pipeline = Self::create_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader_module,
+            depth_compare,
+            anti_aliasing,
+            true,
+        );
+        let prepass_pipeline = Self::create_prepass_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader_module,
+            depth_compare,
+        );
+        let deferred = render_path == RenderPath::Deferred;
+        let gbuffer_pipeline = deferred.then(|| {
+            Self::create_gbuffer_pipeline(&device, &render_pipeline_layout, &shader_module, depth_compare, anti_aliasing)
         });
-
-        // Create cube vertices with colors
-        let vertices = [
-            // Front face (red)
-            Vertex { position: [-0.5, -0.5,  0.5], color: [1.0, 0.0, 0.0] },
-            Vertex { position: [ 0.5, -0.5,  0.5], color: [1.0, 0.0, 0.0] },
-            Vertex { position: [ 0.5,  0.5,  0.5], color: [1.0, 0.0, 0.0] },
-            Vertex { position: [-0.5,  0.5,  0.5], color: [1.0, 0.0, 0.0] },
-            
-            // Back face (green)
-            Vertex { position: [-0.5, -0.5, -0.5], color: [0.0, 1.0, 0.0] },
-            Vertex { position: [-0.5,  0.5, -0.5], color: [0.0, 1.0, 0.0] },
-            Vertex { position: [ 0.5,  0.5, -0.5], color: [0.0, 1.0, 0.0] },
-            Vertex { position: [ 0.5, -0.5, -0.5], color: [0.0, 1.0, 0.0] },
-            
-            // Top face (blue)
-            Vertex { position: [-0.5,  0.5, -0.5], color: [0.0, 0.0, 1.0] },
-            Vertex { position: [-0.5,  0.5,  0.5], color: [0.0, 0.0, 1.0] },
-            Vertex { position: [ 0.5,  0.5,  0.5], color: [0.0, 0.0, 1.0] },
-            Vertex { position: [ 0.5,  0.5, -0.5], color: [0.0, 0.0, 1.0] },
-            
-            // Bottom face (yellow)
-            Vertex { position: [-0.5, -0.5, -0.5], color: [1.0, 1.0, 0.0] },
-            Vertex { position: [ 0.5, -0.5, -0.5], color: [1.0, 1.0, 0.0] },
-            Vertex { position: [ 0.5, -0.5,  0.5], color: [1.0, 1.0, 0.0] },
-            Vertex { position: [-0.5, -0.5,  0.5], color: [1.0, 1.0, 0.0] },
-            
-            // Right face (magenta)
-            Vertex { position: [ 0.5, -0.5, -0.5], color: [1.0, 0.0, 1.0] },
-            Vertex { position: [ 0.5,  0.5, -0.5], color: [1.0, 0.0, 1.0] },
-            Vertex { position: [ 0.5,  0.5,  0.5], color: [1.0, 0.0, 1.0] },
-            Vertex { position: [ 0.5, -0.5,  0.5], color: [1.0, 0.0, 1.0] },
-            
-            // Left face (cyan)
-            Vertex { position: [-0.5, -0.5, -0.5], color: [0.0, 1.0, 1.0] },
-            Vertex { position: [-0.5, -0.5,  0.5], color: [0.0, 1.0, 1.0] },
-            Vertex { position: [-0.5,  0.5,  0.5], color: [0.0, 1.0, 1.0] },
-            Vertex { position: [-0.5,  0.5, -0.5], color: [0.0, 1.0, 1.0] },
-        ];
-
-        // Create indices for the cube
-        #[rustfmt::skip]
-        let indices: &[u16] = &[
-            0,  1,  2,  2,  3,  0,  // front
-            4,  5,  6,  6,  7,  4,  // back
-            8,  9,  10, 10, 11, 8,  // top
-            12, 13, 14, 14, 15, 12, // bottom
-            16, 17, 18, 18, 19, 16, // right
-            20, 21, 22, 22, 23, 20, // left
-        ];
+        let depth_view = Self::create_depth_view(&device, size, anti_aliasing.sample_count());
+        let post_process = PostProcess::new(&device, surface_format, size.width, size.height);
+        let tone_mapper = ToneMapper::new(&device, surface_format, post_process.hdr_views(), size.width, size.height);
 
         use wgpu::util::DeviceExt;
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
 
         // Create uniform buffer
-        let uniform_data = Uniforms::new();
+        let uniform_data = CameraUniforms::new();
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
             contents: bytemuck::cast_slice(&[uniform_data]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        // Create bind group layout
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Uniform Bind Group Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::bytes_of(&LightUniforms::new(Vec3::ZERO, 0, &ShadowViews::default(), false)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
         // Create bind group
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Uniform Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-            ],
-        });
+        let light_clusters = LightClusters::new(&device, std::mem::size_of::<LightUniform>());
+        let shadow_maps = ShadowMaps::new(&device, &object_bind_group_layout, 1024, 512);
+        let ambient_occlusion = AmbientOcclusion::new(&device, size.width, size.height);
+        let gbuffer = deferred.then(|| GBuffer::new(&device, &queue, &bind_group_layout, size.width, size.height));
+        let uniform_bind_group = Self::create_uniform_bind_group(
+            &device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &light_buffer,
+            &light_clusters,
+            &shadow_maps,
+            &ambient_occlusion,
+        );
+
+        // Per-object slots must respect the device's dynamic offset alignment
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let object_stride = (std::mem::size_of::<ObjectUniforms>() as wgpu::BufferAddress)
+            .div_ceil(alignment) * alignment;
+        let object_capacity = 16;
+        let (object_buffer, object_bind_group) = Self::create_object_buffer(
+            &device,
+            &object_bind_group_layout,
+            object_stride,
+            object_capacity,
+        );
+
+        let fallback_texture = |texel: [u8; 4], label| {
+            let image = image::RgbaImage::from_pixel(1, 1, image::Rgba(texel));
+            let data = TextureData::from_image(image.into(), ColorSpace::Linear);
+            let texture = data.upload(&device, &queue, label);
+            Arc::new(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        };
+        let fallback_textures = FallbackTextures {
+            white: fallback_texture([255; 4], "White Texture"),
+            flat_normal: fallback_texture([128, 128, 255, 255], "Flat Normal Texture"),
+        };
+        let default_material_bind_group = Self::create_material_bind_group(
+            &device,
+            &queue,
+            &material_bind_group_layout,
+            &fallback_textures,
+            &mut TextureCache::default(),
+            &Material::default(),
+        );
 
         Self {
-            instance,
             adapter,
             device,
             queue,
-            surface,
-            window,
+            target,
+            size,
+            shader_module,
+            pipeline_layout: render_pipeline_layout,
             pipeline,
-            vertex_buffer,
-            index_buffer,
+            blend_pipeline,
+            prepass_pipeline,
+            gbuffer_pipeline,
+            depth_compare,
+            depth_view,
+            anti_aliasing,
+            msaa_view: None,
+            taa: None,
+            frame_index: 0,
+            previous_view_proj: None,
+            previous_world: HashMap::new(),
             uniform_buffer,
+            light_buffer,
+            light_clusters,
+            uniform_bind_group_layout: bind_group_layout,
             uniform_bind_group,
+            shadow_maps,
+            ambient_occlusion,
+            gbuffer,
+            tone_mapper,
+            post_process,
+            tone_mapping: ToneMapping::default(),
+            exposure: Exposure::default(),
+            object_bind_group_layout,
+            object_buffer,
+            object_bind_group,
+            object_capacity,
+            object_stride,
+            material_bind_group_layout,
+            fallback_textures,
+            textures: TextureCache::default(),
+            gpu_materials: Vec::new(),
+            default_material_bind_group,
             surface_format,
+            scene: Scene::new(),
             camera_position: Vec3::new(0.0, 0.0, 3.0),
-            cube_rotation: Vec3::ZERO,
+            ambient_light: Vec3::splat(0.1),
+            debug_cascades: false,
+            camera_yaw: 0.0,
+            camera_pitch: 0.0,
             start_time: Instant::now(),
+            last_frame: Instant::now(),
             keys_pressed: KeyboardState::default(),
         }
     }
 
-    pub fn run(mut self, event_loop: EventLoop<()>) {
-        let _ = event_loop.run(move |event, target| {
-            target.set_control_flow(ControlFlow::Poll);
+    /// Binds the camera, lights, light clusters, shadow maps and screen-space
+    /// occlusion shared by every draw.
+    fn create_uniform_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &Buffer,
+        light_buffer: &Buffer,
+        light_clusters: &LightClusters,
+        shadow_maps: &ShadowMaps,
+        ambient_occlusion: &AmbientOcclusion,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Uniform Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(shadow_maps.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(shadow_maps.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(shadow_maps.point_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(ambient_occlusion.occlusion_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: light_clusters.light_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: light_clusters.cluster_buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
 
-            match event {
-                Event::WindowEvent {
-                    window_id,
-                    event: WindowEvent::CloseRequested,
-                } if window_id == self.window.id() => {
-                    target.exit();
-                }
-                Event::WindowEvent {
+    /// Creates the main pass pipeline. Blended pipelines test depth but don't
+    /// write it, nor motion vectors under TAA.
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_module: &wgpu::ShaderModule,
+        depth_compare: wgpu::CompareFunction,
+        anti_aliasing: AntiAliasing,
+        blend: bool,
+    ) -> RenderPipeline {
+        let taa = anti_aliasing == AntiAliasing::Taa;
+        let color = Some(wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: blend.then_some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        let velocity = Some(wgpu::ColorTargetState {
+            format: VELOCITY_FORMAT,
+            blend: None,
+            write_mask: if blend { wgpu::ColorWrites::empty() } else { wgpu::ColorWrites::ALL },
+        });
+        let targets = if taa { vec![color, velocity] } else { vec![color] };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(if blend { "Blend Render Pipeline" } else { "Render Pipeline" }),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: if taa { "fs_main_taa" } else { "fs_main" },
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: !blend,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: anti_aliasing.sample_count(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Creates the depth-only pipeline of the SSAO prepass.
+    fn create_prepass_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_module: &wgpu::ShaderModule,
+        depth_compare: wgpu::CompareFunction,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Prepass Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: "fs_prepass",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Creates the pipeline that draws opaque meshes into the G-buffer, with
+    /// their ambient and emitted light in the HDR target and, under TAA,
+    /// motion vectors.
+    fn create_gbuffer_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_module: &wgpu::ShaderModule,
+        depth_compare: wgpu::CompareFunction,
+        anti_aliasing: AntiAliasing,
+    ) -> RenderPipeline {
+        let taa = anti_aliasing == AntiAliasing::Taa;
+        let mut targets: Vec<_> = [HDR_FORMAT, ALBEDO_FORMAT, NORMAL_FORMAT, MATERIAL_FORMAT]
+            .into_iter()
+            .map(|format| Some(format.into()))
+            .collect();
+        if taa {
+            targets.push(Some(VELOCITY_FORMAT.into()));
+        }
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("G-Buffer Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: if taa { "fs_gbuffer_taa" } else { "fs_gbuffer" },
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_object_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        stride: wgpu::BufferAddress,
+        capacity: usize,
+    ) -> (Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Object Uniform Buffer"),
+            size: stride * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Object Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<ObjectUniforms>() as u64),
+                    }),
+                },
+            ],
+        });
+
+        (buffer, bind_group)
+    }
+
+    /// Binds a material's textures, loading them through `textures`.
+    fn create_material_bind_group(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        fallback: &FallbackTextures,
+        textures: &mut TextureCache,
+        material: &Material,
+    ) -> wgpu::BindGroup {
+        let mut view = |source: &Option<_>, color_space, fallback: &Arc<wgpu::TextureView>| {
+            source.as_ref()
+                .and_then(|source| textures.get(device, queue, source, color_space))
+                .unwrap_or_else(|| fallback.clone())
+        };
+        let views = [
+            view(&material.base_color_texture, ColorSpace::Srgb, &fallback.white),
+            view(&material.metallic_roughness_texture, ColorSpace::Linear, &fallback.white),
+            view(&material.normal_texture, ColorSpace::Linear, &fallback.flat_normal),
+            view(&material.occlusion_texture, ColorSpace::Linear, &fallback.white),
+            view(&material.emissive_texture, ColorSpace::Srgb, &fallback.white),
+        ];
+        let sampler = material.sampler.create(device);
+
+        let mut entries: Vec<wgpu::BindGroupEntry> = views.iter()
+            .enumerate()
+            .map(|(binding, view)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: views.len() as u32,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Bind Group", material.name)),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Creates or refreshes the bind group of a material whose textures or
+    /// sampler changed since it was last drawn.
+    fn prepare_material(&mut self, handle: MaterialHandle) {
+        let Some(material) = self.scene.material(handle) else {
+            return;
+        };
+        if handle.0 >= self.gpu_materials.len() {
+            self.gpu_materials.resize_with(handle.0 + 1, || None);
+        }
+        let up_to_date = self.gpu_materials[handle.0].as_ref().is_some_and(|gpu| {
+            let bound = &gpu.material;
+            bound.base_color_texture == material.base_color_texture
+                && bound.metallic_roughness_texture == material.metallic_roughness_texture
+                && bound.normal_texture == material.normal_texture
+                && bound.occlusion_texture == material.occlusion_texture
+                && bound.emissive_texture == material.emissive_texture
+                && bound.sampler == material.sampler
+        });
+        if up_to_date {
+            return;
+        }
+
+        let bind_group = Self::create_material_bind_group(
+            &self.device,
+            &self.queue,
+            &self.material_bind_group_layout,
+            &self.fallback_textures,
+            &mut self.textures,
+            material,
+        );
+        self.gpu_materials[handle.0] = Some(GpuMaterial {
+            material: material.clone(),
+            bind_group,
+        });
+    }
+
+    fn create_depth_view(device: &wgpu::Device, size: PhysicalSize<u32>, sample_count: u32) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Creates the targets the current anti-aliasing draws the main pass
+    /// with: a depth buffer with matching samples, the multisampled color
+    /// target under MSAA, and motion vectors and history under TAA.
+    fn create_anti_aliasing_targets(&mut self) {
+        let sample_count = self.anti_aliasing.sample_count();
+        self.depth_view = Self::create_depth_view(&self.device, self.size, sample_count);
+        self.msaa_view = (sample_count > 1).then(|| {
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("MSAA Color Texture"),
+                size: wgpu::Extent3d {
+                    width: self.size.width,
+                    height: self.size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        self.taa = (self.anti_aliasing == AntiAliasing::Taa)
+            .then(|| TemporalResolve::new(&self.device, self.size.width, self.size.height));
+    }
+
+    pub fn run(mut self, event_loop: EventLoop<()>) {
+        let window = match &self.target {
+            RenderTarget::Window { window, .. } => window.clone(),
+            RenderTarget::Offscreen { .. } => panic!("Renderer::run requires a windowed renderer"),
+        };
+
+        let _ = event_loop.run(move |event, target| {
+            target.set_control_flow(ControlFlow::Poll);
+
+            match event {
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::CloseRequested,
+                } if window_id == window.id() => {
+                    target.exit();
+                }
+                Event::WindowEvent {
                     event: WindowEvent::Resized(physical_size),
                     window_id,
-                } if window_id == self.window.id() => {
+                } if window_id == window.id() => {
                     self.resize(physical_size);
                 }
                 Event::AboutToWait => {
-                    self.window.request_redraw();
+                    window.request_redraw();
                 }
                 Event::WindowEvent {
                     event: WindowEvent::RedrawRequested,
                     window_id,
-                } if window_id == self.window.id() => {
+                } if window_id == window.id() => {
                     self.update_and_render();
                 }
                 Event::WindowEvent {
                     event: WindowEvent::KeyboardInput { event, .. },
                     window_id,
-                } if window_id == self.window.id() => {
+                } if window_id == window.id() => {
                     self.handle_keyboard_input(event);
                 }
                 _ => {}
@@ -359,23 +1228,332 @@ impl Renderer {
         });
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    /// Current size of the render target in pixels.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Resizes the render target. Offscreen targets are recreated at the new size.
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width == 0 || new_size.height == 0 {
             return;
         }
-        
-        let surface_caps = self.surface.get_capabilities(&self.adapter);
-            
-        self.surface.configure(&self.device, &wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.surface_format,
-            width: new_size.width,
-            height: new_size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        });
+        self.size = new_size;
+        self.create_anti_aliasing_targets();
+        self.ambient_occlusion.resize(&self.device, new_size.width, new_size.height);
+        if let Some(gbuffer) = &mut self.gbuffer {
+            gbuffer.resize(&self.device, new_size.width, new_size.height);
+        }
+        self.rebind_uniforms();
+        self.post_process.resize(&self.device, new_size.width, new_size.height);
+        self.tone_mapper.set_inputs(&self.device, self.post_process.hdr_views(), new_size.width, new_size.height);
+
+        match &mut self.target {
+            RenderTarget::Window { surface, .. } => {
+                let surface_caps = surface.get_capabilities(&self.adapter);
+
+                surface.configure(&self.device, &wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: self.surface_format,
+                    width: new_size.width,
+                    height: new_size.height,
+                    present_mode: surface_caps.present_modes[0],
+                    alpha_mode: surface_caps.alpha_modes[0],
+                    view_formats: vec![],
+                    desired_maximum_frame_latency: 2,
+                });
+            }
+            RenderTarget::Offscreen { texture } => {
+                *texture = Self::create_offscreen_texture(&self.device, new_size);
+            }
+        }
+    }
+
+    /// Depth comparison used by the main pass.
+    pub fn depth_compare(&self) -> wgpu::CompareFunction {
+        self.depth_compare
+    }
+
+    /// Changes the depth comparison used by the main pass.
+    ///
+    /// The depth buffer is cleared to the far plane every frame, so
+    /// comparisons that only pass for fragments at or behind what is already
+    /// there (`Equal`, `Greater` and `GreaterEqual`) are rejected.
+    pub fn set_depth_compare(&mut self, compare: wgpu::CompareFunction) -> Result<(), DepthCompareError> {
+        use wgpu::CompareFunction::{Equal, Greater, GreaterEqual};
+        if matches!(compare, Equal | Greater | GreaterEqual) {
+            return Err(DepthCompareError::DrawsNothing(compare));
+        }
+        if compare != self.depth_compare {
+            self.depth_compare = compare;
+            self.create_pipelines();
+        }
+        Ok(())
+    }
+
+    /// Recreates the main pass pipelines after their depth comparison or
+    /// anti-aliasing changed.
+    fn create_pipelines(&mut self) {
+        self.pipeline = Self::create_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &self.shader_module,
+            self.depth_compare,
+            self.anti_aliasing,
+            false,
+        );
+        self.blend_pipeline = Self::create_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &self.shader_module,
+            self.depth_compare,
+            self.anti_aliasing,
+            true,
+        );
+        self.prepass_pipeline = Self::create_prepass_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &self.shader_module,
+            self.depth_compare,
+        );
+        if self.gbuffer_pipeline.is_some() {
+            self.gbuffer_pipeline = Some(Self::create_gbuffer_pipeline(
+                &self.device,
+                &self.pipeline_layout,
+                &self.shader_module,
+                self.depth_compare,
+                self.anti_aliasing,
+            ));
+        }
+    }
+
+    /// How opaque meshes are lit, as chosen when the renderer was created.
+    pub fn render_path(&self) -> RenderPath {
+        if self.gbuffer.is_some() {
+            RenderPath::Deferred
+        } else {
+            RenderPath::Forward
+        }
+    }
+
+    /// G-buffer channel shown in place of the lit frame, if any.
+    pub fn gbuffer_debug(&self) -> Option<GBufferChannel> {
+        self.gbuffer.as_ref().and_then(GBuffer::debug)
+    }
+
+    /// Shows one G-buffer channel instead of the lit frame, without tone
+    /// mapping or post-processing, or the lit frame again with `None`.
+    /// The forward path keeps no G-buffer, so there it does nothing.
+    pub fn set_gbuffer_debug(&mut self, channel: Option<GBufferChannel>) {
+        if let Some(gbuffer) = &mut self.gbuffer {
+            gbuffer.set_debug(&self.queue, channel);
+        }
+    }
+
+    /// Screen-space ambient occlusion settings, or `None` while it is off.
+    pub fn ssao(&self) -> Option<SsaoSettings> {
+        self.ambient_occlusion.settings()
+    }
+
+    /// Turns screen-space ambient occlusion on with the given settings, or
+    /// off with `None`. It darkens the ambient light in creases and under
+    /// objects, at the cost of a depth prepass over the opaque meshes.
+    pub fn set_ssao(&mut self, settings: Option<SsaoSettings>) {
+        self.ambient_occlusion.set_settings(settings);
+    }
+
+    /// How the renderer smooths jagged edges.
+    pub fn anti_aliasing(&self) -> AntiAliasing {
+        self.anti_aliasing
+    }
+
+    /// Switches to another kind of anti-aliasing from the next frame on.
+    /// Switching to TAA starts a new history, so it takes a few frames to
+    /// converge.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) -> Result<(), AntiAliasingError> {
+        if let AntiAliasing::Msaa(count) = anti_aliasing {
+            if self.gbuffer.is_some() {
+                return Err(AntiAliasingError::UnsupportedRenderPath { render_path: RenderPath::Deferred });
+            }
+            let supported = self.supported_sample_counts();
+            if !supported.contains(&count) {
+                return Err(AntiAliasingError::UnsupportedSampleCount { requested: count, supported });
+            }
+        }
+        if anti_aliasing == self.anti_aliasing {
+            return Ok(());
+        }
+        self.anti_aliasing = anti_aliasing;
+        self.create_pipelines();
+        self.create_anti_aliasing_targets();
+        self.post_process.set_fxaa(anti_aliasing == AntiAliasing::Fxaa);
+        Ok(())
+    }
+
+    /// Sample counts MSAA can use on this adapter, in increasing order.
+    pub fn supported_sample_counts(&self) -> Vec<u32> {
+        // Without the adapter-specific feature, the device only allows what
+        // every adapter guarantees
+        let features = |format: wgpu::TextureFormat| {
+            if self.device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                self.adapter.get_texture_format_features(format).flags
+            } else {
+                format.guaranteed_format_features(self.device.features()).flags
+            }
+        };
+        let (color, depth) = (features(HDR_FORMAT), features(DEPTH_FORMAT));
+        let resolvable = color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+        color.supported_sample_counts()
+            .into_iter()
+            .filter(|&count| depth.sample_count_supported(count) && (count == 1 || resolvable))
+            .collect()
+    }
+
+    /// Replaces the scene drawn by the renderer.
+    pub fn set_scene(&mut self, scene: Scene) {
+        self.scene = scene;
+        self.gpu_materials.clear();
+        self.textures.clear();
+    }
+
+    /// The scene drawn by the renderer.
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Mutable access to the scene drawn by the renderer.
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    /// Sets the camera position. The camera always looks at the origin, and
+    /// in the windowed loop orbits it from this position.
+    pub fn set_camera_position(&mut self, position: Vec3) {
+        self.camera_position = position;
+        let distance = position.length();
+        if distance > 0.0 {
+            self.camera_yaw = position.x.atan2(position.z);
+            self.camera_pitch = (position.y / distance).clamp(-1.0, 1.0).asin();
+        }
+    }
+
+    /// View volume of the camera, which frames are rendered and lights are
+    /// clustered with.
+    pub fn camera_frustum(&self) -> Frustum {
+        Frustum {
+            view: Mat4::look_at_rh(self.camera_position, Vec3::ZERO, Vec3::Y),
+            fov_y: 45.0_f32.to_radians(),
+            aspect_ratio: self.size.width as f32 / self.size.height as f32,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    /// Width and height, in texels, of each directional or spot light's shadow map.
+    pub fn shadow_map_size(&self) -> u32 {
+        self.shadow_maps.size()
+    }
+
+    /// Changes the resolution of the directional and spot light shadow maps;
+    /// larger maps give sharper shadows.
+    pub fn set_shadow_map_size(&mut self, size: u32) {
+        let size = size.clamp(1, self.device.limits().max_texture_dimension_2d);
+        if size == self.shadow_maps.size() {
+            return;
+        }
+        self.shadow_maps.resize(&self.device, size);
+        self.rebind_uniforms();
+    }
+
+    /// Width and height, in texels, of each face of a point light's shadow cube.
+    pub fn point_shadow_map_size(&self) -> u32 {
+        self.shadow_maps.point_size()
+    }
+
+    /// Changes the resolution of the point light shadow cube faces.
+    pub fn set_point_shadow_map_size(&mut self, size: u32) {
+        let size = size.clamp(1, self.device.limits().max_texture_dimension_2d);
+        if size == self.shadow_maps.point_size() {
+            return;
+        }
+        self.shadow_maps.resize_point(&self.device, size);
+        self.rebind_uniforms();
+    }
+
+    fn rebind_uniforms(&mut self) {
+        self.uniform_bind_group = Self::create_uniform_bind_group(
+            &self.device,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            &self.light_buffer,
+            &self.light_clusters,
+            &self.shadow_maps,
+            &self.ambient_occlusion,
+        );
+    }
+
+    /// Whether surfaces are tinted by shadow cascade.
+    pub fn shadow_cascade_debug(&self) -> bool {
+        self.debug_cascades
+    }
+
+    /// Tints surfaces red, green, blue and yellow by the cascade of the first
+    /// cascaded light they fall in, to help tune cascade splits.
+    pub fn set_shadow_cascade_debug(&mut self, enabled: bool) {
+        self.debug_cascades = enabled;
+    }
+
+    /// Light reaching every surface regardless of the scene's lights.
+    pub fn ambient_light(&self) -> Vec3 {
+        self.ambient_light
+    }
+
+    /// Sets the linear RGB light added to every surface.
+    pub fn set_ambient_light(&mut self, color: Vec3) {
+        self.ambient_light = color;
+    }
+
+    /// Curve that maps the HDR frame into display range.
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    /// Changes the curve that maps the HDR frame into display range.
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    /// How the frame is exposed before tone mapping.
+    pub fn exposure(&self) -> Exposure {
+        self.exposure
+    }
+
+    /// Changes how the frame is exposed before tone mapping. Switching to
+    /// auto exposure measures the next frame without easing into it.
+    pub fn set_exposure(&mut self, exposure: Exposure) {
+        self.exposure = exposure;
+    }
+
+    /// Adds an effect to the end of its stage's post-processing chain.
+    pub fn add_post_effect(&mut self, effect: impl PostEffect) -> PostEffectHandle {
+        self.post_process.add(Box::new(effect))
+    }
+
+    /// The post-processing effect with the given handle, if it is a `T`.
+    pub fn post_effect<T: PostEffect>(&self, handle: PostEffectHandle) -> Option<&T> {
+        self.post_process.get(handle)?.as_any().downcast_ref()
+    }
+
+    /// Mutable access to the post-processing effect with the given handle,
+    /// if it is a `T`.
+    pub fn post_effect_mut<T: PostEffect>(&mut self, handle: PostEffectHandle) -> Option<&mut T> {
+        self.post_process.get_mut(handle)?.as_any_mut().downcast_mut()
+    }
+
+    /// Takes a post-processing effect out of the chain. Its handle is not reused.
+    pub fn remove_post_effect(&mut self, handle: PostEffectHandle) -> Option<Box<dyn PostEffect>> {
+        let effect: Box<dyn AnyPostEffect> = self.post_process.remove(handle)?;
+        Some(effect)
     }
 
     fn handle_keyboard_input(&mut self, event: KeyEvent) {
@@ -392,112 +1570,561 @@ impl Renderer {
     }
 
     fn update_and_render(&mut self) {
-        // Update cube rotation based on keyboard input
+        // Orbit the camera around the origin based on keyboard input
         let rotation_speed = 2.0 * 0.016; // Assuming ~60 FPS
         
         if self.keys_pressed.w {
-            self.cube_rotation.x -= rotation_speed;
+            self.camera_pitch += rotation_speed;
         }
         if self.keys_pressed.s {
-            self.cube_rotation.x += rotation_speed;
+            self.camera_pitch -= rotation_speed;
         }
         if self.keys_pressed.a {
-            self.cube_rotation.y -= rotation_speed;
+            self.camera_yaw += rotation_speed;
         }
         if self.keys_pressed.d {
-            self.cube_rotation.y += rotation_speed;
+            self.camera_yaw -= rotation_speed;
         }
 
-        // Auto-rotate if no keys pressed
-        let elapsed = self.start_time.elapsed().as_secs_f32();
+        // Auto-orbit if no keys pressed, carrying on from wherever the camera is
+        let now = Instant::now();
+        // A long stall, such as setting up the scene, doesn't jump the orbit
+        let elapsed = now.duration_since(self.last_frame).as_secs_f32().min(0.1);
+        self.last_frame = now;
         if !self.keys_pressed.w && !self.keys_pressed.a
            && !self.keys_pressed.s && !self.keys_pressed.d {
-            self.cube_rotation.y = elapsed;
+            self.camera_yaw -= elapsed;
         }
 
-        // Create transformation matrices
-        let aspect_ratio = self.window.inner_size().width as f32
-                         / self.window.inner_size().height as f32;
-        
-        // Model matrix (cube transformation)
-        let model = Mat4::from_rotation_x(self.cube_rotation.x)
-                  * Mat4::from_rotation_y(self.cube_rotation.y)
-                  * Mat4::from_rotation_z(self.cube_rotation.z);
-        
-        // View matrix (camera)
-        let view = Mat4::look_at_rh(
-            self.camera_position,
-            Vec3::ZERO,
-            Vec3::Y,
-        );
-        
-        // Projection matrix
-        let projection = Mat4::perspective_rh_gl(
-            45.0_f32.to_radians(),
-            aspect_ratio,
-            0.1,
-            100.0,
-        );
-        
-        // Combine into MVP matrix
-        let mvp = projection * view * model;
+        // Keep away from the poles, where the look-at up vector degenerates
+        self.camera_pitch = self.camera_pitch.clamp(-1.5, 1.5);
+        let orbit = Quat::from_rotation_y(self.camera_yaw) * Quat::from_rotation_x(-self.camera_pitch);
+        self.camera_position = orbit * Vec3::new(0.0, 0.0, self.camera_position.length());
+
+        self.render();
+    }
+
+    /// World-space bounding sphere of the scene's uploaded meshes, as its
+    /// center and radius.
+    fn mesh_bounds(&self, world_matrices: &[(ObjectHandle, Mat4)]) -> Option<(Vec3, f32)> {
+        let mut bounds: Option<(Vec3, Vec3)> = None;
+        for &(handle, world) in world_matrices {
+            let Some(gpu) = self.scene.component::<MeshHandle>(handle)
+                .and_then(|&mesh| self.scene.mesh(mesh))
+                .and_then(Mesh::gpu) else {
+                continue;
+            };
+            let (min, max) = gpu.bounds;
+            for corner in 0..8 {
+                let mask = glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+                let point = world.transform_point3(Vec3::select(mask, max, min));
+                bounds = Some(match bounds {
+                    Some((low, high)) => (low.min(point), high.max(point)),
+                    None => (point, point),
+                });
+            }
+        }
+        bounds.map(|(min, max)| ((min + max) * 0.5, (max - min).length() * 0.5))
+    }
+
+    /// Writes the camera, light and per-object uniforms. Returns the meshes to
+    /// draw, opaque ones first and then blended ones from back to front, the
+    /// shadow maps to render, and on the deferred path, the lights to add in
+    /// the lighting pass.
+    fn update_uniforms(&mut self) -> (Vec<Draw>, ShadowViews, Vec<LightVolume>) {
+        // Projection matrix (wgpu clip space has a 0..1 depth range)
+        let frustum = self.camera_frustum();
+        let projection = Mat4::perspective_rh(frustum.fov_y, frustum.aspect_ratio, frustum.near, frustum.far);
+        let view_proj = projection * frustum.view;
+
+        // TAA shifts the whole frame by a subpixel offset; translating in
+        // clip space scales the shift by w, so it stays the same in NDC
+        let jitter = if self.taa.is_some() {
+            let offset = antialias::jitter(self.frame_index) * 2.0
+                / glam::Vec2::new(self.size.width as f32, self.size.height as f32);
+            Mat4::from_translation(offset.extend(0.0))
+        } else {
+            Mat4::IDENTITY
+        };
+        self.ambient_occlusion.update(&self.queue, jitter * projection);
         
-        // Update uniform buffer
-        let mut uniforms = Uniforms::new();
-        uniforms.update_mvp(mvp);
+        // Update camera uniform buffer
+        let mut uniforms = CameraUniforms::new();
+        uniforms.update_view_proj(jitter * view_proj);
+        uniforms.update_motion(view_proj, self.previous_view_proj.unwrap_or(view_proj));
+        self.previous_view_proj = Some(view_proj);
+        uniforms.update_position(self.camera_position);
+        uniforms.update_forward(-self.camera_position.normalize_or_zero());
+        uniforms.update_viewport(self.size, frustum.near, frustum.far);
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
             bytemuck::cast_slice(&[uniforms]),
         );
 
-        self.render();
+        // Gather the scene's lights, falling back to a sun from above the
+        // default camera so scenes without lights stay visible
+        let world_matrices = self.scene.world_matrices();
+        let mut lights: Vec<(Light, Mat4)> = world_matrices.iter()
+            .filter_map(|&(handle, world)| Some((*self.scene.component::<Light>(handle)?, world)))
+            .collect();
+        if lights.is_empty() {
+            // An illuminance of pi lights a white surface facing the sun to full white
+            let direction = Vec3::new(-0.4, -1.0, -0.6).normalize();
+            let sun = Mat4::from_quat(Quat::from_rotation_arc(Vec3::NEG_Z, direction));
+            lights.push((Light::new(LightKind::Directional, std::f32::consts::PI), sun));
+        }
+        lights.truncate(MAX_LIGHTS);
+
+        // Hand out shadow maps in light order. Directional lights without
+        // cascades cover every mesh; cascades take one map each
+        let bounds = self.mesh_bounds(&world_matrices);
+        let mut shadow_views = ShadowViews::default();
+        let mut light_data = Vec::with_capacity(lights.len());
+        let mut light_volumes = Vec::new();
+        for (light, world) in &lights {
+            let mut uniform = LightUniform::new(light, *world);
+            if let (Some(shadows), Some((center, radius))) = (&light.shadows, bounds) {
+                let position = Vec3::from(uniform.position);
+                let direction = Vec3::from(uniform.direction);
+                let point_slot = shadow_views.point_faces.len() / 6;
+                if light.kind == LightKind::Point && point_slot < MAX_POINT_SHADOWS {
+                    let far = light.range.unwrap_or(position.distance(center) + radius);
+                    uniform.set_point_shadow(point_slot, shadows, far);
+                    let faces = shadow::point_face_view_projs(position, far);
+                    shadow_views.point_faces.extend(faces.map(|view_proj| PointFace { view_proj, position, far }));
+                }
+
+                let maps = match (light.kind, shadows.cascades) {
+                    (LightKind::Directional, Some(cascades)) => {
+                        let splits = shadow::cascade_splits(&frustum, &cascades);
+                        let view_projs = splits.windows(2)
+                            .map(|split| {
+                                shadow::cascade_view_proj(
+                                    &frustum,
+                                    split[0],
+                                    split[1],
+                                    direction,
+                                    (center, radius),
+                                    self.shadow_maps.size(),
+                                    cascades.stabilize,
+                                )
+                            })
+                            .collect();
+                        Some((view_projs, splits[1..].to_vec(), cascades.blend))
+                    }
+                    (LightKind::Directional, None) => {
+                        let view_proj = shadow::directional_view_proj(direction, center, radius);
+                        Some((vec![view_proj], vec![f32::MAX], 0.0))
+                    }
+                    (LightKind::Spot { outer_cone_angle, .. }, _) => {
+                        let far = light.range.unwrap_or(position.distance(center) + radius);
+                        let view_proj = shadow::spot_view_proj(position, direction, outer_cone_angle, far);
+                        Some((vec![view_proj], vec![f32::MAX], 0.0))
+                    }
+                    // Point lights use cube faces instead
+                    (LightKind::Point, _) => None,
+                };
+                let first = shadow_views.maps.len();
+                let maps = maps.filter(|(view_projs, ..)| first + view_projs.len() <= MAX_SHADOW_MAPS);
+                if let Some((view_projs, cascade_ends, blend)) = maps {
+                    uniform.set_shadow(first, shadows, &cascade_ends, blend);
+                    shadow_views.maps.extend(view_projs);
+                }
+            }
+
+            // The deferred path lights each pixel its light volume covers
+            if self.gbuffer.is_some() {
+                let (width, height) = (self.size.width, self.size.height);
+                let scissor = match deferred::light_radius(light) {
+                    Some(radius) => deferred::scissor_rect(view_proj, Vec3::from(uniform.position), radius, width, height),
+                    None => Some([0, 0, width, height]),
+                };
+                if let Some(scissor) = scissor {
+                    light_volumes.push(LightVolume { light: light_data.len(), scissor });
+                }
+            }
+            light_data.push(uniform);
+        }
+        let light_uniforms = LightUniforms::new(self.ambient_light, light_data.len(), &shadow_views, self.debug_cascades);
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&light_uniforms));
+        self.light_clusters.update(&self.queue, &frustum, &light_data);
+
+        // Grow the per-object buffer if the scene outgrew it
+        let object_count = world_matrices.len();
+        if object_count > self.object_capacity {
+            self.object_capacity = object_count.next_power_of_two();
+            let (buffer, bind_group) = Self::create_object_buffer(
+                &self.device,
+                &self.object_bind_group_layout,
+                self.object_stride,
+                self.object_capacity,
+            );
+            self.object_buffer = buffer;
+            self.object_bind_group = bind_group;
+        }
+
+        // Upload every object's world matrix and material into its own aligned slot
+        let default_material = Material::default();
+        let stride = self.object_stride as usize;
+        let mut object_data = vec![0u8; object_count * stride];
+        let mut draws = Vec::new();
+        for (index, (slot, &(handle, world))) in object_data.chunks_exact_mut(stride).zip(&world_matrices).enumerate() {
+            let material = self.scene.component::<MaterialHandle>(handle)
+                .and_then(|&material| self.scene.material(material))
+                .unwrap_or(&default_material);
+            let flags = self.scene.component::<ShadowFlags>(handle).copied().unwrap_or_default();
+            let previous = self.previous_world.get(&handle).copied().unwrap_or(world);
+            let uniforms = ObjectUniforms::new(world, previous, material, flags.receive);
+            slot[..std::mem::size_of::<ObjectUniforms>()].copy_from_slice(bytemuck::bytes_of(&uniforms));
+
+            if let Some(&mesh) = self.scene.component::<MeshHandle>(handle) {
+                draws.push(Draw {
+                    slot: index,
+                    mesh,
+                    material: self.scene.component::<MaterialHandle>(handle).copied(),
+                    blend: material.alpha_mode == AlphaMode::Blend,
+                    cast_shadows: flags.cast,
+                    distance: world.w_axis.truncate().distance_squared(self.camera_position),
+                });
+            }
+        }
+        if !object_data.is_empty() {
+            self.queue.write_buffer(&self.object_buffer, 0, &object_data);
+        }
+        self.previous_world = world_matrices.into_iter().collect();
+
+        // Opaque meshes keep their scene order
+        draws.sort_by(|a, b| {
+            let by_distance = if a.blend { b.distance.total_cmp(&a.distance) } else { std::cmp::Ordering::Equal };
+            a.blend.cmp(&b.blend).then(by_distance)
+        });
+        (draws, shadow_views, light_volumes)
     }
 
-    fn render(&mut self) {
-        let frame = match self.surface.get_current_texture() {
-            Ok(frame) => frame,
-            Err(_) => {
-                self.resize(self.window.inner_size());
-                return;
+    /// Renders one frame into the current target.
+    ///
+    /// Windowed renderers present the frame; offscreen renderers keep it in
+    /// their texture until [`Renderer::read_pixels`] is called.
+    pub fn render(&mut self) {
+        // Meshes go first, as their bounds place the shadow maps
+        self.scene.upload_meshes(&self.device);
+        let (draws, shadow_views, light_volumes) = self.update_uniforms();
+        for draw in &draws {
+            if let Some(material) = draw.material {
+                self.prepare_material(material);
+            }
+        }
+
+        let (frame, view) = match &self.target {
+            RenderTarget::Window { surface, .. } => {
+                let frame = match surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    Err(_) => {
+                        self.resize(self.size);
+                        return;
+                    }
+                };
+                let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), view)
+            }
+            RenderTarget::Offscreen { texture } => {
+                (None, texture.create_view(&wgpu::TextureViewDescriptor::default()))
             }
         };
-        
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+        self.light_clusters.assign(&mut encoder);
+
+        if !shadow_views.maps.is_empty() || !shadow_views.point_faces.is_empty() {
+            let casters: Vec<Caster> = draws.iter()
+                .filter(|draw| draw.cast_shadows)
+                .filter_map(|draw| {
+                    Some(Caster {
+                        offset: (draw.slot as wgpu::BufferAddress * self.object_stride) as wgpu::DynamicOffset,
+                        mesh: self.scene.mesh(draw.mesh).and_then(Mesh::gpu)?,
+                    })
+                })
+                .collect();
+            self.shadow_maps.render(&mut encoder, &self.queue, &shadow_views, &self.object_bind_group, &casters);
+        }
+
+        if self.ambient_occlusion.settings().is_some() {
+            self.render_prepass(&mut encoder, &draws);
+        }
+        self.ambient_occlusion.render(&mut encoder);
+
+        if let Some(taa) = &self.taa {
+            taa.clear_velocity(&mut encoder);
+        }
+
+        let hdr_view = self.post_process.hdr_views()[0];
+        if let Some(gbuffer) = &self.gbuffer {
+            self.render_gbuffer(&mut encoder, &draws);
+            gbuffer.render(&mut encoder, &self.uniform_bind_group, hdr_view, &light_volumes);
+            // Blended meshes go over the lit frame with the forward shader
+            let blended = &draws[draws.partition_point(|draw| !draw.blend)..];
+            if gbuffer.debug().is_none() && !blended.is_empty() {
+                self.render_forward(&mut encoder, blended, gbuffer.depth_view(), false);
+            }
+        } else {
+            self.render_forward(&mut encoder, &draws, &self.depth_view, true);
+        }
+
+        // TAA hands the HDR stage its resolved frame in the second target
+        let mut hdr = 0;
+        if let Some(taa) = &mut self.taa {
+            let [input, output] = self.post_process.hdr_views();
+            taa.resolve(&self.device, &self.queue, &mut encoder, input, output);
+            hdr = 1;
+        }
+
+        let time = self.start_time.elapsed().as_secs_f32();
+        if self.gbuffer_debug().is_some() {
+            // G-buffer channels are shown as they are
+            let exposure = Exposure::Manual { stops: 0.0 };
+            self.tone_mapper.render(&mut encoder, &self.queue, hdr, &view, ToneMapping::Clamp, &exposure);
+        } else {
+            let hdr = self.post_process.run_hdr(&self.device, &self.queue, &mut encoder, hdr, time);
+            if self.post_process.has_display_effects() {
+                let display = self.post_process.display_input(&self.device);
+                self.tone_mapper.render(&mut encoder, &self.queue, hdr, display, self.tone_mapping, &self.exposure);
+                self.post_process.run_display(&self.device, &self.queue, &mut encoder, &view, time);
+            } else {
+                self.tone_mapper.render(&mut encoder, &self.queue, hdr, &view, self.tone_mapping, &self.exposure);
+            }
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(frame) = frame {
+            frame.present();
+        }
+        self.frame_index = self.frame_index.wrapping_add(1);
+    }
+
+    /// Draws `draws` into the HDR target, shaded with every light, testing
+    /// against `depth_view`. Both are cleared first when `clear` is set;
+    /// otherwise the meshes go over what is already there.
+    fn render_forward(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &[Draw],
+        depth_view: &wgpu::TextureView,
+        clear: bool,
+    ) {
+        // Under MSAA the samples are resolved into the HDR target, and
+        // only the resolved colors are kept
+        let hdr_view = self.post_process.hdr_views()[0];
+        let (color_view, resolve_target, store) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(hdr_view), wgpu::StoreOp::Discard),
+            None => (hdr_view, None, wgpu::StoreOp::Store),
+        };
+        let mut color_attachments = vec![Some(wgpu::RenderPassColorAttachment {
+            view: color_view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load: if clear { wgpu::LoadOp::Clear(CLEAR_COLOR) } else { wgpu::LoadOp::Load },
+                store,
+            },
+        })];
+        if let Some(taa) = &self.taa {
+            color_attachments.push(Some(wgpu::RenderPassColorAttachment {
+                view: taa.velocity_view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            }));
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear { wgpu::LoadOp::Clear(CLEAR_DEPTH) } else { wgpu::LoadOp::Load },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+
+        let mut blending = false;
+        for draw in draws {
+            if draw.blend && !blending {
+                render_pass.set_pipeline(&self.blend_pipeline);
+                blending = true;
+            }
+            self.draw_mesh(&mut render_pass, draw);
+        }
+    }
 
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..36, 0, 0..1);
+    /// Draws the opaque meshes into the G-buffer, and their ambient and
+    /// emitted light into the HDR target.
+    fn render_gbuffer(&self, encoder: &mut wgpu::CommandEncoder, draws: &[Draw]) {
+        let (Some(gbuffer), Some(pipeline)) = (&self.gbuffer, &self.gbuffer_pipeline) else {
+            return;
+        };
+        let target = |view, load| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        gbuffer.clear(encoder);
+        // The HDR target goes first, as only a pass's first target clears
+        // reliably
+        let mut color_attachments = vec![target(self.post_process.hdr_views()[0], wgpu::LoadOp::Clear(CLEAR_COLOR))];
+        color_attachments.extend(gbuffer.views().map(|view| target(view, wgpu::LoadOp::Load)));
+        if let Some(taa) = &self.taa {
+            color_attachments.push(target(taa.velocity_view(), wgpu::LoadOp::Load));
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: gbuffer.depth_view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(CLEAR_DEPTH),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        for draw in draws.iter().filter(|draw| !draw.blend) {
+            self.draw_mesh(&mut render_pass, draw);
+        }
+    }
+
+    /// Draws the depth of opaque meshes for SSAO.
+    fn render_prepass(&self, encoder: &mut wgpu::CommandEncoder, draws: &[Draw]) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.ambient_occlusion.depth_view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(CLEAR_DEPTH),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.prepass_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        for draw in draws.iter().filter(|draw| !draw.blend) {
+            self.draw_mesh(&mut render_pass, draw);
         }
+    }
 
+    /// Binds a draw's object slot, material and mesh, and draws it.
+    fn draw_mesh<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, draw: &Draw) {
+        let Some(gpu) = self.scene.mesh(draw.mesh).and_then(Mesh::gpu) else {
+            return;
+        };
+        let offset = (draw.slot as wgpu::BufferAddress * self.object_stride) as wgpu::DynamicOffset;
+        render_pass.set_bind_group(1, &self.object_bind_group, &[offset]);
+        let material = draw.material
+            .and_then(|material| self.gpu_materials.get(material.0)?.as_ref())
+            .map_or(&self.default_material_bind_group, |gpu| &gpu.bind_group);
+        render_pass.set_bind_group(2, material, &[]);
+        render_pass.set_vertex_buffer(0, gpu.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(gpu.index_buffer.slice(..), gpu.index_format);
+        render_pass.draw_indexed(0..gpu.index_count, 0, 0..1);
+    }
+
+    /// Reads the offscreen target back as tightly packed RGBA8 rows (sRGB encoded).
+    ///
+    /// Returns `None` for windowed renderers, whose frames are not kept around.
+    pub fn read_pixels(&self) -> Option<Vec<u8>> {
+        let texture = match &self.target {
+            RenderTarget::Offscreen { texture } => texture,
+            RenderTarget::Window { .. } => return None,
+        };
+
+        let width = self.size.width;
+        let height = self.size.height;
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
         self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
+
+        // Wait for the copy to finish and map the buffer
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+
+        // Strip the row padding required by the copy
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(data);
+        readback_buffer.unmap();
+
+        Some(pixels)
+    }
+
+    /// Reads back the lights the last frame assigned to each light cluster,
+    /// in the layout of [`cluster::assign_lights`], as indices into the
+    /// frame's lights in scene order.
+    ///
+    /// [`cluster::assign_lights`]: crate::cluster::assign_lights
+    pub fn read_light_clusters(&self) -> Option<Vec<Vec<u32>>> {
+        self.light_clusters.read(&self.device, &self.queue)
     }
-}
\ No newline at end of file
+}
@@ -1,48 +1,574 @@
 // Scene module for IntSar-3D
 
+use crate::camera::Camera;
+use crate::component::{Component, Components, PendingComponents, Query, QueryMut};
+use crate::light::Light;
+use crate::material::{Material, MaterialHandle};
 use crate::math::Transform;
+use crate::mesh::{Mesh, MeshHandle};
+use crate::shadow::ShadowFlags;
+use glam::Mat4;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Handle to an object stored in a [`Scene`].
+///
+/// Handles stay valid until their object is removed. The slot of a removed
+/// object is reused with a new generation, so stale handles never resolve to
+/// the object that replaced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectHandle {
+    pub(crate) index: u32,
+    pub(crate) generation: u32,
+}
+
+impl fmt::Display for ObjectHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// Which transform [`Scene::set_parent`] preserves when an object moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepTransform {
+    /// The object stays where it is in the world; its local transform is
+    /// recomputed relative to the new parent.
+    World,
+    /// The local transform is kept, so the object moves along with its new parent.
+    Local,
+}
+
+/// Error produced when changing the hierarchy of a scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HierarchyError {
+    /// A handle doesn't refer to an object of the scene.
+    InvalidHandle(ObjectHandle),
+    /// The new parent is the object itself or one of its descendants.
+    Cycle { child: ObjectHandle, parent: ObjectHandle },
+}
+
+impl fmt::Display for HierarchyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HierarchyError::InvalidHandle(handle) => write!(f, "no object with handle {handle}"),
+            HierarchyError::Cycle { child, parent } => {
+                write!(f, "object {child} can't be parented to its descendant {parent}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HierarchyError {}
 
 /// Represents an object within the 3D scene.
-#[derive(Debug, Clone)]
+///
+/// Everything beyond the name, transform and place in the hierarchy is a
+/// [`Component`] stored by the scene, such as a [`MeshHandle`], a
+/// [`MaterialHandle`], a [`Camera`] or a [`Light`].
+#[derive(Debug)]
 pub struct SceneObject {
-    pub name: String,
+    name: String,
+    /// Transform relative to the parent, or to the world for root objects.
     pub transform: Transform,
-    // TODO: Add mesh, material, etc.
+    parent: Option<ObjectHandle>,
+    children: Vec<ObjectHandle>,
+    components: PendingComponents,
 }
 
 impl SceneObject {
     /// Creates a new scene object with a given name and transform.
     pub fn new(name: String, transform: Transform) -> Self {
-        Self { name, transform }
+        Self {
+            name,
+            transform,
+            parent: None,
+            children: Vec::new(),
+            components: PendingComponents::default(),
+        }
+    }
+
+    /// Name of the object. Use [`Scene::rename`] to change it.
+    pub fn name(&self) -> &str {
+        &self.name
     }
+
+    /// Makes the object a child of `parent` once it is added to a scene.
+    pub fn with_parent(mut self, parent: ObjectHandle) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Parent of the object, or `None` for root objects.
+    pub fn parent(&self) -> Option<ObjectHandle> {
+        self.parent
+    }
+
+    /// Children of the object, in the order they were attached.
+    pub fn children(&self) -> &[ObjectHandle] {
+        &self.children
+    }
+
+    /// Attaches a component, stored once the object is added to a scene.
+    /// A later component of the same type replaces an earlier one.
+    pub fn with_component<T: Component>(mut self, component: T) -> Self {
+        self.components.push(component);
+        self
+    }
+
+    /// Attaches a mesh to the object.
+    pub fn with_mesh(self, mesh: MeshHandle) -> Self {
+        self.with_component(mesh)
+    }
+
+    /// Attaches a material to the object.
+    pub fn with_material(self, material: MaterialHandle) -> Self {
+        self.with_component(material)
+    }
+
+    /// Attaches a camera to the object.
+    pub fn with_camera(self, camera: Camera) -> Self {
+        self.with_component(camera)
+    }
+
+    /// Attaches a light to the object.
+    pub fn with_light(self, light: Light) -> Self {
+        self.with_component(light)
+    }
+
+    /// Sets whether the object's mesh casts and receives shadows.
+    pub fn with_shadow_flags(self, flags: ShadowFlags) -> Self {
+        self.with_component(flags)
+    }
+}
+
+/// Storage for one object; empty slots wait on the free list for reuse.
+#[derive(Debug)]
+struct ObjectSlot {
+    generation: u32,
+    object: Option<SceneObject>,
 }
 
 /// Represents the entire 3D scene.
+///
+/// Objects form a hierarchy: each object's transform is relative to its
+/// parent. Use [`Scene::set_parent`] to change it so children stay in sync.
 #[derive(Debug, Default)]
 pub struct Scene {
-    pub objects: Vec<SceneObject>,
+    objects: Vec<ObjectSlot>,
+    free_slots: Vec<u32>,
+    /// Handles of the objects with each name, in the order they got it.
+    names: HashMap<String, Vec<ObjectHandle>>,
+    object_count: usize,
+    components: Components,
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
 }
 
 impl Scene {
     /// Creates a new, empty scene.
     pub fn new() -> Self {
-        Self {
-            objects: Vec::new(),
+        Self::default()
+    }
+
+    /// Adds a mesh to the scene and returns a handle objects can refer to.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshHandle {
+        self.meshes.push(mesh);
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    /// Gets a mesh by handle.
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Mesh> {
+        self.meshes.get(handle.0)
+    }
+
+    /// Gets a mutable reference to a mesh by handle.
+    pub fn mesh_mut(&mut self, handle: MeshHandle) -> Option<&mut Mesh> {
+        self.meshes.get_mut(handle.0)
+    }
+
+    /// All meshes of the scene, in handle order.
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    /// Adds a material to the scene and returns a handle objects can refer to.
+    pub fn add_material(&mut self, material: Material) -> MaterialHandle {
+        self.materials.push(material);
+        MaterialHandle(self.materials.len() - 1)
+    }
+
+    /// Gets a material by handle.
+    pub fn material(&self, handle: MaterialHandle) -> Option<&Material> {
+        self.materials.get(handle.0)
+    }
+
+    /// Gets a mutable reference to a material by handle.
+    pub fn material_mut(&mut self, handle: MaterialHandle) -> Option<&mut Material> {
+        self.materials.get_mut(handle.0)
+    }
+
+    /// All materials of the scene, in handle order.
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    /// Uploads the meshes referenced by objects that aren't on the GPU yet.
+    pub(crate) fn upload_meshes(&mut self, device: &wgpu::Device) {
+        for (_, handle) in self.components.iter::<MeshHandle>() {
+            if let Some(mesh) = self.meshes.get_mut(handle.0) {
+                mesh.upload(device);
+            }
         }
     }
 
-    /// Adds an object to the scene.
-    pub fn add_object(&mut self, object: SceneObject) {
-        self.objects.push(object);
+    /// Adds an object to the scene and returns a handle to it.
+    ///
+    /// If the object was built with [`SceneObject::with_parent`], it becomes
+    /// the last child of that parent. Panics if the parent doesn't exist.
+    pub fn add_object(&mut self, mut object: SceneObject) -> ObjectHandle {
+        if let Some(parent) = object.parent {
+            if self.object(parent).is_none() {
+                panic!("parent object {parent} of `{}` doesn't exist", object.name);
+            }
+        }
+
+        let handle = match self.free_slots.pop() {
+            Some(index) => ObjectHandle {
+                index,
+                generation: self.objects[index as usize].generation,
+            },
+            None => {
+                self.objects.push(ObjectSlot {
+                    generation: 0,
+                    object: None,
+                });
+                ObjectHandle {
+                    index: (self.objects.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        };
+
+        if let Some(parent) = object.parent {
+            self.expect_object_mut(parent).children.push(handle);
+        }
+        object.children.clear();
+        std::mem::take(&mut object.components).insert_into(&mut self.components, handle);
+        self.names.entry(object.name.clone()).or_default().push(handle);
+        self.objects[handle.index as usize].object = Some(object);
+        self.object_count += 1;
+        handle
     }
 
-    /// Gets a mutable reference to an object by name.
-    pub fn get_object_mut(&mut self, name: &str) -> Option<&mut SceneObject> {
-        self.objects.iter_mut().find(|obj| obj.name == name)
+    /// Removes an object together with all of its descendants and their
+    /// components, returning the object.
+    ///
+    /// The returned object is detached: it has no parent or children, so it
+    /// can be added again as a root object.
+    ///
+    /// Handles to the removed objects stop resolving; all other handles stay valid.
+    pub fn remove_object(&mut self, handle: ObjectHandle) -> Option<SceneObject> {
+        let parent = self.object(handle)?.parent;
+        if let Some(parent) = parent {
+            self.expect_object_mut(parent).children.retain(|&child| child != handle);
+        }
+
+        let removed: Vec<ObjectHandle> = std::iter::once(handle).chain(self.descendants(handle)).collect();
+        let mut root = None;
+        for removed_handle in removed {
+            let slot = &mut self.objects[removed_handle.index as usize];
+            let object = slot.object.take().expect("descendant of a live object is live");
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free_slots.push(removed_handle.index);
+            self.object_count -= 1;
+            self.components.remove_object(removed_handle);
+
+            if let Some(handles) = self.names.get_mut(&object.name) {
+                handles.retain(|&named| named != removed_handle);
+                if handles.is_empty() {
+                    self.names.remove(&object.name);
+                }
+            }
+            if removed_handle == handle {
+                root = Some(SceneObject {
+                    parent: None,
+                    children: Vec::new(),
+                    ..object
+                });
+            }
+        }
+        root
+    }
+
+    /// Returns `true` if the handle refers to an object of the scene.
+    pub fn contains(&self, handle: ObjectHandle) -> bool {
+        self.object(handle).is_some()
+    }
+
+    /// Number of objects in the scene.
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    /// Gets an object by handle.
+    pub fn object(&self, handle: ObjectHandle) -> Option<&SceneObject> {
+        self.objects
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.object.as_ref())
+    }
+
+    /// Gets a mutable reference to an object by handle.
+    pub fn object_mut(&mut self, handle: ObjectHandle) -> Option<&mut SceneObject> {
+        self.objects
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.object.as_mut())
+    }
+
+    /// Object of a handle that is known to be valid.
+    fn expect_object(&self, handle: ObjectHandle) -> &SceneObject {
+        self.object(handle).expect("stale object handle in scene hierarchy")
+    }
+
+    fn expect_object_mut(&mut self, handle: ObjectHandle) -> &mut SceneObject {
+        self.object_mut(handle).expect("stale object handle in scene hierarchy")
     }
 
-    /// Gets an immutable reference to an object by name.
+    /// Iterates over all objects with their handles, in storage order.
+    pub fn objects(&self) -> impl Iterator<Item = (ObjectHandle, &SceneObject)> + '_ {
+        self.objects.iter().enumerate().filter_map(|(index, slot)| {
+            let handle = ObjectHandle {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.object.as_ref().map(|object| (handle, object))
+        })
+    }
+
+    /// Iterates mutably over all objects with their handles, in storage order.
+    pub fn objects_mut(&mut self) -> impl Iterator<Item = (ObjectHandle, &mut SceneObject)> + '_ {
+        self.objects.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let handle = ObjectHandle {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.object.as_mut().map(|object| (handle, object))
+        })
+    }
+
+    /// Changes the name of an object, keeping the name index up to date.
+    pub fn rename(&mut self, handle: ObjectHandle, name: String) -> Result<(), HierarchyError> {
+        let object = self.object_mut(handle).ok_or(HierarchyError::InvalidHandle(handle))?;
+        let old_name = std::mem::replace(&mut object.name, name.clone());
+
+        if let Some(handles) = self.names.get_mut(&old_name) {
+            handles.retain(|&named| named != handle);
+            if handles.is_empty() {
+                self.names.remove(&old_name);
+            }
+        }
+        self.names.entry(name).or_default().push(handle);
+        Ok(())
+    }
+
+    /// Handles of every object with the given name, in the order they got it.
+    pub fn objects_named(&self, name: &str) -> &[ObjectHandle] {
+        self.names.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Finds the object with the given name. Returns `None` if there is no
+    /// such object or several objects share the name.
+    pub fn find_object(&self, name: &str) -> Option<ObjectHandle> {
+        match self.objects_named(name) {
+            [handle] => Some(*handle),
+            _ => None,
+        }
+    }
+
+    /// Gets an immutable reference to the only object with the given name.
     pub fn get_object(&self, name: &str) -> Option<&SceneObject> {
-        self.objects.iter().find(|obj| obj.name == name)
+        self.find_object(name).and_then(|handle| self.object(handle))
     }
-}
\ No newline at end of file
+
+    /// Gets a mutable reference to the only object with the given name.
+    pub fn get_object_mut(&mut self, name: &str) -> Option<&mut SceneObject> {
+        self.find_object(name).and_then(|handle| self.object_mut(handle))
+    }
+
+    /// Attaches a component to an object, returning the component of the same
+    /// type it replaces. Returns the component back if the handle is stale.
+    pub fn insert_component<T: Component>(&mut self, handle: ObjectHandle, component: T) -> Result<Option<T>, T> {
+        if !self.contains(handle) {
+            return Err(component);
+        }
+        Ok(self.components.insert(handle, component))
+    }
+
+    /// Detaches a component from an object and returns it.
+    pub fn remove_component<T: Component>(&mut self, handle: ObjectHandle) -> Option<T> {
+        self.components.remove(handle)
+    }
+
+    /// Gets an object's component of type `T`.
+    pub fn component<T: Component>(&self, handle: ObjectHandle) -> Option<&T> {
+        self.components.get(handle)
+    }
+
+    /// Gets a mutable reference to an object's component of type `T`.
+    pub fn component_mut<T: Component>(&mut self, handle: ObjectHandle) -> Option<&mut T> {
+        self.components.get_mut(handle)
+    }
+
+    /// Returns `true` if the object has a component of type `T`.
+    pub fn has_component<T: Component>(&self, handle: ObjectHandle) -> bool {
+        self.component::<T>(handle).is_some()
+    }
+
+    /// Iterates over the objects that have every component in `Q`.
+    ///
+    /// `Q` is `&T` or a tuple such as `(&MeshHandle, &MaterialHandle)`. Only
+    /// the objects of the rarest component type are visited.
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> impl Iterator<Item = (ObjectHandle, Q::Item)> + 'a {
+        self.components
+            .candidates(&Q::component_types())
+            .iter()
+            .filter_map(|&handle| Q::fetch(&self.components, handle).map(|item| (handle, item)))
+    }
+
+    /// Iterates over the objects that have every component in `Q`, with
+    /// mutable access to some of them.
+    ///
+    /// `Q` is `&T`, `&mut T` or a tuple such as `(&mut Light, &ShadowFlags)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `Q` names the same component type more than once.
+    pub fn query_mut<'a, Q: QueryMut<'a> + 'a>(&'a mut self) -> impl Iterator<Item = (ObjectHandle, Q::Item)> + 'a {
+        let types = Q::component_types();
+        for (index, type_id) in types.iter().enumerate() {
+            assert!(
+                !types[..index].contains(type_id),
+                "query_mut names a component type more than once"
+            );
+        }
+        let candidates = self.components.candidates(&types).to_vec();
+        let access = Q::access(&mut self.components);
+        candidates.into_iter().filter_map(move |handle| {
+            let access = access.as_ref()?;
+            // SAFETY: the components stay borrowed mutably for 'a, the types
+            // were checked to be distinct, and every candidate is a
+            // different object
+            unsafe { Q::fetch(access, handle) }.map(|item| (handle, item))
+        })
+    }
+
+    /// Moves `child` under `parent`, or to the root of the scene for `None`.
+    ///
+    /// With [`KeepTransform::World`], shear that results from a rotated
+    /// parent with non-uniform scale can't be represented and is lost.
+    pub fn set_parent(
+        &mut self,
+        child: ObjectHandle,
+        parent: Option<ObjectHandle>,
+        keep: KeepTransform,
+    ) -> Result<(), HierarchyError> {
+        if !self.contains(child) {
+            return Err(HierarchyError::InvalidHandle(child));
+        }
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err(HierarchyError::InvalidHandle(parent));
+            }
+            if parent == child || self.ancestors(parent).any(|ancestor| ancestor == child) {
+                return Err(HierarchyError::Cycle { child, parent });
+            }
+        }
+
+        if keep == KeepTransform::World {
+            let parent_world = parent.map_or(Mat4::IDENTITY, |parent| self.world_matrix(parent));
+            let local = parent_world.inverse() * self.world_matrix(child);
+            let (scale, rotation, position) = local.to_scale_rotation_translation();
+            self.expect_object_mut(child).transform = Transform::new(position, rotation.normalize(), scale);
+        }
+
+        if let Some(old_parent) = self.expect_object(child).parent {
+            self.expect_object_mut(old_parent).children.retain(|&handle| handle != child);
+        }
+        if let Some(parent) = parent {
+            self.expect_object_mut(parent).children.push(child);
+        }
+        self.expect_object_mut(child).parent = parent;
+        Ok(())
+    }
+
+    /// Objects without a parent, in storage order.
+    pub fn roots(&self) -> impl Iterator<Item = ObjectHandle> + '_ {
+        self.objects()
+            .filter(|(_, object)| object.parent.is_none())
+            .map(|(handle, _)| handle)
+    }
+
+    /// Parent, grandparent and so on of an object, nearest first.
+    pub fn ancestors(&self, handle: ObjectHandle) -> impl Iterator<Item = ObjectHandle> + '_ {
+        std::iter::successors(self.object(handle).and_then(SceneObject::parent), |&ancestor| {
+            self.expect_object(ancestor).parent
+        })
+    }
+
+    /// All objects below an object, depth first, not including the object itself.
+    pub fn descendants(&self, handle: ObjectHandle) -> Descendants<'_> {
+        let stack = self.object(handle)
+            .map(|object| object.children.iter().rev().copied().collect())
+            .unwrap_or_default();
+        Descendants { scene: self, stack }
+    }
+
+    /// Every object, with parents visited before their children.
+    pub fn depth_first(&self) -> Descendants<'_> {
+        let mut roots: Vec<_> = self.roots().collect();
+        roots.reverse();
+        Descendants { scene: self, stack: roots }
+    }
+
+    /// Local-to-world matrix of an object, composed up its parent chain.
+    pub fn world_matrix(&self, handle: ObjectHandle) -> Mat4 {
+        let Some(object) = self.object(handle) else {
+            return Mat4::IDENTITY;
+        };
+        self.ancestors(handle)
+            .fold(object.transform.matrix(), |matrix, ancestor| {
+                self.expect_object(ancestor).transform.matrix() * matrix
+            })
+    }
+
+    /// Local-to-world matrices of all objects, with parents before their children.
+    pub fn world_matrices(&self) -> Vec<(ObjectHandle, Mat4)> {
+        let mut matrices = vec![Mat4::IDENTITY; self.objects.len()];
+        self.depth_first()
+            .map(|handle| {
+                let object = self.expect_object(handle);
+                let parent = object.parent.map_or(Mat4::IDENTITY, |parent| matrices[parent.index as usize]);
+                let world = parent * object.transform.matrix();
+                matrices[handle.index as usize] = world;
+                (handle, world)
+            })
+            .collect()
+    }
+}
+
+/// Depth-first iterator over part of a scene's hierarchy.
+pub struct Descendants<'a> {
+    scene: &'a Scene,
+    stack: Vec<ObjectHandle>,
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = ObjectHandle;
+
+    fn next(&mut self) -> Option<ObjectHandle> {
+        let handle = self.stack.pop()?;
+        self.stack.extend(self.scene.expect_object(handle).children.iter().rev());
+        Some(handle)
+    }
+}
@@ -0,0 +1,325 @@
+// Scene files for IntSar-3D
+//
+// Scenes are saved as RON text holding their meshes, materials and objects.
+// Objects refer to meshes, materials and their parent object by position in
+// the file. Only the built-in components (mesh, material, camera, light and
+// shadow flags) are saved. Every file records the format version it was
+// written with; files from older versions are upgraded to the current format
+// while loading.
+
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::{Material, MaterialHandle};
+use crate::math::Transform;
+use crate::mesh::{Mesh, MeshHandle};
+use crate::scene::{KeepTransform, ObjectHandle, Scene, SceneObject};
+use crate::shadow::ShadowFlags;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Version of the format written by [`save_scene`] and [`scene_to_string`].
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Error produced while saving or loading a scene file.
+#[derive(Debug)]
+pub enum SceneFileError {
+    /// A file could not be read or written.
+    Io { path: PathBuf, source: std::io::Error },
+    /// The text is not a valid scene of its version.
+    Parse { file: String, line: usize, column: usize, message: String },
+    /// The file was written by a format version this build doesn't know.
+    UnsupportedVersion { file: String, version: u32 },
+    /// The file parses, but refers to objects, meshes, materials or vertices
+    /// that don't exist, or its hierarchy has a cycle.
+    Invalid { file: String, message: String },
+    /// The scene could not be converted to text.
+    Serialize(ron::Error),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, source } => write!(f, "failed to access {}: {source}", path.display()),
+            SceneFileError::Parse { file, line, column, message } => write!(f, "{file}:{line}:{column}: {message}"),
+            SceneFileError::UnsupportedVersion { file, version } => write!(
+                f,
+                "{file}: unsupported scene format version {version} (latest is {FORMAT_VERSION})"
+            ),
+            SceneFileError::Invalid { file, message } => write!(f, "{file}: {message}"),
+            SceneFileError::Serialize(source) => write!(f, "failed to serialize scene: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            SceneFileError::Serialize(source) => Some(source),
+            SceneFileError::Parse { .. } | SceneFileError::UnsupportedVersion { .. } | SceneFileError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Layout of a file in the current format, borrowing from the scene it saves.
+#[derive(Serialize)]
+struct SceneFileRef<'a> {
+    version: u32,
+    meshes: &'a [Mesh],
+    materials: &'a [Material],
+    objects: Vec<ObjectRecord>,
+}
+
+/// Layout of a file in the current format.
+#[derive(Deserialize)]
+struct SceneFile {
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+    objects: Vec<ObjectRecord>,
+}
+
+/// An object as stored in a file, with its parent given by position in the file.
+#[derive(Serialize, Deserialize)]
+struct ObjectRecord {
+    name: String,
+    transform: Transform,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mesh: Option<MeshHandle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<MaterialHandle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    camera: Option<Camera>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    light: Option<Light>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadows: Option<ShadowFlags>,
+}
+
+/// Layout of a version 1 file. Version 1 had no hierarchy, so every object
+/// is a root.
+#[derive(Deserialize)]
+struct SceneFileV1 {
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+    objects: Vec<ObjectRecordV1>,
+}
+
+/// An object as stored in a version 1 file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectRecordV1 {
+    name: String,
+    transform: Transform,
+    #[serde(default)]
+    mesh: Option<MeshHandle>,
+    #[serde(default)]
+    material: Option<MaterialHandle>,
+    #[serde(default)]
+    camera: Option<Camera>,
+    #[serde(default)]
+    light: Option<Light>,
+}
+
+/// Upgrades a version 1 file to version 2, which added parents and shadow
+/// flags to objects.
+fn upgrade_v1(file: SceneFileV1) -> SceneFile {
+    let objects = file.objects
+        .into_iter()
+        .map(|object| ObjectRecord {
+            name: object.name,
+            transform: object.transform,
+            parent: None,
+            mesh: object.mesh,
+            material: object.material,
+            camera: object.camera,
+            light: object.light,
+            shadows: None,
+        })
+        .collect();
+    SceneFile {
+        meshes: file.meshes,
+        materials: file.materials,
+        objects,
+    }
+}
+
+/// Just the version of a file, read before the rest to pick a layout.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Converts a scene to RON text in the current format.
+pub fn scene_to_string(scene: &Scene) -> Result<String, SceneFileError> {
+    // Objects are written in storage order, leaving out the gaps of removed ones
+    let positions: HashMap<ObjectHandle, usize> = scene.objects()
+        .enumerate()
+        .map(|(position, (handle, _))| (handle, position))
+        .collect();
+    let objects = scene.objects()
+        .map(|(handle, object)| ObjectRecord {
+            name: object.name().to_string(),
+            transform: object.transform,
+            parent: object.parent().map(|parent| positions[&parent]),
+            mesh: scene.component::<MeshHandle>(handle).copied(),
+            material: scene.component::<MaterialHandle>(handle).copied(),
+            camera: scene.component::<Camera>(handle).copied(),
+            light: scene.component::<Light>(handle).copied(),
+            shadows: scene.component::<ShadowFlags>(handle).copied(),
+        })
+        .collect();
+
+    let file = SceneFileRef {
+        version: FORMAT_VERSION,
+        meshes: scene.meshes(),
+        materials: scene.materials(),
+        objects,
+    };
+    // Vertices and numeric arrays get one line each rather than one per number
+    let config = ron::ser::PrettyConfig::new().depth_limit(4).indentor("    ".to_string());
+    let mut text = ron::ser::to_string_pretty(&file, config).map_err(SceneFileError::Serialize)?;
+    text.push('\n');
+    Ok(text)
+}
+
+/// Writes a scene to a RON file in the current format.
+pub fn save_scene(scene: &Scene, path: impl AsRef<Path>) -> Result<(), SceneFileError> {
+    let path = path.as_ref();
+    let text = scene_to_string(scene)?;
+    std::fs::write(path, text).map_err(|source| SceneFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Loads a scene from a RON file, upgrading older format versions.
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene_file(&source, &path.display().to_string())
+}
+
+/// Parses a scene from RON text, upgrading older format versions.
+pub fn scene_from_str(source: &str) -> Result<Scene, SceneFileError> {
+    parse_scene_file(source, "<scene>")
+}
+
+fn parse_scene_file(source: &str, file: &str) -> Result<Scene, SceneFileError> {
+    let Header { version } = parse(source, file)?;
+    let scene_file = migrate(source, file, version)?;
+    build_scene(scene_file, file)
+}
+
+/// Parses a file written with format `version` into the current layout.
+///
+/// Each old version is parsed with its own structs, then passed through the
+/// upgrade to every later version in turn. When the format changes, the
+/// previous layout keeps its structs and gains an upgrade to the next
+/// version, which the arms of older versions chain onto.
+fn migrate(source: &str, file: &str, version: u32) -> Result<SceneFile, SceneFileError> {
+    match version {
+        1 => parse(source, file).map(upgrade_v1),
+        FORMAT_VERSION => parse(source, file),
+        _ => Err(SceneFileError::UnsupportedVersion {
+            file: file.to_string(),
+            version,
+        }),
+    }
+}
+
+fn parse<'de, T: Deserialize<'de>>(source: &'de str, file: &str) -> Result<T, SceneFileError> {
+    ron::from_str(source).map_err(|error| SceneFileError::Parse {
+        file: file.to_string(),
+        line: error.position.line,
+        column: error.position.col,
+        message: error.code.to_string(),
+    })
+}
+
+/// Checks that every reference in the file resolves, then builds the scene.
+fn build_scene(scene_file: SceneFile, file: &str) -> Result<Scene, SceneFileError> {
+    let invalid = |message: String| SceneFileError::Invalid {
+        file: file.to_string(),
+        message,
+    };
+
+    for mesh in &scene_file.meshes {
+        let vertex_count = mesh.vertices().len();
+        if let Some(index) = mesh.indices().iter().find(|&index| index as usize >= vertex_count) {
+            return Err(invalid(format!(
+                "index {index} out of range in mesh `{}` with {vertex_count} vertices",
+                mesh.name
+            )));
+        }
+    }
+    let object_count = scene_file.objects.len();
+    for object in &scene_file.objects {
+        if let Some(parent) = object.parent.filter(|&parent| parent >= object_count) {
+            return Err(invalid(format!(
+                "object `{}` refers to parent {parent} but the file has {object_count} objects",
+                object.name
+            )));
+        }
+        if let Some(mesh) = object.mesh.filter(|mesh| mesh.0 >= scene_file.meshes.len()) {
+            return Err(invalid(format!(
+                "object `{}` refers to mesh {} but the file has {} meshes",
+                object.name,
+                mesh.0,
+                scene_file.meshes.len()
+            )));
+        }
+        if let Some(material) = object.material.filter(|material| material.0 >= scene_file.materials.len()) {
+            return Err(invalid(format!(
+                "object `{}` refers to material {} but the file has {} materials",
+                object.name,
+                material.0,
+                scene_file.materials.len()
+            )));
+        }
+    }
+
+    // Handles are indices, so adding in file order keeps them valid
+    let mut scene = Scene::new();
+    for mesh in scene_file.meshes {
+        scene.add_mesh(mesh);
+    }
+    for material in scene_file.materials {
+        scene.add_material(material);
+    }
+    // Parents may come after their children, so link them once all exist
+    let mut handles = Vec::with_capacity(object_count);
+    for record in &scene_file.objects {
+        let mut object = SceneObject::new(record.name.clone(), record.transform);
+        if let Some(mesh) = record.mesh {
+            object = object.with_mesh(mesh);
+        }
+        if let Some(material) = record.material {
+            object = object.with_material(material);
+        }
+        if let Some(camera) = record.camera {
+            object = object.with_camera(camera);
+        }
+        if let Some(light) = record.light {
+            object = object.with_light(light);
+        }
+        if let Some(flags) = record.shadows {
+            object = object.with_shadow_flags(flags);
+        }
+        handles.push(scene.add_object(object));
+    }
+    for (record, &handle) in scene_file.objects.iter().zip(&handles) {
+        if let Some(parent) = record.parent {
+            scene
+                .set_parent(handle, Some(handles[parent]), KeepTransform::Local)
+                .map_err(|_| invalid(format!("object `{}` is its own ancestor", record.name)))?;
+        }
+    }
+    Ok(scene)
+}
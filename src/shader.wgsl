@@ -1,35 +1,258 @@
-// WGSL shaders for IntSar-3D
+// WGSL shaders for IntSar-3D, appended to lighting.wgsl
 
-// Uniform buffer for MVP matrix
-struct Uniforms {
-    mvp: mat4x4<f32>,
+// Metallic-roughness parameters of the object's material
+struct MaterialUniforms {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    // Fragments with less alpha are discarded
+    alpha_cutoff: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
 };
 
-@group(0) @binding(0)
-var<uniform> uniforms: Uniforms;
+// Per-object uniforms
+struct ObjectUniforms {
+    model: mat4x4<f32>,
+    // Inverse transpose of the model matrix
+    normal: mat4x4<f32>,
+    material: MaterialUniforms,
+    receive_shadows: u32,
+    // Model matrix of the previous frame
+    previous_model: mat4x4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniforms;
+
+// Material textures, with white or flat-normal stand-ins for missing maps
+@group(2) @binding(0)
+var base_color_texture: texture_2d<f32>;
+// Roughness in green, metalness in blue
+@group(2) @binding(1)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var normal_texture: texture_2d<f32>;
+// Ambient occlusion in red
+@group(2) @binding(3)
+var occlusion_texture: texture_2d<f32>;
+@group(2) @binding(4)
+var emissive_texture: texture_2d<f32>;
+@group(2) @binding(5)
+var material_sampler: sampler;
 
 // Vertex shader
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+    // Bitangent sign in w
+    @location(4) tangent: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+    @location(4) tangent: vec4<f32>,
+    // Unjittered clip positions this frame and the previous one
+    @location(5) current_clip: vec4<f32>,
+    @location(6) previous_clip: vec4<f32>,
 };
 
 @vertex
 fn vs_main(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    // Transform position with MVP matrix
-    out.clip_position = uniforms.mvp * vec4<f32>(model.position, 1.0);
+    // Transform position with model and view-projection matrices
+    let world_position = object.model * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
     out.color = model.color;
+    out.world_position = world_position.xyz;
+    out.normal = (object.normal * vec4<f32>(model.normal, 0.0)).xyz;
+    out.uv = model.uv;
+    out.tangent = vec4<f32>((object.model * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
+    out.current_clip = camera.unjittered_view_proj * world_position;
+    out.previous_clip = camera.previous_view_proj * object.previous_model * vec4<f32>(model.position, 1.0);
     return out;
 }
 
-// Fragment shader
+// Material properties of a fragment, with its textures applied
+struct MaterialSample {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    // Perturbed by the normal map; zero for meshes without normals
+    normal: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+};
+
+// Samples the object's material, discarding cut-out texels
+fn sample_material(in: VertexOutput) -> MaterialSample {
+    // Sample everything up front, while control flow is still uniform
+    let material = object.material;
+    let base_color_sample = textureSample(base_color_texture, material_sampler, in.uv);
+    let metallic_roughness_sample = textureSample(metallic_roughness_texture, material_sampler, in.uv);
+    let normal_sample = textureSample(normal_texture, material_sampler, in.uv);
+    let occlusion_sample = textureSample(occlusion_texture, material_sampler, in.uv);
+    let emissive_sample = textureSample(emissive_texture, material_sampler, in.uv);
+
+    var out: MaterialSample;
+    out.base_color = material.base_color * vec4<f32>(in.color, 1.0) * base_color_sample;
+    if out.base_color.a < material.alpha_cutoff {
+        discard;
+    }
+    out.emissive = material.emissive * emissive_sample.rgb;
+    out.metallic = clamp(material.metallic * metallic_roughness_sample.b, 0.0, 1.0);
+    out.roughness = clamp(material.roughness * metallic_roughness_sample.g, 0.04, 1.0);
+    out.occlusion = mix(1.0, occlusion_sample.r, material.occlusion_strength);
+
+    // Perturb the normal in tangent space, unless the mesh has no tangents
+    if dot(in.normal, in.normal) == 0.0 {
+        return out;
+    }
+    var normal = normalize(in.normal);
+    if dot(in.tangent.xyz, in.tangent.xyz) > 0.0 {
+        let tangent = normalize(in.tangent.xyz - normal * dot(normal, in.tangent.xyz));
+        let bitangent = cross(normal, tangent) * in.tangent.w;
+        let perturbed = (normal_sample.xyz * 2.0 - 1.0) * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+        normal = normalize(tangent * perturbed.x + bitangent * perturbed.y + normal * perturbed.z);
+    }
+    out.normal = normal;
+    return out;
+}
+
+// Ambient and emitted light leaving a fragment
+fn ambient(in: VertexOutput, material: MaterialSample) -> vec3<f32> {
+    // Meshes without normals can't be lit
+    if dot(material.normal, material.normal) == 0.0 {
+        return material.base_color.rgb + material.emissive;
+    }
+    let diffuse_color = material.base_color.rgb * (1.0 - material.metallic);
+    let screen_occlusion = textureLoad(ambient_occlusion_texture, vec2<i32>(in.clip_position.xy), 0).r;
+    return lighting.ambient * diffuse_color * material.occlusion * screen_occlusion + material.emissive;
+}
+
+// Lit color of a fragment
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let material = sample_material(in);
+    var color = ambient(in, material);
+    if dot(material.normal, material.normal) == 0.0 {
+        return vec4<f32>(color, material.base_color.a);
+    }
+
+    let surface = surface(
+        in.world_position,
+        material.normal,
+        normalize(in.normal),
+        material.base_color.rgb,
+        material.metallic,
+        material.roughness,
+        object.receive_shadows != 0u,
+    );
+    // Only the lights that reach this fragment's cluster
+    let cluster = light_cluster(in.clip_position.xy, in.world_position);
+    for (var i = 0u; i < light_clusters[cluster]; i++) {
+        color += reflected(lights[light_clusters[cluster + 1u + i]], surface);
+    }
+
+    if lighting.debug_cascades != 0u {
+        color *= cascade_tint(in.world_position);
+    }
+    return vec4<f32>(color, material.base_color.a);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+    return shade(in);
+}
+
+// Fragment shader for the SSAO depth prepass, which only drops cut-out texels
+@fragment
+fn fs_prepass(in: VertexOutput) {
+    let alpha = object.material.base_color.a * textureSample(base_color_texture, material_sampler, in.uv).a;
+    if alpha < object.material.alpha_cutoff {
+        discard;
+    }
+}
+
+// Motion since the previous frame in UV units
+fn velocity(in: VertexOutput) -> vec2<f32> {
+    let current = in.current_clip.xy / in.current_clip.w;
+    let previous = in.previous_clip.xy / in.previous_clip.w;
+    // UV runs down the screen, while clip space runs up
+    return (current - previous) * vec2<f32>(0.5, -0.5);
+}
+
+struct TaaOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) velocity: vec2<f32>,
+};
+
+// Fragment shader for TAA, which also writes motion vectors
+@fragment
+fn fs_main_taa(in: VertexOutput) -> TaaOutput {
+    var out: TaaOutput;
+    out.color = shade(in);
+    out.velocity = velocity(in);
+    return out;
+}
+
+// G-buffer of the deferred path, matching the targets in deferred.rs
+struct GBuffer {
+    // Ambient and emitted light, which the lighting pass adds onto
+    @location(0) color: vec4<f32>,
+    // Base color
+    @location(1) albedo: vec4<f32>,
+    // World-space shading normal in xyz, and 1 in w where the surface is lit
+    @location(2) normal: vec4<f32>,
+    // Metallic, roughness, occlusion, and 1 where the surface receives shadows
+    @location(3) material: vec4<f32>,
+};
+
+fn gbuffer(in: VertexOutput) -> GBuffer {
+    let material = sample_material(in);
+    var out: GBuffer;
+    out.albedo = vec4<f32>(material.base_color.rgb, 1.0);
+    if dot(material.normal, material.normal) > 0.0 {
+        out.normal = vec4<f32>(material.normal, 1.0);
+    }
+    out.material = vec4<f32>(material.metallic, material.roughness, material.occlusion, f32(object.receive_shadows != 0u));
+    var color = ambient(in, material);
+    if lighting.debug_cascades != 0u && out.normal.w > 0.0 {
+        color *= cascade_tint(in.world_position);
+    }
+    out.color = vec4<f32>(color, 1.0);
+    return out;
+}
+
+// Fragment shader of the deferred path's G-buffer pass
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBuffer {
+    return gbuffer(in);
+}
+
+struct GBufferTaa {
+    @location(0) color: vec4<f32>,
+    @location(1) albedo: vec4<f32>,
+    @location(2) normal: vec4<f32>,
+    @location(3) material: vec4<f32>,
+    @location(4) velocity: vec2<f32>,
+};
+
+// G-buffer pass fragment shader for TAA, which also writes motion vectors
+@fragment
+fn fs_gbuffer_taa(in: VertexOutput) -> GBufferTaa {
+    let gbuffer = gbuffer(in);
+    var out: GBufferTaa;
+    out.color = gbuffer.color;
+    out.albedo = gbuffer.albedo;
+    out.normal = gbuffer.normal;
+    out.material = gbuffer.material;
+    out.velocity = velocity(in);
+    return out;
+}
@@ -0,0 +1,577 @@
+// Shadow mapping for IntSar-3D
+//
+// Each directional or spot light with shadows renders the depth of the
+// shadow-casting meshes into its own layer of a depth texture array. The main
+// pass compares against that layer with a 3x3 PCF kernel of bilinear
+// comparison samples. Directional shadows cover the bounding sphere of the
+// scene's meshes; spot shadows cover the light's cone.
+//
+// Directional lights can instead split the camera's view distance into
+// cascades, each with its own layer, so nearby shadows stay sharp in scenes
+// too large for one map. The main pass picks a cascade by view depth and
+// blends into the next one near the boundary.
+//
+// Point lights render six faces of a cube around the light into a 3x2 atlas
+// in one layer, storing linear distance so the main pass can filter across
+// face boundaries by picking the face per sample.
+
+use crate::mesh::{GpuMesh, Vertex};
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Most shadow maps in use at once. Each cascade takes a map of its own;
+/// lights that don't fit in the remaining maps are unshadowed.
+pub const MAX_SHADOW_MAPS: usize = 8;
+
+/// Most cascades a directional light can split its shadows into.
+pub const MAX_CASCADES: usize = 4;
+
+/// Most point lights that cast shadows at once, each into six cube faces.
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+/// Format of the shadow map texture array.
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Shadow settings of a [`Light`](crate::light::Light).
+///
+/// The biases push receivers towards the light to keep surfaces from
+/// shadowing themselves ("shadow acne"); too much makes shadows detach from
+/// their casters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Shadows {
+    /// Constant offset in shadow map depth, which runs from 0 to 1.
+    pub depth_bias: f32,
+    /// Depth offset per unit of surface slope away from the light.
+    pub slope_bias: f32,
+    /// Distance, in world units, that lookups move along the surface normal.
+    pub normal_bias: f32,
+    /// Cascades following the camera, or `None` for one map covering the
+    /// whole scene. Only used by directional lights.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cascades: Option<Cascades>,
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Self {
+            depth_bias: 0.0005,
+            slope_bias: 0.001,
+            normal_bias: 0.02,
+            cascades: None,
+        }
+    }
+}
+
+/// How the camera's view distance is divided between cascades.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CascadeSplit {
+    /// Equally long cascades.
+    Uniform,
+    /// Cascades growing geometrically with distance, matching perspective.
+    Logarithmic,
+    /// Mix of the two, from all uniform (0) to all logarithmic (1).
+    Practical { lambda: f32 },
+}
+
+/// Cascaded shadow map settings of a directional light.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Cascades {
+    /// Number of cascades, up to [`MAX_CASCADES`].
+    pub count: usize,
+    pub split: CascadeSplit,
+    /// View distance past which nothing is shadowed.
+    pub max_distance: f32,
+    /// Fraction at the far end of each cascade that fades into the next one.
+    pub blend: f32,
+    /// Keeps each cascade's size fixed and snaps it to whole shadow map
+    /// texels, so shadow edges don't shimmer as the camera moves.
+    pub stabilize: bool,
+}
+
+impl Default for Cascades {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            split: CascadeSplit::Practical { lambda: 0.75 },
+            max_distance: 50.0,
+            blend: 0.1,
+            stabilize: true,
+        }
+    }
+}
+
+/// View volume of a perspective camera, as needed to place cascades.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// World-to-view transform of the camera, which looks down -Z.
+    pub view: Mat4,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Frustum {
+    /// World-space corners of the slice of the frustum between two view distances.
+    fn corners(&self, near: f32, far: f32) -> [Vec3; 8] {
+        let to_world = self.view.inverse();
+        let tan_y = (self.fov_y * 0.5).tan();
+        let tan_x = tan_y * self.aspect_ratio;
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let distance = if i < 4 { near } else { far };
+            let x = if i & 1 == 0 { -tan_x } else { tan_x };
+            let y = if i & 2 == 0 { -tan_y } else { tan_y };
+            *corner = to_world.transform_point3(Vec3::new(x, y, -1.0) * distance);
+        }
+        corners
+    }
+}
+
+/// View distances bounding each cascade, from the near plane to the end of
+/// the last cascade, so there is one more distance than cascades.
+pub fn cascade_splits(frustum: &Frustum, cascades: &Cascades) -> Vec<f32> {
+    let count = cascades.count.clamp(1, MAX_CASCADES);
+    let near = frustum.near;
+    let far = frustum.far.min(cascades.max_distance).max(near);
+    let mut splits: Vec<f32> = (0..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let uniform = near + (far - near) * t;
+            let logarithmic = near * (far / near).powf(t);
+            match cascades.split {
+                CascadeSplit::Uniform => uniform,
+                CascadeSplit::Logarithmic => logarithmic,
+                CascadeSplit::Practical { lambda } => logarithmic * lambda + uniform * (1.0 - lambda),
+            }
+        })
+        .collect();
+    // Pin the ends against rounding in the power
+    splits[0] = near;
+    splits[count] = far;
+    splits
+}
+
+/// View-projection of the cascade covering view distances `near` to `far`
+/// for a directional light travelling along `direction`.
+///
+/// The depth range reaches back to the casters' bounding sphere, given as
+/// center and radius, so casters between the light and the cascade still
+/// cast into it. With `stabilize`, the cascade keeps a size that only
+/// depends on its frustum slice and moves in whole texels of a `map_size`
+/// map.
+pub fn cascade_view_proj(
+    frustum: &Frustum,
+    near: f32,
+    far: f32,
+    direction: Vec3,
+    casters: (Vec3, f32),
+    map_size: u32,
+    stabilize: bool,
+) -> Mat4 {
+    let light_view = Mat4::look_to_rh(Vec3::ZERO, direction, light_up(direction));
+    let corners = frustum.corners(near, far).map(|corner| light_view.transform_point3(corner));
+
+    let (mut min, mut max) = corners.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), &corner| (min.min(corner), max.max(corner)),
+    );
+    if stabilize {
+        // The bounding sphere doesn't change size as the camera turns
+        let center = corners.iter().sum::<Vec3>() / 8.0;
+        let radius = corners.iter().map(|corner| corner.distance(center)).fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+        let texel = 2.0 * radius / map_size.max(1) as f32;
+        let snapped = (center.truncate() / texel).floor() * texel;
+        min = (snapped - radius).extend(center.z - radius);
+        max = (snapped + radius).extend(center.z + radius);
+    }
+
+    // Light view space looks down -Z, so the light is towards +Z
+    let (caster_center, caster_radius) = casters;
+    let eye = max.z.max(light_view.transform_point3(caster_center).z + caster_radius);
+    let view = Mat4::from_translation(Vec3::new(0.0, 0.0, -eye)) * light_view;
+    Mat4::orthographic_rh(min.x, max.x, min.y, max.y, 0.0, (eye - min.z).max(0.001)) * view
+}
+
+/// Up vector for a light's view that isn't parallel to its direction.
+fn light_up(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y }
+}
+
+/// Whether an object's mesh casts and receives shadows.
+///
+/// Objects without this component do both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShadowFlags {
+    pub cast: bool,
+    pub receive: bool,
+}
+
+impl Default for ShadowFlags {
+    fn default() -> Self {
+        Self {
+            cast: true,
+            receive: true,
+        }
+    }
+}
+
+/// View-projection of a directional light travelling along `direction`,
+/// covering the sphere at `center`.
+pub(crate) fn directional_view_proj(direction: Vec3, center: Vec3, radius: f32) -> Mat4 {
+    let radius = radius.max(0.001);
+    let view = Mat4::look_to_rh(center - direction * 2.0 * radius, direction, light_up(direction));
+    Mat4::orthographic_rh(-radius, radius, -radius, radius, radius, 3.0 * radius) * view
+}
+
+/// View-projection of a spot light's cone, out to `far`.
+pub(crate) fn spot_view_proj(position: Vec3, direction: Vec3, outer_cone_angle: f32, far: f32) -> Mat4 {
+    let view = Mat4::look_to_rh(position, direction, light_up(direction));
+    let fov = (2.0 * outer_cone_angle).clamp(0.01, std::f32::consts::PI - 0.01);
+    let far = far.max(0.02);
+    Mat4::perspective_rh(fov, 1.0, (far * 0.001).max(0.01), far) * view
+}
+
+/// View-projections of the six faces of a point light's cube map, in the
+/// order +X, -X, +Y, -Y, +Z, -Z, reaching out to `far`.
+pub(crate) fn point_face_view_projs(position: Vec3, far: f32) -> [Mat4; 6] {
+    let far = far.max(0.02);
+    let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, (far * 0.001).max(0.01), far);
+    let faces = [
+        (Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_Y),
+    ];
+    faces.map(|(direction, up)| projection * Mat4::look_to_rh(position, direction, up))
+}
+
+/// One face of a point light's cube map to render.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PointFace {
+    pub view_proj: Mat4,
+    pub position: Vec3,
+    /// Distance stored as depth 1.
+    pub far: f32,
+}
+
+/// Everything the shadow pass renders in one frame.
+#[derive(Default)]
+pub(crate) struct ShadowViews {
+    /// View-projections of directional and spot light maps, by layer.
+    pub maps: Vec<Mat4>,
+    /// Point light cube faces, six per light, by layer.
+    pub point_faces: Vec<PointFace>,
+}
+
+// View-projection of one shadow map, selected with a dynamic offset. Point
+// light faces also store the distance from the light
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniforms {
+    view_proj: [[f32; 4]; 4],
+    light_position: [f32; 3],
+    far: f32,
+}
+
+/// A mesh drawn into shadow maps, with the dynamic offset of its object slot.
+pub(crate) struct Caster<'a> {
+    pub offset: wgpu::DynamicOffset,
+    pub mesh: &'a GpuMesh,
+}
+
+/// Columns and rows of the cube face atlas in each point light's layer.
+const POINT_ATLAS: (u32, u32) = (3, 2);
+
+/// Layers of one depth texture array, each holding one or more square tiles.
+struct MapArray {
+    /// Width and height of one tile.
+    size: u32,
+    /// All layers, as sampled by the main pass.
+    view: wgpu::TextureView,
+    /// One view per layer, as rendered to by the shadow pass.
+    layer_views: Vec<wgpu::TextureView>,
+}
+
+impl MapArray {
+    fn new(device: &wgpu::Device, label: &str, size: u32, (columns, rows): (u32, u32), layers: usize) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size * columns,
+                height: size * rows,
+                depth_or_array_layers: layers as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("{label} View")),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..layers as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(&format!("{label} Layer View")),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Self { size, view, layer_views }
+    }
+}
+
+/// Shadow map textures and the depth-only pipelines that fill them.
+///
+/// Directional and spot lights store clip-space depth in `maps`. Point lights
+/// store their distance from the light, divided by the far distance, in a
+/// layer of `point_maps` each, so one value compares across all faces.
+pub(crate) struct ShadowMaps {
+    maps: MapArray,
+    point_maps: MapArray,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_stride: wgpu::BufferAddress,
+}
+
+impl ShadowMaps {
+    /// Creates `size`x`size` shadow maps and `point_size`x`point_size` cube
+    /// faces, drawing casters with the renderer's per-object bind group layout.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        object_bind_group_layout: &wgpu::BindGroupLayout,
+        size: u32,
+        point_size: u32,
+    ) -> Self {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ShadowUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let uniform_stride = (std::mem::size_of::<ShadowUniforms>() as wgpu::BufferAddress)
+            .div_ceil(alignment) * alignment;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: uniform_stride * (MAX_SHADOW_MAPS + 6 * MAX_POINT_SHADOWS) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<ShadowUniforms>() as u64),
+                    }),
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, object_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, vertex_entry, fragment_entry: Option<&str>| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: vertex_entry,
+                    buffers: &[Vertex::layout()],
+                },
+                fragment: fragment_entry.map(|entry_point| wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    // Both sides cast, so single-sided meshes like planes do too
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: SHADOW_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline("Shadow Pipeline", "vs_main", None);
+        let point_pipeline = create_pipeline("Point Shadow Pipeline", "vs_point", Some("fs_point"));
+
+        // Outside the map counts as lit
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self {
+            maps: MapArray::new(device, "Shadow Maps", size, (1, 1), MAX_SHADOW_MAPS),
+            point_maps: MapArray::new(device, "Point Shadow Maps", point_size, POINT_ATLAS, MAX_POINT_SHADOWS),
+            sampler,
+            pipeline,
+            point_pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            uniform_stride,
+        }
+    }
+
+    /// Width and height of each directional or spot shadow map in texels.
+    pub(crate) fn size(&self) -> u32 {
+        self.maps.size
+    }
+
+    /// Width and height of each point light cube face in texels.
+    pub(crate) fn point_size(&self) -> u32 {
+        self.point_maps.size
+    }
+
+    /// Recreates the directional and spot shadow maps at a new size.
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, size: u32) {
+        self.maps = MapArray::new(device, "Shadow Maps", size, (1, 1), MAX_SHADOW_MAPS);
+    }
+
+    /// Recreates the point light cube faces at a new size.
+    pub(crate) fn resize_point(&mut self, device: &wgpu::Device, size: u32) {
+        self.point_maps = MapArray::new(device, "Point Shadow Maps", size, POINT_ATLAS, MAX_POINT_SHADOWS);
+    }
+
+    /// Every directional and spot shadow map layer, for sampling.
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        &self.maps.view
+    }
+
+    /// Every point light's cube face atlas, for sampling.
+    pub(crate) fn point_view(&self) -> &wgpu::TextureView {
+        &self.point_maps.view
+    }
+
+    /// Comparison sampler for [`ShadowMaps::view`] and [`ShadowMaps::point_view`].
+    pub(crate) fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Renders the casters into one layer per shadow view.
+    pub(crate) fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        views: &ShadowViews,
+        object_bind_group: &wgpu::BindGroup,
+        casters: &[Caster],
+    ) {
+        let uniforms = views.maps.iter()
+            .map(|view_proj| ShadowUniforms {
+                view_proj: view_proj.to_cols_array_2d(),
+                light_position: [0.0; 3],
+                far: 1.0,
+            })
+            .chain(views.point_faces.iter().map(|face| ShadowUniforms {
+                view_proj: face.view_proj.to_cols_array_2d(),
+                light_position: face.position.to_array(),
+                far: face.far,
+            }));
+        let stride = self.uniform_stride as usize;
+        let mut uniform_data = vec![0u8; (views.maps.len() + views.point_faces.len()) * stride];
+        for (slot, uniforms) in uniform_data.chunks_exact_mut(stride).zip(uniforms) {
+            slot[..std::mem::size_of::<ShadowUniforms>()].copy_from_slice(bytemuck::bytes_of(&uniforms));
+        }
+        if !uniform_data.is_empty() {
+            queue.write_buffer(&self.uniform_buffer, 0, &uniform_data);
+        }
+
+        // Directional and spot maps take one pass each; point lights clear
+        // their layer once and draw each face into its tile of the atlas
+        let maps = self.maps.layer_views.iter()
+            .take(views.maps.len())
+            .map(|layer_view| (layer_view, &self.pipeline, 1));
+        let point_maps = self.point_maps.layer_views.iter()
+            .take(views.point_faces.len() / 6)
+            .map(|layer_view| (layer_view, &self.point_pipeline, 6));
+        let mut slot = 0;
+        for (layer_view, pipeline, tiles) in maps.chain(point_maps) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: layer_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            for tile in 0..tiles {
+                if tiles > 1 {
+                    let size = self.point_maps.size;
+                    let (x, y) = (tile % POINT_ATLAS.0 * size, tile / POINT_ATLAS.0 * size);
+                    render_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+                }
+                let offset = (slot as wgpu::BufferAddress * self.uniform_stride) as wgpu::DynamicOffset;
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[offset]);
+                for caster in casters {
+                    render_pass.set_bind_group(1, object_bind_group, &[caster.offset]);
+                    render_pass.set_vertex_buffer(0, caster.mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(caster.mesh.index_buffer.slice(..), caster.mesh.index_format);
+                    render_pass.draw_indexed(0..caster.mesh.index_count, 0, 0..1);
+                }
+                slot += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Depth-only shadow map shader for IntSar-3D
+
+// View-projection of the light whose shadow map is being drawn. Point light
+// faces also carry the light's position and the distance stored as depth 1
+struct ShadowUniforms {
+    view_proj: mat4x4<f32>,
+    light_position: vec3<f32>,
+    far: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> shadow: ShadowUniforms;
+
+// Leading part of the main shader's per-object uniforms
+struct ObjectUniforms {
+    model: mat4x4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniforms;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return shadow.view_proj * object.model * vec4<f32>(position, 1.0);
+}
+
+struct PointOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_point(@location(0) position: vec3<f32>) -> PointOutput {
+    var out: PointOutput;
+    let world_position = object.model * vec4<f32>(position, 1.0);
+    out.clip_position = shadow.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+// Point lights store linear distance, which compares the same on every face
+@fragment
+fn fs_point(in: PointOutput) -> @builtin(frag_depth) f32 {
+    return clamp(distance(in.world_position, shadow.light_position) / shadow.far, 0.0, 1.0);
+}
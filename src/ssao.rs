@@ -0,0 +1,351 @@
+// Screen-space ambient occlusion for IntSar-3D
+//
+// Before the main pass, opaque meshes are drawn into a depth buffer of their
+// own. For each pixel, the SSAO pass rebuilds the view-space position from
+// that depth, estimates the normal from the neighbouring positions, and tests
+// a hemisphere of sample points around it against the depth buffer: the more
+// of them end up behind other surfaces, the more occluded the pixel is. A
+// randomly rotated kernel trades banding for noise, which a separable
+// bilateral blur then smooths without bleeding across depth edges. The main
+// shader darkens its ambient term by the result.
+
+use crate::renderer::DEPTH_FORMAT;
+use glam::{Mat4, Vec3};
+use std::f32::consts::TAU;
+
+/// Most hemisphere samples SSAO can take per pixel.
+pub const MAX_SSAO_SAMPLES: usize = 64;
+
+/// Format of the occlusion target, 1 for unoccluded.
+const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Settings of the screen-space ambient occlusion pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoSettings {
+    /// Radius of the sampled hemisphere, in world units.
+    pub radius: f32,
+    /// Exponent applied to the unoccluded fraction; 0 turns occlusion off.
+    pub intensity: f32,
+    /// Samples per pixel, up to [`MAX_SSAO_SAMPLES`].
+    pub samples: u32,
+    /// View-space distance a sample must be behind a surface to count, which
+    /// keeps flat surfaces from occluding themselves.
+    pub bias: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            intensity: 1.0,
+            samples: 16,
+            bias: 0.025,
+        }
+    }
+}
+
+/// `count` sample offsets in the unit hemisphere around +Z.
+///
+/// Directions follow a cosine-weighted Hammersley set, and lengths are
+/// spread so that more samples land close to the center, where occlusion
+/// matters most.
+pub fn hemisphere_kernel(count: usize) -> Vec<Vec3> {
+    (0..count)
+        .map(|index| {
+            let u = (index as f32 + 0.5) / count as f32;
+            let v = (index as u32).reverse_bits() as f32 / 2f32.powi(32);
+            let radius = u.sqrt();
+            let angle = TAU * v;
+            let direction = Vec3::new(radius * angle.cos(), radius * angle.sin(), (1.0 - u).sqrt());
+
+            // A golden ratio sequence keeps lengths independent of directions
+            let length = (index as f32 * 0.618_034 + 0.5).fract();
+            direction * (0.1 + 0.9 * length * length)
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniforms {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    kernel: [[f32; 4]; MAX_SSAO_SAMPLES],
+    radius: f32,
+    intensity: f32,
+    bias: f32,
+    sample_count: u32,
+}
+
+/// The SSAO depth buffer, occlusion targets and the passes between them.
+pub(crate) struct AmbientOcclusion {
+    settings: Option<SsaoSettings>,
+    /// Whether the occlusion target has to be cleared to unoccluded, as it
+    /// is new or SSAO was turned off.
+    stale: bool,
+    depth: wgpu::TextureView,
+    /// The result ends up in the first target; the second holds the
+    /// horizontally blurred occlusion in between.
+    occlusion: [wgpu::TextureView; 2],
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Bind groups reading the first and the second occlusion target.
+    bind_groups: [wgpu::BindGroup; 2],
+    ssao_pipeline: wgpu::RenderPipeline,
+    horizontal_blur_pipeline: wgpu::RenderPipeline,
+    vertical_blur_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl AmbientOcclusion {
+    pub(crate) fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // Read as plain floats, as some backends can't load
+                    // texels of depth textures
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ssao.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(OCCLUSION_FORMAT.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let ssao_pipeline = create_pipeline("SSAO Pipeline", "fs_ssao");
+        let horizontal_blur_pipeline = create_pipeline("SSAO Horizontal Blur Pipeline", "fs_blur_horizontal");
+        let vertical_blur_pipeline = create_pipeline("SSAO Vertical Blur Pipeline", "fs_blur_vertical");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Uniform Buffer"),
+            size: std::mem::size_of::<SsaoUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (depth, occlusion, bind_groups) =
+            Self::create_targets(device, &bind_group_layout, &uniform_buffer, width, height);
+        Self {
+            settings: None,
+            stale: true,
+            depth,
+            occlusion,
+            bind_group_layout,
+            bind_groups,
+            ssao_pipeline,
+            horizontal_blur_pipeline,
+            vertical_blur_pipeline,
+            uniform_buffer,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::TextureView, [wgpu::TextureView; 2], [wgpu::BindGroup; 2]) {
+        let create_view = |label, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let depth = create_view("SSAO Depth Texture", DEPTH_FORMAT);
+        let occlusion = [
+            create_view("Occlusion Texture 0", OCCLUSION_FORMAT),
+            create_view("Occlusion Texture 1", OCCLUSION_FORMAT),
+        ];
+        let create_bind_group = |input: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("SSAO Bind Group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let bind_groups = [create_bind_group(&occlusion[0]), create_bind_group(&occlusion[1])];
+        (depth, occlusion, bind_groups)
+    }
+
+    /// Recreates the targets at a new size.
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.depth, self.occlusion, self.bind_groups) =
+            Self::create_targets(device, &self.bind_group_layout, &self.uniform_buffer, width, height);
+        self.stale = true;
+    }
+
+    pub(crate) fn settings(&self) -> Option<SsaoSettings> {
+        self.settings
+    }
+
+    pub(crate) fn set_settings(&mut self, settings: Option<SsaoSettings>) {
+        if settings.is_none() && self.settings.is_some() {
+            self.stale = true;
+        }
+        self.settings = settings;
+    }
+
+    /// Depth buffer the opaque meshes are drawn into before the main pass.
+    pub(crate) fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth
+    }
+
+    /// Occlusion of each pixel, read by the main pass.
+    pub(crate) fn occlusion_view(&self) -> &wgpu::TextureView {
+        &self.occlusion[0]
+    }
+
+    /// Writes the uniforms for a frame drawn with `projection`.
+    pub(crate) fn update(&self, queue: &wgpu::Queue, projection: Mat4) {
+        let Some(settings) = self.settings else {
+            return;
+        };
+        let sample_count = (settings.samples as usize).clamp(1, MAX_SSAO_SAMPLES);
+        let mut kernel = [[0.0; 4]; MAX_SSAO_SAMPLES];
+        for (slot, offset) in kernel.iter_mut().zip(hemisphere_kernel(sample_count)) {
+            *slot = offset.extend(0.0).to_array();
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&SsaoUniforms {
+            projection: projection.to_cols_array_2d(),
+            inverse_projection: projection.inverse().to_cols_array_2d(),
+            kernel,
+            radius: settings.radius,
+            intensity: settings.intensity,
+            bias: settings.bias,
+            sample_count: sample_count as u32,
+        }));
+    }
+
+    /// Computes and blurs the occlusion from the depth buffer, or marks
+    /// every pixel unoccluded while SSAO is off.
+    pub(crate) fn render(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.settings.is_none() {
+            if self.stale {
+                pass(encoder, "Occlusion Clear Pass", &self.occlusion[0], None);
+                self.stale = false;
+            }
+            return;
+        }
+        pass(encoder, "SSAO Pass", &self.occlusion[0], Some((&self.ssao_pipeline, &self.bind_groups[1])));
+        pass(
+            encoder,
+            "SSAO Horizontal Blur Pass",
+            &self.occlusion[1],
+            Some((&self.horizontal_blur_pipeline, &self.bind_groups[0])),
+        );
+        pass(
+            encoder,
+            "SSAO Vertical Blur Pass",
+            &self.occlusion[0],
+            Some((&self.vertical_blur_pipeline, &self.bind_groups[1])),
+        );
+        self.stale = false;
+    }
+}
+
+/// Draws a fullscreen triangle into `target`, which is cleared to white
+/// first; with no pipeline, the clear is all that happens.
+fn pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    draw: Option<(&wgpu::RenderPipeline, &wgpu::BindGroup)>,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    if let Some((pipeline, bind_group)) = draw {
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
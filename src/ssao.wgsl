@@ -0,0 +1,161 @@
+// Screen-space ambient occlusion shader for IntSar-3D
+
+const MAX_SSAO_SAMPLES: u32 = 64u;
+
+struct SsaoUniforms {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    // Offsets in the unit hemisphere around +Z, in xyz
+    kernel: array<vec4<f32>, MAX_SSAO_SAMPLES>,
+    radius: f32,
+    intensity: f32,
+    bias: f32,
+    sample_count: u32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_2d<f32>;
+
+// Occlusion being blurred
+@group(0) @binding(1)
+var occlusion_texture: texture_2d<f32>;
+
+@group(0) @binding(2)
+var<uniform> ssao: SsaoUniforms;
+
+// Blur taps on either side of the pixel, and how fast their weight drops
+// with distance and with relative depth difference
+const BLUR_RADIUS: i32 = 4;
+const BLUR_SIGMA: f32 = 2.5;
+const BLUR_SHARPNESS: f32 = 16.0;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+fn clamp_pixel(pixel: vec2<i32>) -> vec2<i32> {
+    return clamp(pixel, vec2<i32>(0), vec2<i32>(textureDimensions(depth_texture)) - 1);
+}
+
+// View-space position of the surface at a pixel
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+    let depth = textureLoad(depth_texture, pixel, 0).r;
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(depth_texture));
+    let position = ssao.inverse_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Distance in front of the camera of the surface at a pixel
+fn linear_depth(pixel: vec2<i32>) -> f32 {
+    let position = ssao.inverse_projection * vec4<f32>(0.0, 0.0, textureLoad(depth_texture, pixel, 0).r, 1.0);
+    return -position.z / position.w;
+}
+
+// Of the neighbours on either side, the closer one in depth is more likely
+// to be on the same surface
+fn closest_difference(position: vec3<f32>, before: vec3<f32>, after: vec3<f32>) -> vec3<f32> {
+    if abs(after.z - position.z) < abs(position.z - before.z) {
+        return after - position;
+    }
+    return position - before;
+}
+
+// Jorge Jimenez's interleaved gradient noise, which a small blur averages out
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_ssao(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    // Nothing was drawn here
+    if textureLoad(depth_texture, pixel, 0).r >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(pixel);
+    let dx = closest_difference(
+        position,
+        view_position(clamp_pixel(pixel - vec2<i32>(1, 0))),
+        view_position(clamp_pixel(pixel + vec2<i32>(1, 0))),
+    );
+    let dy = closest_difference(
+        position,
+        view_position(clamp_pixel(pixel + vec2<i32>(0, 1))),
+        view_position(clamp_pixel(pixel - vec2<i32>(0, 1))),
+    );
+    var normal = normalize(cross(dx, dy));
+    if dot(normal, position) > 0.0 {
+        normal = -normal;
+    }
+
+    // Rotate the kernel around the normal by a different angle per pixel
+    let angle = 6.28318530718 * interleaved_gradient_noise(in.position.xy);
+    var tangent = vec3<f32>(cos(angle), sin(angle), 0.0);
+    tangent -= normal * dot(normal, tangent);
+    if dot(tangent, tangent) < 0.0001 {
+        tangent = cross(normal, vec3<f32>(1.0, 0.0, 0.0));
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent);
+
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    var occlusion = 0.0;
+    for (var i = 0u; i < min(ssao.sample_count, MAX_SSAO_SAMPLES); i++) {
+        let offset = ssao.kernel[i].xyz;
+        let sample_position = position + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * ssao.radius;
+
+        let clip = ssao.projection * vec4<f32>(sample_position, 1.0);
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) {
+            continue;
+        }
+        let surface = view_position(vec2<i32>(uv * size));
+
+        // Surfaces far in front of the sample are separate objects, not
+        // creases, and fade out
+        let in_range = smoothstep(0.0, 1.0, ssao.radius / max(abs(position.z - surface.z), 0.0001));
+        if surface.z >= sample_position.z + ssao.bias {
+            occlusion += in_range;
+        }
+    }
+
+    let visible = 1.0 - occlusion / f32(max(ssao.sample_count, 1u));
+    return vec4<f32>(pow(clamp(visible, 0.0, 1.0), ssao.intensity));
+}
+
+// Gaussian blur that skips over depth discontinuities
+fn blur(pixel: vec2<i32>, direction: vec2<i32>) -> f32 {
+    let center = linear_depth(pixel);
+    var total = 0.0;
+    var total_weight = 0.0;
+    for (var i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        let tap = clamp_pixel(pixel + direction * i);
+        let difference = abs(linear_depth(tap) - center) / max(center, 0.0001);
+        let weight = exp(-f32(i * i) / (2.0 * BLUR_SIGMA * BLUR_SIGMA)) * exp(-difference * BLUR_SHARPNESS);
+        total += textureLoad(occlusion_texture, tap, 0).r * weight;
+        total_weight += weight;
+    }
+    return total / total_weight;
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(blur(vec2<i32>(in.position.xy), vec2<i32>(1, 0)));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(blur(vec2<i32>(in.position.xy), vec2<i32>(0, 1)));
+}
@@ -0,0 +1,87 @@
+// Temporal anti-aliasing resolve for IntSar-3D
+
+struct TaaUniforms {
+    // Share of the current frame in the result
+    blend: f32,
+    // Nonzero to ignore the history
+    reset: u32,
+};
+
+// The jittered main pass
+@group(0) @binding(0)
+var current_texture: texture_2d<f32>;
+
+// The previous result
+@group(0) @binding(1)
+var history_texture: texture_2d<f32>;
+
+// Motion since the previous frame, in UV units
+@group(0) @binding(2)
+var velocity_texture: texture_2d<f32>;
+
+@group(0) @binding(3)
+var history_sampler: sampler;
+
+@group(0) @binding(4)
+var<uniform> taa: TaaUniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+struct ResolveOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) history: vec4<f32>,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> ResolveOutput {
+    let size = vec2<i32>(textureDimensions(current_texture));
+    let pixel = vec2<i32>(in.position.xy);
+    let current = textureLoad(current_texture, pixel, 0).rgb;
+
+    // History outside the colors around the pixel this frame belongs to
+    // something that has since moved or been uncovered
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbor = textureLoad(current_texture, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1), 0).rgb;
+            low = min(low, neighbor);
+            high = max(high, neighbor);
+        }
+    }
+
+    let previous_uv = in.uv - textureLoad(velocity_texture, pixel, 0).xy;
+    let history = clamp(textureSampleLevel(history_texture, history_sampler, previous_uv, 0.0).rgb, low, high);
+    let on_screen = all(previous_uv >= vec2<f32>(0.0)) && all(previous_uv <= vec2<f32>(1.0));
+
+    var color = current;
+    if taa.reset == 0u && on_screen {
+        // Weighing by inverse luminance keeps bright HDR samples from
+        // dominating the average and flickering
+        let current_weight = taa.blend / (1.0 + luminance(current));
+        let history_weight = (1.0 - taa.blend) / (1.0 + luminance(history));
+        color = (current * current_weight + history * history_weight) / (current_weight + history_weight);
+    }
+
+    var out: ResolveOutput;
+    out.color = vec4<f32>(color, 1.0);
+    out.history = out.color;
+    return out;
+}
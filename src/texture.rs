@@ -0,0 +1,445 @@
+// Texture module for IntSar-3D
+//
+// Decodes PNG, JPEG and Radiance HDR images into RGBA texels with a full mip
+// chain, ready to upload to the GPU. Color textures are stored as sRGB so the
+// GPU linearizes them when sampling; data textures (normals, roughness, ...)
+// stay linear, as do HDR images, which are stored as half floats.
+
+use crate::material::TextureSource;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How the texels of an 8-bit texture are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors, such as base color and emissive maps.
+    Srgb,
+    /// Non-color data, such as normal and metallic-roughness maps.
+    Linear,
+}
+
+/// Error produced while loading a texture.
+#[derive(Debug)]
+pub enum TextureError {
+    /// The image file couldn't be read.
+    Io { path: PathBuf, source: std::io::Error },
+    /// The image data isn't in a supported format or is corrupt.
+    Decode { name: String, source: image::ImageError },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            TextureError::Decode { name, source } => write!(f, "failed to decode {name}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decode { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Texel filtering of a [`Sampler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl From<Filter> for wgpu::FilterMode {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => wgpu::FilterMode::Nearest,
+            Filter::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// How a [`Sampler`] handles texture coordinates outside `0..1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AddressMode {
+    #[default]
+    Repeat,
+    MirrorRepeat,
+    ClampToEdge,
+}
+
+impl From<AddressMode> for wgpu::AddressMode {
+    fn from(mode: AddressMode) -> Self {
+        match mode {
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+/// How the textures of a material are sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sampler {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    /// Filtering between mip levels.
+    pub mipmap_filter: Filter,
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    /// Maximum anisotropy, from 1 (off) to 16. Only takes effect when all
+    /// three filters are linear.
+    pub anisotropy: u16,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_filter: Filter::Linear,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Repeat,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl Sampler {
+    pub(crate) fn create(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        // wgpu rejects anisotropic samplers with any nearest filter
+        let all_linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|&filter| filter == Filter::Linear);
+        let anisotropy = if all_linear { self.anisotropy.clamp(1, 16) } else { 1 };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Material Sampler"),
+            address_mode_u: self.address_u.into(),
+            address_mode_v: self.address_v.into(),
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: self.mag_filter.into(),
+            min_filter: self.min_filter.into(),
+            mipmap_filter: self.mipmap_filter.into(),
+            anisotropy_clamp: anisotropy,
+            ..Default::default()
+        })
+    }
+}
+
+/// Decoded RGBA texels of a 2D texture and its mip chain.
+#[derive(Debug, Clone)]
+pub struct TextureData {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    /// Texels of each mip level, largest first.
+    levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// Loads and decodes an image, detecting its format from its contents.
+    ///
+    /// `color_space` applies to 8-bit images; HDR images are always linear.
+    pub fn load(source: &TextureSource, color_space: ColorSpace) -> Result<Self, TextureError> {
+        let (name, decoded) = match source {
+            TextureSource::File(path) => {
+                let data = std::fs::read(path).map_err(|source| TextureError::Io {
+                    path: path.clone(),
+                    source,
+                })?;
+                (path.display().to_string(), image::load_from_memory(&data))
+            }
+            TextureSource::Embedded { mime_type, data } => {
+                let name = format!("embedded {} image", mime_type.as_deref().unwrap_or("unknown"));
+                (name, image::load_from_memory(data))
+            }
+        };
+        let image = decoded.map_err(|source| TextureError::Decode { name, source })?;
+        Ok(Self::from_image(image, color_space))
+    }
+
+    /// Converts a decoded image to RGBA texels and generates its mip chain.
+    pub fn from_image(image: image::DynamicImage, color_space: ColorSpace) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let hdr = matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+
+        if hdr {
+            let texels: Vec<[f32; 4]> = image.into_rgba32f().pixels().map(|pixel| pixel.0).collect();
+            let levels = mip_chain(width, height, texels)
+                .into_iter()
+                .map(|level| level.iter().flatten().flat_map(|&value| f16_bits(value).to_le_bytes()).collect())
+                .collect();
+            return Self {
+                width,
+                height,
+                format: wgpu::TextureFormat::Rgba16Float,
+                levels,
+            };
+        }
+
+        let base = image.into_rgba8().into_raw();
+        let (format, decode, encode): (_, DecodeChannel, EncodeChannel) = match color_space {
+            ColorSpace::Srgb => (wgpu::TextureFormat::Rgba8UnormSrgb, srgb_to_linear, linear_to_srgb),
+            ColorSpace::Linear => (wgpu::TextureFormat::Rgba8Unorm, unorm_to_f32, f32_to_unorm),
+        };
+
+        // Average in linear space; alpha is never sRGB encoded
+        let texels: Vec<[f32; 4]> = base
+            .chunks_exact(4)
+            .map(|texel| [decode(texel[0]), decode(texel[1]), decode(texel[2]), unorm_to_f32(texel[3])])
+            .collect();
+        let mut levels: Vec<Vec<u8>> = mip_chain(width, height, texels)
+            .into_iter()
+            .skip(1)
+            .map(|level| {
+                level
+                    .iter()
+                    .flat_map(|texel| [encode(texel[0]), encode(texel[1]), encode(texel[2]), f32_to_unorm(texel[3])])
+                    .collect()
+            })
+            .collect();
+        // Keep the original texels of the top level rather than round-tripping them
+        levels.insert(0, base);
+
+        Self {
+            width,
+            height,
+            format,
+            levels,
+        }
+    }
+
+    /// Width of the top mip level in texels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the top mip level in texels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// GPU format of the texels.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Number of mip levels, down to 1x1.
+    pub fn mip_level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Texels of a mip level, row by row without padding.
+    pub fn level(&self, level: u32) -> &[u8] {
+        &self.levels[level as usize]
+    }
+
+    /// Creates a GPU texture and uploads every mip level.
+    pub(crate) fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> wgpu::Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: self.mip_level_count(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let texel_size = self.format.block_copy_size(None).expect("texture format has a fixed texel size");
+        for (level, texels) in self.levels.iter().enumerate() {
+            let width = (self.width >> level).max(1);
+            let height = (self.height >> level).max(1);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                texels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * texel_size),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        texture
+    }
+}
+
+/// GPU textures by source, so materials sharing an image upload it once.
+#[derive(Default)]
+pub(crate) struct TextureCache {
+    entries: Vec<CachedTexture>,
+}
+
+struct CachedTexture {
+    source: TextureSource,
+    color_space: ColorSpace,
+    /// `None` if the texture failed to load.
+    view: Option<Arc<wgpu::TextureView>>,
+}
+
+impl TextureCache {
+    /// Returns a view of the texture, loading it on first use. Load failures
+    /// are logged once and give `None`.
+    pub(crate) fn get(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &TextureSource,
+        color_space: ColorSpace,
+    ) -> Option<Arc<wgpu::TextureView>> {
+        if let Some(entry) = self.entries.iter().find(|entry| entry.color_space == color_space && entry.source == *source) {
+            return entry.view.clone();
+        }
+
+        let view = match TextureData::load(source, color_space) {
+            Ok(data) => {
+                let texture = data.upload(device, queue, "Material Texture");
+                Some(Arc::new(texture.create_view(&wgpu::TextureViewDescriptor::default())))
+            }
+            Err(err) => {
+                log::warn!("{err}");
+                None
+            }
+        };
+        self.entries.push(CachedTexture {
+            source: source.clone(),
+            color_space,
+            view: view.clone(),
+        });
+        view
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Halves `texels` repeatedly with a box filter, down to a single texel.
+/// The first level returned is `texels` itself.
+fn mip_chain(width: u32, height: u32, texels: Vec<[f32; 4]>) -> Vec<Vec<[f32; 4]>> {
+    let (mut width, mut height) = (width as usize, height as usize);
+    let mut levels = vec![texels];
+    while width > 1 || height > 1 {
+        let source = levels.last().unwrap();
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut level = Vec::with_capacity(next_width * next_height);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                let mut sum = [0.0; 4];
+                for (sy, y_weight) in box_taps(height, y) {
+                    for (sx, x_weight) in box_taps(width, x) {
+                        for (total, value) in sum.iter_mut().zip(source[sy * width + sx]) {
+                            *total += value * x_weight * y_weight;
+                        }
+                    }
+                }
+                level.push(sum);
+            }
+        }
+        levels.push(level);
+        (width, height) = (next_width, next_height);
+    }
+    levels
+}
+
+/// Source texels and weights that texel `index` of the next mip level
+/// averages along an axis `size` texels long. An odd axis is shrunk over
+/// three texels at a time, weighted so that every source texel counts
+/// equally and the image does not shift. Unused taps have no weight.
+fn box_taps(size: usize, index: usize) -> [(usize, f32); 3] {
+    let first = 2 * index;
+    if size == 1 {
+        [(0, 1.0), (0, 0.0), (0, 0.0)]
+    } else if size.is_multiple_of(2) {
+        [(first, 0.5), (first + 1, 0.5), (first, 0.0)]
+    } else {
+        let (half, size, index) = ((size / 2) as f32, size as f32, index as f32);
+        [
+            (first, (half - index) / size),
+            (first + 1, half / size),
+            (first + 2, (index + 1.0) / size),
+        ]
+    }
+}
+
+/// Converts an 8-bit color channel to a linear value.
+type DecodeChannel = fn(u8) -> f32;
+/// Converts a linear value to an 8-bit color channel.
+type EncodeChannel = fn(f32) -> u8;
+
+fn unorm_to_f32(value: u8) -> f32 {
+    value as f32 / 255.0
+}
+
+fn f32_to_unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = unorm_to_f32(value);
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    f32_to_unorm(encoded)
+}
+
+/// Converts to IEEE half precision, rounding to nearest and flushing values
+/// too small for a half to zero.
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        return sign;
+    }
+    let half = sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16;
+    // Round to nearest; a carry into the exponent is still correct
+    if mantissa & 0x1000 != 0 {
+        half + 1
+    } else {
+        half
+    }
+}
@@ -0,0 +1,484 @@
+// Tone mapping for IntSar-3D
+//
+// The main pass renders linear HDR color into an `Rgba16Float` target. After
+// the HDR post-processing effects, the tone mapping pass scales it by the
+// exposure, compresses it into display range with the selected operator and
+// writes it to the surface, which takes care of sRGB encoding.
+//
+// Auto exposure sorts the frame's pixels into a histogram of log2 luminance
+// on the GPU. A second pass averages the histogram between two percentiles,
+// so a few very dark or very bright pixels don't swing the result, and eases
+// the exposure towards the one that brings that average to middle grey. The
+// exposure never leaves the GPU.
+
+use bytemuck::{Pod, Zeroable};
+use std::time::Instant;
+
+/// Format of the HDR target the main pass renders into.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Number of bins in the auto exposure luminance histogram.
+pub const HISTOGRAM_BINS: usize = 256;
+
+/// Width and height of the pixel tiles the histogram pass works through.
+const HISTOGRAM_TILE: u32 = 16;
+
+/// Curve that maps exposed HDR color into display range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Clips every channel at 1.0, as if there were no tone mapping.
+    Clamp,
+    /// Reinhard's `l / (1 + l)` applied to luminance, which keeps hues but
+    /// never quite reaches white.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a toe and a shoulder
+    /// that saturate bright colors.
+    #[default]
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates towards white as colors get
+    /// brighter instead of skewing their hue.
+    AgX,
+}
+
+impl ToneMapping {
+    /// Index of the operator in the tone mapping shader.
+    fn index(self) -> u32 {
+        match self {
+            ToneMapping::Clamp => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+            ToneMapping::AgX => 3,
+        }
+    }
+}
+
+/// How bright the scene is made before tone mapping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    /// Scales the scene by `2^stops`; zero leaves it unchanged.
+    Manual { stops: f32 },
+    /// Adapts to the average luminance of each frame.
+    Auto(AutoExposure),
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::Manual { stops: 0.0 }
+    }
+}
+
+/// Settings of [`Exposure::Auto`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+    /// Log2 of the darkest luminance the histogram tells apart. Darker
+    /// pixels count as this dark.
+    pub min_log_luminance: f32,
+    /// Log2 of the brightest luminance the histogram tells apart.
+    pub max_log_luminance: f32,
+    /// Fraction of the darkest pixels left out of the average.
+    pub low_percentile: f32,
+    /// Fraction of pixels, from the darkest, up to which the average is taken.
+    pub high_percentile: f32,
+    /// Stops added to the adapted exposure.
+    pub compensation: f32,
+    /// Stops per second the exposure rises by when the scene gets darker.
+    pub speed_brighten: f32,
+    /// Stops per second the exposure falls by when the scene gets brighter.
+    pub speed_darken: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -8.0,
+            max_log_luminance: 8.0,
+            low_percentile: 0.1,
+            high_percentile: 0.9,
+            compensation: 0.0,
+            speed_brighten: 3.0,
+            speed_darken: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct ToneMapUniforms {
+    curve: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct AutoExposureUniforms {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    compensation: f32,
+    speed_brighten: f32,
+    speed_darken: f32,
+    delta_time: f32,
+    /// Nonzero to jump straight to the measured exposure.
+    reset: u32,
+    _padding: [u32; 3],
+}
+
+impl AutoExposureUniforms {
+    fn new(settings: &AutoExposure, delta_time: Option<f32>) -> Self {
+        Self {
+            min_log_luminance: settings.min_log_luminance,
+            log_luminance_range: (settings.max_log_luminance - settings.min_log_luminance).max(f32::EPSILON),
+            low_percentile: settings.low_percentile,
+            high_percentile: settings.high_percentile,
+            compensation: settings.compensation,
+            speed_brighten: settings.speed_brighten,
+            speed_darken: settings.speed_darken,
+            delta_time: delta_time.unwrap_or(0.0),
+            reset: delta_time.is_none() as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Exposure multiplier read by the tone mapping pass.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct ExposureState {
+    exposure: f32,
+    _padding: [f32; 3],
+}
+
+/// Passes that resolve the HDR frame to the surface.
+///
+/// The frame can end up in either of the post-processing HDR targets, so
+/// everything that reads it is bound once for each.
+pub(crate) struct ToneMapper {
+    width: u32,
+    height: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_groups: [wgpu::BindGroup; 2],
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    exposure_buffer: wgpu::Buffer,
+    histogram_bind_group_layout: wgpu::BindGroupLayout,
+    histogram_bind_groups: [wgpu::BindGroup; 2],
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    histogram_buffer: wgpu::Buffer,
+    auto_exposure_buffer: wgpu::Buffer,
+    /// When auto exposure last adapted; `None` until it has measured a frame.
+    last_adapted: Option<Instant>,
+}
+
+impl ToneMapper {
+    /// Creates passes resolving either of the `width`x`height` HDR `inputs`
+    /// into `output_format`.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        inputs: [&wgpu::TextureView; 2],
+        width: u32,
+        height: u32,
+    ) -> Self {
+        use wgpu::util::DeviceExt;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tone Map Uniform Buffer"),
+            size: std::mem::size_of::<ToneMapUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Written by the average pass with auto exposure, and by us otherwise
+        let exposure_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure Buffer"),
+            contents: bytemuck::bytes_of(&ExposureState { exposure: 1.0, _padding: [0.0; 3] }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let histogram_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Luminance Histogram Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let auto_exposure_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure Uniform Buffer"),
+            size: std::mem::size_of::<AutoExposureUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let hdr_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tone Map Bind Group Layout"),
+            entries: &[
+                hdr_entry(0, wgpu::ShaderStages::FRAGMENT),
+                buffer_entry(1, wgpu::ShaderStages::FRAGMENT, wgpu::BufferBindingType::Uniform),
+                buffer_entry(2, wgpu::ShaderStages::FRAGMENT, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        let histogram_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Auto Exposure Bind Group Layout"),
+            entries: &[
+                buffer_entry(0, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Uniform),
+                hdr_entry(1, wgpu::ShaderStages::COMPUTE),
+                buffer_entry(2, wgpu::ShaderStages::COMPUTE, storage),
+                buffer_entry(3, wgpu::ShaderStages::COMPUTE, storage),
+            ],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Tone Map Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("tonemap.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tone Map Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone Map Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let exposure_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Auto Exposure Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("exposure.wgsl").into()),
+        });
+        let exposure_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Auto Exposure Pipeline Layout"),
+            bind_group_layouts: &[&histogram_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&exposure_layout),
+                module: &exposure_module,
+                entry_point,
+            })
+        };
+        let histogram_pipeline = create_compute_pipeline("Luminance Histogram Pipeline", "cs_histogram");
+        let average_pipeline = create_compute_pipeline("Average Luminance Pipeline", "cs_average");
+
+        let bind_groups = inputs.map(|input| {
+            Self::create_bind_group(device, &bind_group_layout, input, &uniform_buffer, &exposure_buffer)
+        });
+        let histogram_bind_groups = inputs.map(|input| {
+            Self::create_histogram_bind_group(
+                device,
+                &histogram_bind_group_layout,
+                input,
+                &auto_exposure_buffer,
+                &histogram_buffer,
+                &exposure_buffer,
+            )
+        });
+
+        Self {
+            width,
+            height,
+            bind_group_layout,
+            bind_groups,
+            pipeline,
+            uniform_buffer,
+            exposure_buffer,
+            histogram_bind_group_layout,
+            histogram_bind_groups,
+            histogram_pipeline,
+            average_pipeline,
+            histogram_buffer,
+            auto_exposure_buffer,
+            last_adapted: None,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr_view: &wgpu::TextureView,
+        uniform_buffer: &wgpu::Buffer,
+        exposure_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tone Map Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: exposure_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_histogram_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr_view: &wgpu::TextureView,
+        auto_exposure_buffer: &wgpu::Buffer,
+        histogram_buffer: &wgpu::Buffer,
+        exposure_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Auto Exposure Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: auto_exposure_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: exposure_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Rebinds to new HDR targets, such as after a resize. The adapted
+    /// exposure is kept.
+    pub(crate) fn set_inputs(&mut self, device: &wgpu::Device, inputs: [&wgpu::TextureView; 2], width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.bind_groups = inputs.map(|input| {
+            Self::create_bind_group(device, &self.bind_group_layout, input, &self.uniform_buffer, &self.exposure_buffer)
+        });
+        self.histogram_bind_groups = inputs.map(|input| {
+            Self::create_histogram_bind_group(
+                device,
+                &self.histogram_bind_group_layout,
+                input,
+                &self.auto_exposure_buffer,
+                &self.histogram_buffer,
+                &self.exposure_buffer,
+            )
+        });
+    }
+
+    /// Exposes and tone maps HDR input `input` into `output`.
+    ///
+    /// Auto exposure measures the frame being resolved, so the first frame
+    /// after switching to it is already exposed correctly; later frames ease
+    /// towards their measurement by the settings' speeds.
+    pub(crate) fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        input: usize,
+        output: &wgpu::TextureView,
+        tone_mapping: ToneMapping,
+        exposure: &Exposure,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&ToneMapUniforms {
+            curve: tone_mapping.index(),
+            _padding: [0; 3],
+        }));
+
+        match exposure {
+            Exposure::Manual { stops } => {
+                queue.write_buffer(&self.exposure_buffer, 0, bytemuck::bytes_of(&ExposureState {
+                    exposure: stops.exp2(),
+                    _padding: [0.0; 3],
+                }));
+                self.last_adapted = None;
+            }
+            Exposure::Auto(settings) => {
+                let now = Instant::now();
+                let delta_time = self.last_adapted.map(|last| (now - last).as_secs_f32());
+                self.last_adapted = Some(now);
+                queue.write_buffer(
+                    &self.auto_exposure_buffer,
+                    0,
+                    bytemuck::bytes_of(&AutoExposureUniforms::new(settings, delta_time)),
+                );
+
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Auto Exposure Pass"),
+                    timestamp_writes: None,
+                });
+                pass.set_bind_group(0, &self.histogram_bind_groups[input], &[]);
+                pass.set_pipeline(&self.histogram_pipeline);
+                pass.dispatch_workgroups(
+                    self.width.div_ceil(HISTOGRAM_TILE),
+                    self.height.div_ceil(HISTOGRAM_TILE),
+                    1,
+                );
+                pass.set_pipeline(&self.average_pipeline);
+                pass.dispatch_workgroups(1, 1, 1);
+            }
+        }
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tone Map Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_groups[input], &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,103 @@
+// Tone mapping shader for IntSar-3D
+
+@group(0) @binding(0)
+var hdr: texture_2d<f32>;
+
+struct ToneMapUniforms {
+    curve: u32,
+};
+
+@group(0) @binding(1)
+var<uniform> tone_map: ToneMapUniforms;
+
+struct ExposureState {
+    exposure: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> exposure: ExposureState;
+
+const CLAMP: u32 = 0u;
+const REINHARD: u32 = 1u;
+const ACES: u32 = 2u;
+const AGX: u32 = 3u;
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    return color * (1.0 / (1.0 + l));
+}
+
+// Krzysztof Narkowicz, "ACES Filmic Tone Mapping Curve"
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let x = color * 0.6;
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+// Benjamin Wrensch's fit of AgX's default look
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let encoded = clamp(log2(max(inset * color, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    let curved = agx_contrast((encoded - min_ev) / (max_ev - min_ev));
+    // The curve's output is display encoded; the surface encodes it again
+    return pow(max(outset * curved, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = max(textureLoad(hdr, vec2<i32>(position.xy), 0).rgb, vec3<f32>(0.0)) * exposure.exposure;
+
+    var mapped: vec3<f32>;
+    switch tone_map.curve {
+        case REINHARD: {
+            mapped = reinhard(color);
+        }
+        case ACES: {
+            mapped = aces(color);
+        }
+        case AGX: {
+            mapped = agx(color);
+        }
+        default: {
+            mapped = color;
+        }
+    }
+    return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,141 @@
+// Tests for clustered light assignment
+
+use approx::assert_relative_eq;
+use glam::{Mat4, Quat, UVec3, Vec3};
+use intsar_3d::cluster::{self, CLUSTER_COUNT, CLUSTER_GRID, MAX_LIGHTS_PER_CLUSTER};
+use intsar_3d::light::{Light, LightKind};
+use intsar_3d::math::Transform;
+use intsar_3d::primitives;
+use intsar_3d::renderer::Renderer;
+use intsar_3d::scene::SceneObject;
+use intsar_3d::shadow::Frustum;
+
+fn frustum() -> Frustum {
+    Frustum {
+        view: Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y),
+        fov_y: 45.0_f32.to_radians(),
+        aspect_ratio: 16.0 / 9.0,
+        near: 0.1,
+        far: 100.0,
+    }
+}
+
+fn point_light(range: f32) -> Light {
+    let mut light = Light::new(LightKind::Point, 1.0);
+    light.range = Some(range);
+    light
+}
+
+#[test]
+fn cluster_indices_round_trip() {
+    for index in [0, 1, 15, 16, 143, 144, CLUSTER_COUNT - 1] {
+        assert_eq!(cluster::cluster_index(cluster::cluster_position(index)), index);
+    }
+    assert_eq!(cluster::cluster_position(CLUSTER_COUNT - 1), CLUSTER_GRID - UVec3::ONE);
+}
+
+#[test]
+fn clusters_tile_the_frustum() {
+    let frustum = frustum();
+    let (first_min, first_max) = cluster::cluster_bounds(&frustum, UVec3::ZERO);
+    assert_relative_eq!(first_max.z, -0.1, epsilon = 1e-6);
+    let (last_min, _) = cluster::cluster_bounds(&frustum, CLUSTER_GRID - UVec3::ONE);
+    assert_relative_eq!(last_min.z, -100.0, epsilon = 1e-3);
+
+    // Slices meet, and grow deeper with distance
+    for slice in 1..CLUSTER_GRID.z {
+        let (near_min, near_max) = cluster::cluster_bounds(&frustum, UVec3::new(0, 0, slice - 1));
+        let (far_min, far_max) = cluster::cluster_bounds(&frustum, UVec3::new(0, 0, slice));
+        assert_relative_eq!(near_min.z, far_max.z, max_relative = 1e-5);
+        assert!(far_max.z - far_min.z > near_max.z - near_min.z);
+    }
+
+    // Row 0 is at the top left of the screen
+    assert!(first_min.x < 0.0 && first_min.y > 0.0, "{first_min} {first_max}");
+}
+
+#[test]
+fn lights_reach_the_clusters_around_them() {
+    let frustum = frustum();
+    let ahead = Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0));
+    let behind = Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0));
+    let sun = Light::new(LightKind::Directional, 1.0);
+    let lights = [(point_light(1.0), ahead), (point_light(1.0), behind), (sun, Mat4::IDENTITY)];
+    let assigned = cluster::assign_lights(&frustum, &lights);
+    assert_eq!(assigned.len(), CLUSTER_COUNT);
+
+    // The light ahead reaches only clusters near the middle of the screen,
+    // including the one it is in
+    let (near, far) = (frustum.near, frustum.far);
+    let slice = ((10.0f32 / near).ln() / (far / near).ln() * CLUSTER_GRID.z as f32) as u32;
+    let middle = cluster::cluster_index(UVec3::new(CLUSTER_GRID.x / 2, CLUSTER_GRID.y / 2, slice));
+    assert_eq!(assigned[middle], vec![0, 2]);
+    for (index, lights) in assigned.iter().enumerate() {
+        let position = cluster::cluster_position(index);
+        if lights.contains(&0) {
+            assert!(position.x.abs_diff(CLUSTER_GRID.x / 2) <= 2, "{position}");
+            assert!(position.y.abs_diff(CLUSTER_GRID.y / 2) <= 2, "{position}");
+            assert!(position.z.abs_diff(slice) <= 1, "{position}");
+        }
+        // Nothing behind the camera is in view, and the sun is everywhere
+        assert!(!lights.contains(&1));
+        assert!(lights.contains(&2));
+    }
+}
+
+#[test]
+fn clusters_keep_the_first_lights() {
+    let sun = (Light::new(LightKind::Directional, 1.0), Mat4::IDENTITY);
+    let lights = vec![sun; MAX_LIGHTS_PER_CLUSTER + 10];
+    let assigned = cluster::assign_lights(&frustum(), &lights);
+    let expected: Vec<u32> = (0..MAX_LIGHTS_PER_CLUSTER as u32).collect();
+    assert!(assigned.iter().all(|lights| *lights == expected));
+}
+
+#[test]
+fn compute_pass_matches_reference() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let Some(mut renderer) = runtime.block_on(Renderer::new_headless(160, 90)) else {
+        eprintln!("Skipping light cluster test: no graphics adapter available");
+        return;
+    };
+
+    // Three hundred lights of every kind scattered around the camera, some
+    // in view and some behind it
+    let scene = renderer.scene_mut();
+    let floor = scene.add_mesh(primitives::plane(20.0, 20.0, 1, 1));
+    scene.add_object(SceneObject::new("Floor".to_string(), Transform::identity()).with_mesh(floor));
+    for index in 0..300 {
+        let kind = if index % 100 == 50 {
+            LightKind::Directional
+        } else if index % 7 == 0 {
+            LightKind::Spot { inner_cone_angle: 0.3, outer_cone_angle: 0.5 }
+        } else {
+            LightKind::Point
+        };
+        let mut light = Light::new(kind, 0.01 + (index % 11) as f32 * 0.002);
+        light.range = (index % 10 != 0).then_some(0.5 + (index % 5) as f32 * 0.5);
+        let angle = index as f32 * 2.399_963;
+        let distance = 1.0 + (index as f32 * 0.618_034).fract() * 12.0;
+        let position = Vec3::new(angle.cos() * distance, (index % 4) as f32 - 1.0, angle.sin() * distance);
+        let transform = Transform::new(position, Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new(format!("Light {index}"), transform).with_light(light));
+    }
+    renderer.set_camera_position(Vec3::new(0.0, 3.0, 8.0));
+    renderer.render();
+
+    let lights: Vec<(Light, Mat4)> = renderer.scene().world_matrices()
+        .into_iter()
+        .filter_map(|(handle, world)| Some((*renderer.scene().component::<Light>(handle)?, world)))
+        .collect();
+    let expected = cluster::assign_lights(&renderer.camera_frustum(), &lights);
+    let actual = renderer.read_light_clusters().expect("Failed to read light clusters");
+    assert_eq!(actual.len(), CLUSTER_COUNT);
+    for (index, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+        assert_eq!(actual, expected, "cluster {}", cluster::cluster_position(index));
+    }
+
+    // Clusters are reached by only a few of the lights
+    let average = expected.iter().map(Vec::len).sum::<usize>() as f32 / CLUSTER_COUNT as f32;
+    assert!(average < 15.0, "{average} lights per cluster");
+}
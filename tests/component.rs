@@ -0,0 +1,112 @@
+// Tests for component storage on scene objects
+
+use intsar_3d::component::Component;
+use intsar_3d::light::{Light, LightKind};
+use intsar_3d::material::{Material, MaterialHandle};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::{Mesh, MeshHandle};
+use intsar_3d::scene::{Scene, SceneObject};
+
+#[derive(Debug, PartialEq)]
+struct Health(u32);
+
+#[derive(Debug, PartialEq)]
+struct Spin(f32);
+
+impl Component for Health {}
+impl Component for Spin {}
+
+fn object(name: &str) -> SceneObject {
+    SceneObject::new(name.to_string(), Transform::identity())
+}
+
+#[test]
+fn custom_components_can_be_inserted_replaced_and_removed() {
+    let mut scene = Scene::new();
+    let handle = scene.add_object(object("Player").with_component(Health(10)));
+
+    assert_eq!(scene.component::<Health>(handle), Some(&Health(10)));
+    assert!(!scene.has_component::<Spin>(handle));
+
+    assert_eq!(scene.insert_component(handle, Health(5)), Ok(Some(Health(10))));
+    scene.component_mut::<Health>(handle).unwrap().0 += 1;
+    assert_eq!(scene.component::<Health>(handle), Some(&Health(6)));
+
+    assert_eq!(scene.remove_component::<Health>(handle), Some(Health(6)));
+    assert_eq!(scene.remove_component::<Health>(handle), None);
+    assert!(!scene.has_component::<Health>(handle));
+}
+
+#[test]
+fn queries_match_objects_with_every_component() {
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::cube());
+    let material = scene.add_material(Material::new("Red".to_string()));
+    let full = scene.add_object(object("Full").with_mesh(mesh).with_material(material));
+    let bare = scene.add_object(object("Bare").with_mesh(mesh));
+    scene.add_object(object("Empty"));
+
+    let drawn: Vec<_> = scene.query::<(&MeshHandle, &MaterialHandle)>().collect();
+    assert_eq!(drawn, [(full, (&mesh, &material))]);
+
+    let meshes: Vec<_> = scene.query::<&MeshHandle>().map(|(handle, _)| handle).collect();
+    assert_eq!(meshes, [full, bare]);
+
+    scene.insert_component(bare, Spin(0.0)).unwrap();
+    for (_, spin) in scene.query_mut::<&mut Spin>() {
+        spin.0 += 1.0;
+    }
+    assert_eq!(scene.component::<Spin>(bare), Some(&Spin(1.0)));
+}
+
+#[test]
+fn removed_objects_lose_their_components() {
+    let mut scene = Scene::new();
+    let parent = scene.add_object(object("Parent").with_component(Health(1)));
+    let child = scene.add_object(object("Child").with_parent(parent).with_component(Health(2)));
+    let other = scene.add_object(object("Other").with_component(Health(3)));
+
+    scene.remove_object(parent);
+    assert_eq!(scene.component::<Health>(child), None);
+    assert_eq!(scene.component::<Health>(other), Some(&Health(3)));
+    assert_eq!(scene.query::<&Health>().count(), 1);
+
+    // A new object in the reused slot starts without components
+    let reused = scene.add_object(object("Reused"));
+    assert_eq!(scene.component::<Health>(reused), None);
+    assert_eq!(scene.insert_component(parent, Health(4)), Err(Health(4)));
+    assert_eq!(scene.component::<Health>(reused), None);
+}
+
+#[test]
+fn mutable_queries_read_some_components_while_changing_others() {
+    let mut scene = Scene::new();
+    let fast = scene.add_object(object("Fast").with_component(Spin(2.0)).with_light(Light::new(LightKind::Point, 1.0)));
+    let slow = scene.add_object(object("Slow").with_component(Spin(0.5)).with_light(Light::new(LightKind::Point, 1.0)));
+    scene.add_object(object("Unlit").with_component(Spin(3.0)));
+
+    for (_, (light, spin)) in scene.query_mut::<(&mut Light, &Spin)>() {
+        light.intensity *= spin.0;
+    }
+    assert_eq!(scene.component::<Light>(fast).unwrap().intensity, 2.0);
+    assert_eq!(scene.component::<Light>(slow).unwrap().intensity, 0.5);
+
+    // Several components can be changed at once
+    for (_, (light, spin)) in scene.query_mut::<(&mut Light, &mut Spin)>() {
+        light.range = Some(spin.0);
+        spin.0 = 0.0;
+    }
+    assert_eq!(scene.component::<Light>(slow).unwrap().range, Some(0.5));
+    assert_eq!(scene.component::<Spin>(fast), Some(&Spin(0.0)));
+
+    // Types nobody has match nothing
+    assert_eq!(scene.query_mut::<(&mut Light, &Health)>().count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "more than once")]
+fn mutable_queries_reject_repeated_types() {
+    let mut scene = Scene::new();
+    scene.add_object(object("Spinner").with_component(Spin(1.0)));
+    let _ = scene.query_mut::<(&mut Spin, &Spin)>().count();
+}
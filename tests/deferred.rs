@@ -0,0 +1,54 @@
+// Tests for deferred light volumes
+
+use approx::assert_relative_eq;
+use glam::{Mat4, Vec3};
+use intsar_3d::deferred::{self, LIGHT_CUTOFF};
+use intsar_3d::light::{Light, LightKind};
+
+fn view_proj() -> Mat4 {
+    let projection = Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, 0.1, 100.0);
+    projection * Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y)
+}
+
+#[test]
+fn light_radius_ends_where_light_fades_out() {
+    let mut light = Light::new(LightKind::Point, 4.0);
+    light.color = Vec3::new(0.25, 1.0, 0.5);
+    let radius = deferred::light_radius(&light).unwrap();
+    assert_relative_eq!(radius, (4.0 / LIGHT_CUTOFF).sqrt());
+
+    // A closer range wins, a further one does not
+    light.range = Some(3.0);
+    assert_relative_eq!(deferred::light_radius(&light).unwrap(), 3.0);
+    light.range = Some(1000.0);
+    assert_relative_eq!(deferred::light_radius(&light).unwrap(), radius);
+
+    assert_eq!(deferred::light_radius(&Light::new(LightKind::Directional, 1.0)), None);
+}
+
+#[test]
+fn scissor_rect_bounds_light_volume() {
+    // A small sphere straight ahead stays near the middle of the target
+    let [x, y, width, height] = deferred::scissor_rect(view_proj(), Vec3::new(0.0, 0.0, -10.0), 1.0, 100, 100).unwrap();
+    assert!(x > 30 && y > 30 && x + width < 70 && y + height < 70, "{x} {y} {width} {height}");
+    assert!(x < 50 && y < 50 && x + width > 50 && y + height > 50, "{x} {y} {width} {height}");
+
+    // Up and to the right on screen is the top right of the target
+    let [x, y, width, height] = deferred::scissor_rect(view_proj(), Vec3::new(5.0, 5.0, -10.0), 1.0, 100, 100).unwrap();
+    assert!(x > 50 && y + height < 50, "{x} {y} {width} {height}");
+
+    // Partly off screen is clamped to the target
+    let [x, y, width, height] = deferred::scissor_rect(view_proj(), Vec3::new(10.0, 0.0, -10.0), 2.0, 100, 100).unwrap();
+    assert!(x > 50 && x + width == 100 && y + height <= 100, "{x} {y} {width} {height}");
+}
+
+#[test]
+fn scissor_rect_of_lights_around_camera() {
+    // Reaching behind the camera covers the whole target
+    let rect = deferred::scissor_rect(view_proj(), Vec3::new(0.0, 0.0, -0.5), 1.0, 100, 80);
+    assert_eq!(rect, Some([0, 0, 100, 80]));
+
+    // Entirely behind it or off to the side covers nothing
+    assert_eq!(deferred::scissor_rect(view_proj(), Vec3::new(0.0, 0.0, 10.0), 1.0, 100, 80), None);
+    assert_eq!(deferred::scissor_rect(view_proj(), Vec3::new(50.0, 0.0, -10.0), 1.0, 100, 80), None);
+}
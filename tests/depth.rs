@@ -0,0 +1,68 @@
+// Tests for depth testing in the main pass
+
+use glam::{Quat, Vec3, Vec4};
+use intsar_3d::material::Material;
+use intsar_3d::math::Transform;
+use intsar_3d::primitives;
+use intsar_3d::renderer::{DepthCompareError, Renderer};
+use intsar_3d::scene::SceneObject;
+
+/// Adds a glowing cube of one color.
+fn add_cube(renderer: &mut Renderer, name: &str, position: Vec3, size: f32, color: Vec3) {
+    let scene = renderer.scene_mut();
+    let mesh = scene.add_mesh(primitives::cube(size, 1));
+    let mut material = Material::new(name.to_string());
+    material.base_color = Vec4::new(0.0, 0.0, 0.0, 1.0);
+    material.emissive = color;
+    let material = scene.add_material(material);
+    let transform = Transform::new(position, Quat::IDENTITY, Vec3::ONE);
+    scene.add_object(SceneObject::new(name.to_string(), transform).with_mesh(mesh).with_material(material));
+}
+
+fn center_pixel(renderer: &mut Renderer) -> [u8; 4] {
+    renderer.render();
+    let pixels = renderer.read_pixels().expect("Failed to read pixels");
+    let (width, height) = (32, 32);
+    let offset = ((height / 2) * width + width / 2) * 4;
+    pixels[offset..offset + 4].try_into().unwrap()
+}
+
+#[test]
+fn nearer_surfaces_hide_farther_ones() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let Some(mut renderer) = runtime.block_on(Renderer::new_headless(32, 32)) else {
+        eprintln!("Skipping depth test: no graphics adapter available");
+        return;
+    };
+
+    // The near cube is drawn first, so only the depth test keeps it in front
+    add_cube(&mut renderer, "Near", Vec3::new(0.0, 0.0, 1.0), 0.5, Vec3::X);
+    add_cube(&mut renderer, "Far", Vec3::new(0.0, 0.0, -1.0), 2.0, Vec3::Z);
+    renderer.set_camera_position(Vec3::new(0.0, 0.0, 4.0));
+
+    let [r, _, b, _] = center_pixel(&mut renderer);
+    assert!(r > b, "far cube drawn over the near one: {r} {b}");
+
+    renderer.set_depth_compare(wgpu::CompareFunction::LessEqual).unwrap();
+    let [r, _, b, _] = center_pixel(&mut renderer);
+    assert!(r > b, "far cube drawn over the near one: {r} {b}");
+
+    // Without depth testing, the last cube drawn wins
+    renderer.set_depth_compare(wgpu::CompareFunction::Always).unwrap();
+    let [r, _, b, _] = center_pixel(&mut renderer);
+    assert!(b > r, "near cube drawn over the far one: {r} {b}");
+}
+
+#[test]
+fn rejects_comparisons_that_draw_nothing() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let Some(mut renderer) = runtime.block_on(Renderer::new_headless(32, 32)) else {
+        eprintln!("Skipping depth test: no graphics adapter available");
+        return;
+    };
+
+    for compare in [wgpu::CompareFunction::Greater, wgpu::CompareFunction::GreaterEqual, wgpu::CompareFunction::Equal] {
+        assert_eq!(renderer.set_depth_compare(compare), Err(DepthCompareError::DrawsNothing(compare)));
+    }
+    assert_eq!(renderer.depth_compare(), wgpu::CompareFunction::Less);
+}
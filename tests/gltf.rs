@@ -0,0 +1,163 @@
+// Tests for the glTF 2.0 importer
+
+use glam::{Quat, Vec3, Vec4};
+use intsar_3d::camera::Camera;
+use intsar_3d::import::gltf::{self, GltfError};
+use intsar_3d::light::{Light, LightKind};
+use intsar_3d::material::{AlphaMode, MaterialHandle, TextureSource};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::{Mesh, MeshHandle};
+use intsar_3d::scene::{ObjectHandle, Scene, SceneObject};
+use std::path::{Path, PathBuf};
+
+fn asset(name: &str) -> PathBuf {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/assets")).join(name)
+}
+
+fn handle(scene: &Scene, name: &str) -> ObjectHandle {
+    scene.find_object(name).unwrap_or_else(|| panic!("no object named `{name}`"))
+}
+
+fn object<'a>(scene: &'a Scene, name: &str) -> &'a SceneObject {
+    scene.object(handle(scene, name)).unwrap()
+}
+
+fn mesh<'a>(scene: &'a Scene, name: &str) -> &'a Mesh {
+    let &mesh = scene.component::<MeshHandle>(handle(scene, name)).unwrap();
+    scene.mesh(mesh).unwrap()
+}
+
+fn face_normals(mesh: &Mesh) -> Vec<Vec3> {
+    let indices: Vec<u32> = mesh.indices().iter().collect();
+    indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(mesh.vertices()[triangle[i] as usize].position));
+            (b - a).cross(c - a).normalize()
+        })
+        .collect()
+}
+
+fn check_scene(scene: &Scene) {
+    let names: Vec<&str> = scene.objects().map(|(_, object)| object.name()).collect();
+    assert_eq!(names, ["Root", "Child", "Child.1", "Sun", "Camera", "Node 4"]);
+
+    // The node hierarchy is kept, with local transforms
+    let root = object(scene, "Root");
+    assert_eq!(root.parent(), None);
+    assert_eq!(root.children(), [handle(scene, "Child"), handle(scene, "Sun")]);
+    assert_eq!(root.transform.position, Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(root.transform.scale, Vec3::splat(2.0));
+    let child = object(scene, "Child");
+    assert_eq!(child.parent(), Some(handle(scene, "Root")));
+    assert_eq!(child.transform.position, Vec3::X);
+    assert_eq!(child.transform.scale, Vec3::ONE);
+    assert_eq!(object(scene, "Sun").parent(), Some(handle(scene, "Root")));
+    let world = scene.world_matrix(handle(scene, "Child"));
+    assert!(world.transform_point3(Vec3::ZERO).abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-6));
+
+    // Extra primitives hang off their node's object
+    let extra = object(scene, "Child.1");
+    assert_eq!(extra.parent(), Some(handle(scene, "Child")));
+    assert_eq!(extra.transform.position, Vec3::ZERO);
+
+    let quad = mesh(scene, "Child");
+    assert_eq!(quad.name, "Quad.0");
+    assert_eq!(quad.vertices().len(), 4);
+    assert_eq!(quad.indices().len(), 6);
+    assert_eq!(quad.vertices()[0].uv, [0.0, 1.0]);
+    assert_eq!(quad.vertices()[0].tangent, [1.0, 0.0, 0.0, 1.0]);
+
+    // Primitives without normals are flat shaded
+    let triangle = mesh(scene, "Child.1");
+    assert_eq!(triangle.name, "Quad.1");
+    assert!(triangle.vertices().iter().all(|vertex| vertex.normal == [1.0, 0.0, 0.0]));
+    assert_eq!(triangle.vertices()[1].color, [0.0, 1.0, 0.0]);
+    assert_eq!(scene.component::<MaterialHandle>(handle(scene, "Child.1")), None);
+
+    // Triangle strips are converted to lists with consistent winding
+    let strip = mesh(scene, "Node 4");
+    assert_eq!(strip.name, "Strip");
+    assert_eq!(strip.indices().len(), 6);
+    for normal in face_normals(strip) {
+        assert!(normal.abs_diff_eq(Vec3::Z, 1e-6));
+    }
+
+    let &material = scene.component::<MaterialHandle>(handle(scene, "Child")).unwrap();
+    let material = scene.material(material).unwrap();
+    assert_eq!(material.name, "Painted");
+    assert_eq!(material.base_color, Vec4::new(1.0, 0.5, 0.25, 1.0));
+    assert_eq!(material.metallic, 0.25);
+    assert_eq!(material.roughness, 0.75);
+    assert_eq!(material.emissive, Vec3::new(2.0, 1.0, 0.0));
+    assert_eq!(material.alpha_mode, AlphaMode::Opaque);
+    match &material.base_color_texture {
+        Some(TextureSource::Embedded { mime_type, data }) => {
+            assert_eq!(mime_type.as_deref(), Some("image/png"));
+            assert!(data.starts_with(b"\x89PNG"));
+        }
+        other => panic!("expected an embedded texture, got {other:?}"),
+    }
+    assert_eq!(material.normal_texture, Some(TextureSource::File(asset("textures/normal map.png"))));
+    assert_eq!(material.metallic_roughness_texture, None);
+
+    let sun = object(scene, "Sun");
+    let light = scene.component::<Light>(handle(scene, "Sun")).unwrap();
+    assert_eq!(
+        light.kind,
+        LightKind::Spot {
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.6
+        }
+    );
+    assert_eq!(light.color, Vec3::new(1.0, 0.9, 0.8));
+    assert_eq!(light.intensity, 500.0);
+    assert_eq!(light.range, Some(20.0));
+    // Pointing straight down
+    assert!((sun.transform.rotation * Vec3::NEG_Z).abs_diff_eq(Vec3::NEG_Y, 1e-5));
+
+    let camera = object(scene, "Camera");
+    assert_eq!(camera.transform.rotation, Quat::IDENTITY);
+    assert_eq!(
+        scene.component::<Camera>(handle(scene, "Camera")),
+        Some(&Camera::Perspective {
+            fov_y: 0.8,
+            aspect_ratio: None,
+            near: 0.1,
+            far: None
+        })
+    );
+}
+
+#[test]
+fn loads_gltf_with_embedded_buffers() {
+    check_scene(&gltf::load_gltf(asset("scene.gltf")).unwrap());
+}
+
+#[test]
+fn loads_binary_gltf() {
+    check_scene(&gltf::load_gltf(asset("scene.glb")).unwrap());
+}
+
+#[test]
+fn imports_into_existing_scene() {
+    let mut scene = Scene::new();
+    scene.add_object(SceneObject::new("Existing".to_string(), Transform::identity()));
+
+    let transform = Transform::new(Vec3::new(0.0, 0.0, -3.0), Quat::IDENTITY, Vec3::ONE);
+    gltf::import_gltf(asset("scene.gltf"), &mut scene, transform).unwrap();
+
+    assert_eq!(scene.object_count(), 7);
+    assert_eq!(object(&scene, "Root").transform.position, Vec3::new(0.0, 1.0, -3.0));
+    // Only root nodes are moved; their children follow through the hierarchy
+    assert_eq!(object(&scene, "Child").transform.position, Vec3::X);
+    let world = scene.world_matrix(handle(&scene, "Child"));
+    assert!(world.transform_point3(Vec3::ZERO).abs_diff_eq(Vec3::new(2.0, 1.0, -3.0), 1e-6));
+}
+
+#[test]
+fn missing_files_are_errors() {
+    let error = gltf::load_gltf(asset("does_not_exist.gltf")).unwrap_err();
+    assert!(matches!(error, GltfError::Gltf { .. }));
+    assert!(error.to_string().contains("does_not_exist.gltf"));
+}
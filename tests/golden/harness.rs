@@ -0,0 +1,148 @@
+// Golden-image test harness for IntSar-3D
+//
+// Renders a named scene headlessly and compares it with a reference PNG in
+// `tests/golden/images`. Run with `UPDATE_GOLDEN=1` to write new references.
+// When a comparison fails, the rendered image and a diff image are written to
+// cargo's integration-test temp dir so they can be inspected.
+
+use image::{Rgba, RgbaImage};
+use intsar_3d::deferred::RenderPath;
+use intsar_3d::renderer::Renderer;
+use std::path::PathBuf;
+
+/// A scene that can be rendered and compared against a stored image.
+pub struct GoldenScene {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub setup: fn(&mut Renderer),
+}
+
+/// How far a rendering may drift from its reference image.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Largest allowed difference in any channel of a single pixel.
+    pub per_channel: u8,
+    /// Number of pixels allowed to exceed `per_channel` (rasterization differs
+    /// slightly between adapters along triangle edges).
+    pub max_mismatched_pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            per_channel: 3,
+            max_mismatched_pixels: 16,
+        }
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden/images")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+/// Renders a scene headlessly. Returns `None` if no adapter is available.
+pub fn render(scene: &GoldenScene) -> Option<RgbaImage> {
+    render_with_path(scene, RenderPath::Forward)
+}
+
+/// Renders a scene headlessly on the given render path.
+pub fn render_with_path(scene: &GoldenScene, render_path: RenderPath) -> Option<RgbaImage> {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let renderer = Renderer::new_headless_with_render_path(scene.width, scene.height, render_path);
+    let mut renderer = runtime.block_on(renderer)?;
+    (scene.setup)(&mut renderer);
+    renderer.render();
+    let pixels = renderer.read_pixels().expect("Headless renderer has no pixels");
+    RgbaImage::from_raw(scene.width, scene.height, pixels)
+}
+
+/// Compares two images, returning the number of mismatched pixels and a diff
+/// image with mismatches in red over a dimmed copy of the expected image.
+pub fn compare(expected: &RgbaImage, actual: &RgbaImage, tolerance: Tolerance) -> (usize, RgbaImage) {
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut mismatched = 0;
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let max_delta = expected_pixel.0.iter()
+            .zip(actual_pixel.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+
+        let pixel = if max_delta > tolerance.per_channel {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected_pixel.0;
+            let luma = ((r as u32 + g as u32 + b as u32) / 12) as u8;
+            Rgba([luma, luma, luma, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+
+    (mismatched, diff)
+}
+
+/// Renders `scene` and asserts it matches its reference image.
+pub fn check(scene: &GoldenScene, tolerance: Tolerance) {
+    check_with_path(scene, RenderPath::Forward, tolerance);
+}
+
+/// Renders `scene` on `render_path` and asserts it matches its reference
+/// image. References are only ever written from the forward path, which the
+/// others have to match.
+pub fn check_with_path(scene: &GoldenScene, render_path: RenderPath, tolerance: Tolerance) {
+    let Some(actual) = render_with_path(scene, render_path) else {
+        eprintln!("Skipping golden test '{}': no graphics adapter available", scene.name);
+        return;
+    };
+
+    let path = golden_path(scene.name);
+    if render_path == RenderPath::Forward && std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&path).expect("Failed to write golden image");
+        return;
+    }
+
+    let expected = match image::open(&path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => panic!(
+            "Missing golden image {} ({err}); run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        ),
+    };
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "Golden image '{}' has a different resolution",
+        scene.name
+    );
+
+    let (mismatched, diff) = compare(&expected, &actual, tolerance);
+    if mismatched > tolerance.max_mismatched_pixels {
+        let dir = output_dir();
+        std::fs::create_dir_all(&dir).expect("Failed to create golden output dir");
+        let label = match render_path {
+            RenderPath::Forward => scene.name.to_string(),
+            RenderPath::Deferred => format!("{}.deferred", scene.name),
+        };
+        let actual_path = dir.join(format!("{label}.actual.png"));
+        let diff_path = dir.join(format!("{label}.diff.png"));
+        actual.save(&actual_path).expect("Failed to write rendered image");
+        diff.save(&diff_path).expect("Failed to write diff image");
+        panic!(
+            "Golden image '{}' differs in {mismatched} pixels (allowed {}); see {} and {}",
+            scene.name,
+            tolerance.max_mismatched_pixels,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
@@ -0,0 +1,1023 @@
+// Golden-image regression tests for IntSar-3D
+
+mod harness;
+
+use glam::{EulerRot, Quat, Vec3, Vec4};
+use harness::{GoldenScene, Tolerance};
+use intsar_3d::antialias::{AntiAliasing, AntiAliasingError};
+use intsar_3d::bloom::{Bloom, BloomSettings};
+use intsar_3d::deferred::{GBufferChannel, RenderPath};
+use intsar_3d::light::{Light, LightKind};
+use intsar_3d::material::{AlphaMode, Material, TextureSource};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::{Indices, Mesh, MeshHandle, Vertex};
+use intsar_3d::postprocess::{PostStage, ShaderEffect};
+use intsar_3d::primitives;
+use intsar_3d::renderer::Renderer;
+use intsar_3d::scene::SceneObject;
+use intsar_3d::shadow::{Cascades, ShadowFlags, Shadows};
+use intsar_3d::ssao::SsaoSettings;
+use intsar_3d::texture::Filter;
+use intsar_3d::tonemap::{AutoExposure, Exposure, ToneMapping};
+use std::sync::Arc;
+
+/// Adds a mesh with the given Euler rotation (applied X, then Y, then Z).
+fn add_mesh_object(renderer: &mut Renderer, name: &str, mesh: MeshHandle, position: Vec3, rotation: Vec3) {
+    let rotation = Quat::from_euler(EulerRot::XYZ, rotation.x, rotation.y, rotation.z);
+    let transform = Transform::new(position, rotation, Vec3::ONE);
+    renderer.scene_mut().add_object(SceneObject::new(name.to_string(), transform).with_mesh(mesh));
+}
+
+/// Encodes an image as an embedded PNG texture.
+fn embedded_png(image: image::RgbaImage) -> TextureSource {
+    let mut data = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut data, image::ImageFormat::Png).expect("Failed to encode texture");
+    TextureSource::Embedded {
+        mime_type: Some("image/png".to_string()),
+        data: Arc::from(data.into_inner()),
+    }
+}
+
+/// Adds a built-in unit cube, registering a new cube mesh each time.
+fn add_cube(renderer: &mut Renderer, name: &str, position: Vec3, rotation: Vec3) {
+    let cube = renderer.scene_mut().add_mesh(Mesh::cube());
+    add_mesh_object(renderer, name, cube, position, rotation);
+}
+
+const CUBE_FRONT: GoldenScene = GoldenScene {
+    name: "cube_front",
+    width: 128,
+    height: 128,
+    setup: |renderer| add_cube(renderer, "Cube", Vec3::ZERO, Vec3::ZERO),
+};
+
+const CUBE_ROTATED: GoldenScene = GoldenScene {
+    name: "cube_rotated",
+    width: 128,
+    height: 128,
+    setup: |renderer| add_cube(renderer, "Cube", Vec3::ZERO, Vec3::new(0.5, 0.8, 0.0)),
+};
+
+const CUBE_WIDE: GoldenScene = GoldenScene {
+    name: "cube_wide",
+    width: 160,
+    height: 90,
+    setup: |renderer| {
+        add_cube(renderer, "Cube", Vec3::ZERO, Vec3::new(-0.4, 2.3, 0.0));
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 2.5));
+    },
+};
+
+const OVERLAPPING_CUBES: GoldenScene = GoldenScene {
+    name: "overlapping_cubes",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        // Added far-to-near and near-to-far so draw order can't hide depth bugs
+        add_cube(renderer, "Near", Vec3::new(0.3, 0.0, 0.5), Vec3::new(0.3, 0.6, 0.0));
+        add_cube(renderer, "Middle", Vec3::new(-0.2, 0.2, -0.5), Vec3::new(0.0, 0.4, 0.2));
+        add_cube(renderer, "Far", Vec3::new(-0.7, -0.3, -1.5), Vec3::ZERO);
+        add_cube(renderer, "Intersecting", Vec3::new(0.6, 0.4, 0.1), Vec3::new(0.7, 0.0, 0.7));
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 4.0));
+    },
+};
+
+const SHARED_MESHES: GoldenScene = GoldenScene {
+    name: "shared_meshes",
+    width: 128,
+    height: 128,
+    setup: |renderer| {
+        // A 32-bit indexed triangle drawn twice, next to a cube
+        let triangle = Mesh::new(
+            "Triangle".to_string(),
+            vec![
+                Vertex::new([-0.5, -0.5, 0.0], [1.0, 0.5, 0.0]),
+                Vertex::new([0.5, -0.5, 0.0], [0.0, 0.5, 1.0]),
+                Vertex::new([0.0, 0.5, 0.0], [0.5, 1.0, 0.5]),
+            ],
+            Indices::U32(vec![0, 1, 2]),
+        );
+        let triangle = renderer.scene_mut().add_mesh(triangle);
+        add_mesh_object(renderer, "Left", triangle, Vec3::new(-0.8, 0.5, 0.0), Vec3::ZERO);
+        add_mesh_object(renderer, "Right", triangle, Vec3::new(0.8, 0.5, 0.0), Vec3::new(0.0, 0.0, 0.5));
+        add_cube(renderer, "Cube", Vec3::new(0.0, -0.6, 0.0), Vec3::new(0.4, 0.4, 0.0));
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 4.0));
+    },
+};
+
+const PRIMITIVES: GoldenScene = GoldenScene {
+    name: "primitives",
+    width: 192,
+    height: 128,
+    setup: |renderer| {
+        let meshes = [
+            primitives::cube(0.7, 2),
+            primitives::uv_sphere(0.4, 24, 12),
+            primitives::icosphere(0.4, 2),
+            primitives::cylinder(0.35, 0.8, 16, 1),
+            primitives::cone(0.4, 0.8, 16, 1),
+            primitives::torus(0.3, 0.12, 24, 12),
+            primitives::plane(0.8, 0.8, 2, 2),
+            primitives::capsule(0.2, 0.4, 16, 4),
+        ];
+        for (index, mut mesh) in meshes.into_iter().enumerate() {
+            // Color by normal so shading differences show up in the image
+            for vertex in mesh.vertices_mut() {
+                vertex.color = vertex.normal.map(|n| n * 0.5 + 0.5);
+            }
+            let name = mesh.name.clone();
+            let handle = renderer.scene_mut().add_mesh(mesh);
+            let position = Vec3::new((index % 4) as f32 - 1.5, if index < 4 { 0.5 } else { -0.5 }, 0.0);
+            add_mesh_object(renderer, &name, handle, position, Vec3::new(0.5, 0.3, 0.0));
+        }
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 3.5));
+    },
+};
+
+const HIERARCHY: GoldenScene = GoldenScene {
+    name: "hierarchy",
+    width: 128,
+    height: 128,
+    setup: |renderer| {
+        // A small cube orbiting a rotated, scaled parent must inherit both
+        let cube = renderer.scene_mut().add_mesh(Mesh::cube());
+        let parent = Transform::new(Vec3::new(-0.5, 0.0, 0.0), Quat::from_rotation_z(0.6), Vec3::splat(0.8));
+        let parent = renderer.scene_mut().add_object(SceneObject::new("Parent".to_string(), parent).with_mesh(cube));
+        let child = Transform::new(Vec3::new(1.5, 0.0, 0.0), Quat::from_rotation_y(0.7), Vec3::splat(0.5));
+        renderer.scene_mut().add_object(
+            SceneObject::new("Child".to_string(), child).with_mesh(cube).with_parent(parent),
+        );
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 4.0));
+    },
+};
+
+const LIGHTS: GoldenScene = GoldenScene {
+    name: "lights",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(4.0, 4.0, 1, 1));
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.5, 32, 16));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+        scene.add_object(SceneObject::new("Sphere".to_string(), Transform::identity()).with_mesh(sphere));
+
+        // Dim blue sun, a red point light on the left and a green spot
+        // pointing straight down on the right
+        let mut sun = Light::new(LightKind::Directional, 1.0);
+        sun.color = Vec3::new(0.5, 0.6, 1.0);
+        let sun_transform = Transform::new(Vec3::ZERO, Quat::from_rotation_x(-1.0), Vec3::ONE);
+        scene.add_object(SceneObject::new("Sun".to_string(), sun_transform).with_light(sun));
+
+        let mut point = Light::new(LightKind::Point, 5.0);
+        point.color = Vec3::new(1.0, 0.2, 0.1);
+        point.range = Some(3.0);
+        let point_transform = Transform::new(Vec3::new(-1.2, 0.3, 0.6), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Point".to_string(), point_transform).with_light(point));
+
+        let spot_kind = LightKind::Spot { inner_cone_angle: 0.3, outer_cone_angle: 0.5 };
+        let mut spot = Light::new(spot_kind, 12.0);
+        spot.color = Vec3::new(0.2, 1.0, 0.3);
+        let spot_transform = Transform::new(
+            Vec3::new(1.2, 1.5, 0.0),
+            Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            Vec3::ONE,
+        );
+        scene.add_object(SceneObject::new("Spot".to_string(), spot_transform).with_light(spot));
+
+        renderer.set_ambient_light(Vec3::splat(0.05));
+        renderer.set_camera_position(Vec3::new(0.0, 1.5, 3.5));
+    },
+};
+
+const MATERIALS: GoldenScene = GoldenScene {
+    name: "materials",
+    width: 192,
+    height: 128,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.4, 32, 16));
+
+        // Top row: rough to smooth dielectrics; bottom row: rough to smooth metals
+        for row in 0..2 {
+            for column in 0..4 {
+                let mut material = Material::new(format!("Sphere {row}.{column}"));
+                material.base_color = Vec4::new(1.0, 0.6, 0.2, 1.0);
+                material.metallic = row as f32;
+                material.roughness = 1.0 - column as f32 * 0.3;
+                let material = scene.add_material(material);
+                let position = Vec3::new(column as f32 - 1.5, 0.5 - row as f32, 0.0);
+                let transform = Transform::new(position, Quat::IDENTITY, Vec3::ONE);
+                let name = format!("Sphere {row}.{column}");
+                scene.add_object(SceneObject::new(name, transform).with_mesh(sphere).with_material(material));
+            }
+        }
+
+        // A glowing cube behind a half-transparent one, and a cube with alpha
+        // below its mask cutoff that must not show up at all
+        let cube = scene.add_mesh(primitives::cube(0.5, 1));
+        let mut glow = Material::new("Glow".to_string());
+        glow.base_color = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        glow.emissive = Vec3::new(0.2, 0.8, 1.0);
+        let glow = scene.add_material(glow);
+        let mut glass = Material::new("Glass".to_string());
+        glass.base_color = Vec4::new(1.0, 1.0, 1.0, 0.4);
+        glass.alpha_mode = AlphaMode::Blend;
+        let glass = scene.add_material(glass);
+        let mut cutout = Material::new("Cutout".to_string());
+        cutout.base_color = Vec4::new(0.3, 0.9, 0.3, 0.4);
+        cutout.alpha_mode = AlphaMode::Mask { cutoff: 0.5 };
+        let cutout = scene.add_material(cutout);
+
+        let behind = Transform::new(Vec3::new(-0.6, 0.0, 1.0), Quat::from_rotation_y(0.5), Vec3::ONE);
+        scene.add_object(SceneObject::new("Glow".to_string(), behind).with_mesh(cube).with_material(glow));
+        let front = Transform::new(Vec3::new(-0.4, 0.0, 1.6), Quat::from_rotation_y(0.3), Vec3::ONE);
+        scene.add_object(SceneObject::new("Glass".to_string(), front).with_mesh(cube).with_material(glass));
+        let hidden = Transform::new(Vec3::new(0.8, 0.0, 1.2), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Cutout".to_string(), hidden).with_mesh(cube).with_material(cutout));
+
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 4.0));
+    },
+};
+
+const TEXTURES: GoldenScene = GoldenScene {
+    name: "textures",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+
+        // Crisp 4x4 checkerboard on a cube, magnified with nearest filtering
+        let checker = image::RgbaImage::from_fn(4, 4, |x, y| {
+            if (x + y) % 2 == 0 { image::Rgba([230, 40, 40, 255]) } else { image::Rgba([240, 240, 240, 255]) }
+        });
+        let mut checkered = Material::new("Checkered".to_string());
+        checkered.base_color_texture = Some(embedded_png(checker));
+        checkered.sampler.mag_filter = Filter::Nearest;
+        let checkered = scene.add_material(checkered);
+        let cube = scene.add_mesh(primitives::cube(0.9, 1));
+        let transform = Transform::new(Vec3::new(-0.7, 0.1, 0.0), Quat::from_euler(EulerRot::XYZ, 0.4, 0.6, 0.0), Vec3::ONE);
+        scene.add_object(SceneObject::new("Cube".to_string(), transform).with_mesh(cube).with_material(checkered));
+
+        // Ridges running along V on a plane lit from the side, from a normal map alone
+        let ridges = image::RgbaImage::from_fn(16, 16, |x, _| {
+            let slope = ((x as f32 / 16.0) * std::f32::consts::TAU * 2.0).sin() * 0.7;
+            let normal = Vec3::new(slope, 0.0, 1.0).normalize() * 0.5 + 0.5;
+            image::Rgba([(normal.x * 255.0) as u8, (normal.y * 255.0) as u8, (normal.z * 255.0) as u8, 255])
+        });
+        let mut ridged = Material::new("Ridged".to_string());
+        ridged.normal_texture = Some(embedded_png(ridges));
+        let ridged = scene.add_material(ridged);
+        let plane = scene.add_mesh(primitives::plane(1.2, 1.2, 1, 1));
+        let transform = Transform::new(Vec3::new(0.8, 0.0, 0.0), Quat::from_rotation_x(1.1), Vec3::ONE);
+        scene.add_object(SceneObject::new("Plane".to_string(), transform).with_mesh(plane).with_material(ridged));
+
+        let sun = Transform::new(Vec3::ZERO, Quat::from_rotation_y(-1.2), Vec3::ONE);
+        scene.add_object(SceneObject::new("Sun".to_string(), sun).with_light(Light::new(LightKind::Directional, 3.0)));
+        renderer.set_camera_position(Vec3::new(0.0, 0.0, 3.0));
+    },
+};
+
+const SHADOWS: GoldenScene = GoldenScene {
+    name: "shadows",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(4.0, 4.0, 1, 1));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+
+        // A sphere in the sun, a cube under the spot light and a torus that
+        // is kept out of the shadow maps
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.4, 32, 16));
+        let transform = Transform::new(Vec3::new(-0.8, 0.0, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Sphere".to_string(), transform).with_mesh(sphere));
+        let cube = scene.add_mesh(primitives::cube(0.5, 1));
+        let transform = Transform::new(Vec3::new(0.9, -0.1, 0.0), Quat::from_rotation_y(0.5), Vec3::ONE);
+        scene.add_object(SceneObject::new("Cube".to_string(), transform).with_mesh(cube));
+        let torus = scene.add_mesh(primitives::torus(0.3, 0.1, 32, 12));
+        let transform = Transform::new(Vec3::new(0.0, 0.3, 0.8), Quat::from_rotation_x(1.2), Vec3::ONE);
+        let flags = ShadowFlags { cast: false, receive: true };
+        scene.add_object(SceneObject::new("Torus".to_string(), transform).with_mesh(torus).with_shadow_flags(flags));
+
+        let mut sun = Light::new(LightKind::Directional, 2.0);
+        sun.shadows = Some(Shadows::default());
+        let sun_direction = Vec3::new(0.5, -1.0, 0.6).normalize();
+        let sun_transform = Transform::new(Vec3::ZERO, Quat::from_rotation_arc(Vec3::NEG_Z, sun_direction), Vec3::ONE);
+        scene.add_object(SceneObject::new("Sun".to_string(), sun_transform).with_light(sun));
+
+        let spot_kind = LightKind::Spot { inner_cone_angle: 0.4, outer_cone_angle: 0.6 };
+        let mut spot = Light::new(spot_kind, 8.0);
+        spot.color = Vec3::new(1.0, 0.8, 0.4);
+        spot.shadows = Some(Shadows::default());
+        let spot_position = Vec3::new(1.2, 1.5, -0.6);
+        let spot_direction = (Vec3::new(0.9, -0.5, 0.0) - spot_position).normalize();
+        let spot_rotation = Quat::from_rotation_arc(Vec3::NEG_Z, spot_direction);
+        let spot_transform = Transform::new(spot_position, spot_rotation, Vec3::ONE);
+        scene.add_object(SceneObject::new("Spot".to_string(), spot_transform).with_light(spot));
+
+        renderer.set_ambient_light(Vec3::splat(0.05));
+        renderer.set_camera_position(Vec3::new(0.0, 2.0, 3.5));
+    },
+};
+
+const CASCADES: GoldenScene = GoldenScene {
+    name: "cascades",
+    width: 192,
+    height: 128,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+        let ground = scene.add_mesh(primitives::plane(60.0, 60.0, 1, 1));
+        let ground_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Ground".to_string(), ground_transform).with_mesh(ground));
+
+        // Rows of posts receding from the camera, each row in a further cascade
+        let post = scene.add_mesh(primitives::cube(0.4, 1));
+        for row in 0..8 {
+            for column in -2..=2 {
+                let position = Vec3::new(column as f32 * 1.5, 0.0, 2.0 - row as f32 * 3.0);
+                let transform = Transform::new(position, Quat::IDENTITY, Vec3::new(1.0, 2.5, 1.0));
+                scene.add_object(SceneObject::new(format!("Post {row}.{column}"), transform).with_mesh(post));
+            }
+        }
+
+        let mut sun = Light::new(LightKind::Directional, 3.0);
+        sun.shadows = Some(Shadows {
+            cascades: Some(Cascades { max_distance: 30.0, ..Cascades::default() }),
+            ..Shadows::default()
+        });
+        let sun_direction = Vec3::new(0.8, -1.0, 0.3).normalize();
+        let sun_transform = Transform::new(Vec3::ZERO, Quat::from_rotation_arc(Vec3::NEG_Z, sun_direction), Vec3::ONE);
+        scene.add_object(SceneObject::new("Sun".to_string(), sun_transform).with_light(sun));
+
+        renderer.set_shadow_cascade_debug(true);
+        renderer.set_camera_position(Vec3::new(0.0, 2.0, 6.0));
+    },
+};
+
+const POINT_SHADOWS: GoldenScene = GoldenScene {
+    name: "point_shadows",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        let scene = renderer.scene_mut();
+
+        // A floor and two walls around a lamp, with objects on every side of it
+        let panel = scene.add_mesh(primitives::plane(5.0, 5.0, 1, 1));
+        let floor = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor).with_mesh(panel));
+        let back = Transform::new(Vec3::new(0.0, 2.0, -2.0), Quat::from_rotation_x(std::f32::consts::FRAC_PI_2), Vec3::ONE);
+        scene.add_object(SceneObject::new("Back Wall".to_string(), back).with_mesh(panel));
+        let side = Transform::new(
+            Vec3::new(-2.0, 2.0, 0.0),
+            Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
+            Vec3::ONE,
+        );
+        scene.add_object(SceneObject::new("Side Wall".to_string(), side).with_mesh(panel));
+
+        let cube = scene.add_mesh(primitives::cube(0.4, 1));
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.25, 24, 12));
+        let pillar = scene.add_mesh(primitives::cylinder(0.12, 1.4, 16, 1));
+        let left = Transform::new(Vec3::new(-0.9, -0.3, 0.2), Quat::from_rotation_y(0.4), Vec3::ONE);
+        scene.add_object(SceneObject::new("Cube".to_string(), left).with_mesh(cube));
+        let behind = Transform::new(Vec3::new(0.3, 0.1, -0.9), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Sphere".to_string(), behind).with_mesh(sphere));
+        let right = Transform::new(Vec3::new(1.0, 0.2, 0.3), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Pillar".to_string(), right).with_mesh(pillar));
+
+        let mut lamp = Light::new(LightKind::Point, 6.0);
+        lamp.color = Vec3::new(1.0, 0.85, 0.6);
+        lamp.shadows = Some(Shadows::default());
+        let lamp_transform = Transform::new(Vec3::new(0.0, 0.9, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Lamp".to_string(), lamp_transform).with_light(lamp));
+
+        renderer.set_ambient_light(Vec3::splat(0.03));
+        renderer.set_camera_position(Vec3::new(1.2, 2.0, 4.0));
+    },
+};
+
+/// Adds spheres lit well past 1.0: a white one, saturated red and blue ones,
+/// and one glowing brighter still, under a sun `intensity` lux strong.
+fn add_hdr_scene(renderer: &mut Renderer, intensity: f32) {
+    let scene = renderer.scene_mut();
+    let floor = scene.add_mesh(primitives::plane(8.0, 8.0, 1, 1));
+    let sphere = scene.add_mesh(primitives::uv_sphere(0.4, 32, 16));
+    let floor_transform = Transform::new(Vec3::new(0.0, -0.4, 0.0), Quat::IDENTITY, Vec3::ONE);
+    scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+
+    let colors = [
+        (Vec3::ONE, Vec3::ZERO),
+        (Vec3::new(1.0, 0.1, 0.05), Vec3::ZERO),
+        (Vec3::new(0.1, 0.3, 1.0), Vec3::ZERO),
+        (Vec3::ONE, Vec3::new(1.0, 0.6, 0.2) * intensity * 2.0),
+    ];
+    for (index, (color, emissive)) in colors.into_iter().enumerate() {
+        let mut material = Material::new(format!("Sphere {index}"));
+        material.base_color = color.extend(1.0);
+        material.roughness = 0.4;
+        material.emissive = emissive;
+        let material = scene.add_material(material);
+        let position = Vec3::new(index as f32 - 1.5, 0.0, 0.0);
+        scene.add_object(
+            SceneObject::new(format!("Sphere {index}"), Transform::new(position, Quat::IDENTITY, Vec3::ONE))
+                .with_mesh(sphere)
+                .with_material(material),
+        );
+    }
+
+    let sun_transform = Transform::new(Vec3::ZERO, Quat::from_euler(EulerRot::YXZ, 0.5, -0.9, 0.0), Vec3::ONE);
+    scene.add_object(
+        SceneObject::new("Sun".to_string(), sun_transform).with_light(Light::new(LightKind::Directional, intensity)),
+    );
+
+    renderer.set_ambient_light(Vec3::splat(0.05 * intensity));
+    renderer.set_camera_position(Vec3::new(0.0, 1.0, 3.5));
+}
+
+const TONE_MAPPING_REINHARD: GoldenScene = GoldenScene {
+    name: "tone_mapping_reinhard",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        add_hdr_scene(renderer, 6.0);
+        renderer.set_tone_mapping(ToneMapping::Reinhard);
+    },
+};
+
+const TONE_MAPPING_ACES: GoldenScene = GoldenScene {
+    name: "tone_mapping_aces",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        add_hdr_scene(renderer, 6.0);
+        renderer.set_tone_mapping(ToneMapping::Aces);
+    },
+};
+
+const TONE_MAPPING_AGX: GoldenScene = GoldenScene {
+    name: "tone_mapping_agx",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        add_hdr_scene(renderer, 6.0);
+        renderer.set_tone_mapping(ToneMapping::AgX);
+    },
+};
+
+const AUTO_EXPOSURE: GoldenScene = GoldenScene {
+    name: "auto_exposure",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        // A hundred times dimmer, brought back up by auto exposure. Looking
+        // down keeps the fixed clear color out of the measurement
+        add_hdr_scene(renderer, 0.06);
+        renderer.set_exposure(Exposure::Auto(AutoExposure::default()));
+        renderer.set_camera_position(Vec3::new(0.0, 2.5, 1.5));
+    },
+};
+
+const POST_EFFECTS: GoldenScene = GoldenScene {
+    name: "post_effects",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        add_hdr_scene(renderer, 6.0);
+
+        // Scales the scene's light before tone mapping
+        let scale = ShaderEffect::new("Scale", PostStage::Hdr, "
+            struct Parameters { scale: f32 };
+            @group(1) @binding(0) var<uniform> parameters: Parameters;
+
+            @fragment
+            fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+                let color = textureSample(post_input, post_sampler, in.uv);
+                return vec4<f32>(color.rgb * parameters.scale, color.a);
+            }
+        ").unwrap();
+        let scale = renderer.add_post_effect(scale.with_parameters(&[1.0f32]));
+
+        let vignette = ShaderEffect::new("Vignette", PostStage::Display, "
+            struct Parameters { radius: f32, softness: f32 };
+            @group(1) @binding(0) var<uniform> parameters: Parameters;
+
+            @fragment
+            fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+                let color = textureSample(post_input, post_sampler, in.uv);
+                let offset = (in.uv - 0.5) * vec2<f32>(post.size.x * post.texel_size.y, 1.0);
+                let falloff = smoothstep(parameters.radius, parameters.radius - parameters.softness, length(offset));
+                return vec4<f32>(color.rgb * falloff, color.a);
+            }
+        ").unwrap();
+        renderer.add_post_effect(vignette.with_parameters(&[0.9f32, 0.6]));
+
+        // Disabled effects are skipped
+        let mut invert = ShaderEffect::new("Invert", PostStage::Display, "
+            @fragment
+            fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+                let color = textureSample(post_input, post_sampler, in.uv);
+                return vec4<f32>(1.0 - color.rgb, color.a);
+            }
+        ").unwrap();
+        invert.set_enabled(false);
+        renderer.add_post_effect(invert);
+
+        let desaturate = ShaderEffect::new("Desaturate", PostStage::Display, "
+            @fragment
+            fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+                let color = textureSample(post_input, post_sampler, in.uv);
+                let grey = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+                return vec4<f32>(mix(color.rgb, vec3<f32>(grey), 0.7), color.a);
+            }
+        ").unwrap();
+        renderer.add_post_effect(desaturate);
+
+        renderer.post_effect_mut::<ShaderEffect>(scale)
+            .expect("Scale effect is a shader effect")
+            .set_parameters(&[0.25f32]);
+    },
+};
+
+const BLOOM: GoldenScene = GoldenScene {
+    name: "bloom",
+    width: 192,
+    height: 128,
+    setup: |renderer| {
+        // Glowing spheres and a bright highlight on a metal one bloom; the
+        // dimly lit floor and the matte sphere don't
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(8.0, 8.0, 1, 1));
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.35, 32, 16));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.35, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+
+        let materials = [
+            ("Matte", Vec3::splat(0.6), 0.0, 0.8, Vec3::ZERO),
+            ("Glow", Vec3::ONE, 0.0, 0.5, Vec3::new(4.0, 1.2, 0.3)),
+            ("Chrome", Vec3::splat(0.9), 1.0, 0.15, Vec3::ZERO),
+            ("Neon", Vec3::ONE, 0.0, 0.5, Vec3::new(0.2, 1.0, 6.0)),
+        ];
+        for (index, (name, color, metallic, roughness, emissive)) in materials.into_iter().enumerate() {
+            let mut material = Material::new(name.to_string());
+            material.base_color = color.extend(1.0);
+            material.metallic = metallic;
+            material.roughness = roughness;
+            material.emissive = emissive;
+            let material = scene.add_material(material);
+            let position = Vec3::new(index as f32 * 0.9 - 1.35, 0.0, 0.0);
+            scene.add_object(
+                SceneObject::new(name.to_string(), Transform::new(position, Quat::IDENTITY, Vec3::ONE))
+                    .with_mesh(sphere)
+                    .with_material(material),
+            );
+        }
+
+        let sun_transform = Transform::new(Vec3::ZERO, Quat::from_euler(EulerRot::YXZ, -0.4, -0.6, 0.0), Vec3::ONE);
+        scene.add_object(
+            SceneObject::new("Sun".to_string(), sun_transform).with_light(Light::new(LightKind::Directional, 2.0)),
+        );
+
+        renderer.set_ambient_light(Vec3::splat(0.02));
+        renderer.set_camera_position(Vec3::new(0.0, 0.8, 3.0));
+        renderer.add_post_effect(Bloom::new(BloomSettings {
+            intensity: 1.0,
+            ..BloomSettings::default()
+        }));
+    },
+};
+
+const MSAA: GoldenScene = GoldenScene {
+    name: "msaa",
+    width: 128,
+    height: 128,
+    setup: |renderer| {
+        (CUBE_ROTATED.setup)(renderer);
+        renderer.set_anti_aliasing(AntiAliasing::Msaa(4)).expect("4x MSAA is always supported");
+    },
+};
+
+const FXAA: GoldenScene = GoldenScene {
+    name: "fxaa",
+    width: 128,
+    height: 128,
+    setup: |renderer| {
+        (CUBE_ROTATED.setup)(renderer);
+        renderer.set_anti_aliasing(AntiAliasing::Fxaa).unwrap();
+    },
+};
+
+const TAA: GoldenScene = GoldenScene {
+    name: "taa",
+    width: 128,
+    height: 128,
+    setup: |renderer| {
+        (CUBE_ROTATED.setup)(renderer);
+        renderer.set_anti_aliasing(AntiAliasing::Taa).unwrap();
+        // Accumulate a full cycle of jitter before the frame that is compared
+        for _ in 0..15 {
+            renderer.render();
+        }
+    },
+};
+
+const SSAO: GoldenScene = GoldenScene {
+    name: "ssao",
+    width: 160,
+    height: 96,
+    setup: |renderer| {
+        // Lit mostly by ambient light, so the occlusion in the creases and
+        // under the objects stands out
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(8.0, 8.0, 1, 1));
+        let cube = scene.add_mesh(Mesh::cube());
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.4, 32, 16));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+        let cube_transform = Transform::new(Vec3::new(-0.6, 0.0, 0.0), Quat::from_rotation_y(0.5), Vec3::ONE);
+        scene.add_object(SceneObject::new("Cube".to_string(), cube_transform).with_mesh(cube));
+        let sphere_transform = Transform::new(Vec3::new(0.8, -0.1, 0.3), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Sphere".to_string(), sphere_transform).with_mesh(sphere));
+
+        let sun_transform = Transform::new(Vec3::ZERO, Quat::from_euler(EulerRot::YXZ, 0.3, -1.2, 0.0), Vec3::ONE);
+        scene.add_object(
+            SceneObject::new("Sun".to_string(), sun_transform).with_light(Light::new(LightKind::Directional, 0.3)),
+        );
+
+        renderer.set_ambient_light(Vec3::splat(0.8));
+        renderer.set_camera_position(Vec3::new(0.0, 1.5, 3.0));
+        renderer.set_ssao(Some(SsaoSettings {
+            intensity: 2.0,
+            samples: 32,
+            ..SsaoSettings::default()
+        }));
+    },
+};
+
+const MANY_LIGHTS: GoldenScene = GoldenScene {
+    name: "many_lights",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        // A ring of small colored lamps over a floor, each reaching only
+        // a little of it
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(6.0, 6.0, 1, 1));
+        let sphere = scene.add_mesh(primitives::uv_sphere(0.4, 32, 16));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+        scene.add_object(SceneObject::new("Sphere".to_string(), Transform::identity()).with_mesh(sphere));
+
+        let colors = [Vec3::new(1.0, 0.2, 0.1), Vec3::new(0.2, 1.0, 0.2), Vec3::new(0.2, 0.3, 1.0)];
+        for index in 0..12 {
+            let angle = index as f32 / 12.0 * std::f32::consts::TAU;
+            let mut lamp = Light::new(LightKind::Point, 1.5);
+            lamp.color = colors[index % 3];
+            lamp.range = Some(1.2);
+            let position = Vec3::new(angle.cos() * 1.6, -0.2, angle.sin() * 1.6);
+            let transform = Transform::new(position, Quat::IDENTITY, Vec3::ONE);
+            scene.add_object(SceneObject::new(format!("Lamp {index}"), transform).with_light(lamp));
+        }
+
+        renderer.set_ambient_light(Vec3::splat(0.02));
+        renderer.set_camera_position(Vec3::new(0.0, 3.0, 4.0));
+    },
+};
+
+const HUNDREDS_OF_LIGHTS: GoldenScene = GoldenScene {
+    name: "hundreds_of_lights",
+    width: 160,
+    height: 120,
+    setup: |renderer| {
+        // A 16x16 grid of dim lamps just over a floor, far more than any one
+        // pixel is lit by
+        let scene = renderer.scene_mut();
+        let floor = scene.add_mesh(primitives::plane(6.0, 6.0, 1, 1));
+        let floor_transform = Transform::new(Vec3::new(0.0, -0.5, 0.0), Quat::IDENTITY, Vec3::ONE);
+        scene.add_object(SceneObject::new("Floor".to_string(), floor_transform).with_mesh(floor));
+
+        let colors = [Vec3::new(1.0, 0.3, 0.1), Vec3::new(0.2, 1.0, 0.3), Vec3::new(0.2, 0.4, 1.0)];
+        for row in 0..16 {
+            for column in 0..16 {
+                let mut lamp = Light::new(LightKind::Point, 0.08);
+                lamp.color = colors[(row + column) % 3];
+                lamp.range = Some(0.5);
+                let position = Vec3::new(column as f32 * 0.36 - 2.7, -0.4, row as f32 * 0.36 - 2.7);
+                let transform = Transform::new(position, Quat::IDENTITY, Vec3::ONE);
+                scene.add_object(SceneObject::new(format!("Lamp {row}.{column}"), transform).with_light(lamp));
+            }
+        }
+
+        renderer.set_ambient_light(Vec3::splat(0.02));
+        renderer.set_camera_position(Vec3::new(0.0, 4.0, 5.0));
+    },
+};
+
+#[test]
+fn cube_front() {
+    harness::check(&CUBE_FRONT, Tolerance::default());
+}
+
+#[test]
+fn cube_rotated() {
+    harness::check(&CUBE_ROTATED, Tolerance::default());
+}
+
+#[test]
+fn cube_wide() {
+    harness::check(&CUBE_WIDE, Tolerance::default());
+}
+
+#[test]
+fn overlapping_cubes() {
+    harness::check(&OVERLAPPING_CUBES, Tolerance::default());
+}
+
+#[test]
+fn shared_meshes() {
+    harness::check(&SHARED_MESHES, Tolerance::default());
+}
+
+#[test]
+fn primitives() {
+    harness::check(&PRIMITIVES, Tolerance::default());
+}
+
+#[test]
+fn hierarchy() {
+    harness::check(&HIERARCHY, Tolerance::default());
+}
+
+#[test]
+fn lights() {
+    harness::check(&LIGHTS, Tolerance::default());
+}
+
+#[test]
+fn materials() {
+    harness::check(&MATERIALS, Tolerance::default());
+}
+
+#[test]
+fn textures() {
+    harness::check(&TEXTURES, Tolerance::default());
+}
+
+#[test]
+fn shadows() {
+    harness::check(&SHADOWS, Tolerance::default());
+}
+
+#[test]
+fn cascades() {
+    harness::check(&CASCADES, Tolerance::default());
+}
+
+#[test]
+fn point_shadows() {
+    harness::check(&POINT_SHADOWS, Tolerance::default());
+}
+
+#[test]
+fn tone_mapping_reinhard() {
+    harness::check(&TONE_MAPPING_REINHARD, Tolerance::default());
+}
+
+#[test]
+fn tone_mapping_aces() {
+    harness::check(&TONE_MAPPING_ACES, Tolerance::default());
+}
+
+#[test]
+fn tone_mapping_agx() {
+    harness::check(&TONE_MAPPING_AGX, Tolerance::default());
+}
+
+#[test]
+fn auto_exposure() {
+    harness::check(&AUTO_EXPOSURE, Tolerance::default());
+}
+
+#[test]
+fn post_effects() {
+    harness::check(&POST_EFFECTS, Tolerance::default());
+}
+
+#[test]
+fn bloom() {
+    harness::check(&BLOOM, Tolerance::default());
+}
+
+#[test]
+fn bloom_leaves_dim_scenes_alone() {
+    // Nothing in the scene is brighter than the threshold, so it matches
+    // the reference rendered without bloom
+    harness::check(
+        &GoldenScene {
+            setup: |renderer| {
+                (CUBE_ROTATED.setup)(renderer);
+                renderer.add_post_effect(Bloom::default());
+            },
+            ..CUBE_ROTATED
+        },
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn manual_exposure_scales_light() {
+    // Two stops of exposure look the same as four times the light, as long
+    // as the unlit clear color stays out of view
+    let exposed = GoldenScene {
+        name: "manual_exposure",
+        width: 160,
+        height: 96,
+        setup: |renderer| {
+            add_hdr_scene(renderer, 1.0);
+            renderer.set_exposure(Exposure::Manual { stops: 2.0 });
+            renderer.set_camera_position(Vec3::new(0.0, 2.5, 1.5));
+        },
+    };
+    let brighter = GoldenScene {
+        setup: |renderer| {
+            add_hdr_scene(renderer, 4.0);
+            renderer.set_camera_position(Vec3::new(0.0, 2.5, 1.5));
+        },
+        ..exposed
+    };
+    let (Some(exposed), Some(brighter)) = (harness::render(&exposed), harness::render(&brighter)) else {
+        eprintln!("Skipping manual exposure test: no graphics adapter available");
+        return;
+    };
+    let (mismatched, _) = harness::compare(&brighter, &exposed, Tolerance::default());
+    assert!(mismatched <= Tolerance::default().max_mismatched_pixels, "{mismatched} pixels differ");
+}
+
+#[test]
+fn msaa() {
+    harness::check(&MSAA, Tolerance::default());
+}
+
+#[test]
+fn fxaa() {
+    harness::check(&FXAA, Tolerance::default());
+}
+
+#[test]
+fn taa() {
+    harness::check(&TAA, Tolerance::default());
+}
+
+#[test]
+fn anti_aliasing_switches_at_runtime() {
+    // Going through every mode and back renders like it was never changed
+    harness::check(
+        &GoldenScene {
+            setup: |renderer| {
+                (CUBE_ROTATED.setup)(renderer);
+                for anti_aliasing in [AntiAliasing::Msaa(4), AntiAliasing::Fxaa, AntiAliasing::Taa, AntiAliasing::None] {
+                    renderer.set_anti_aliasing(anti_aliasing).unwrap();
+                    renderer.render();
+                }
+            },
+            ..CUBE_ROTATED
+        },
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn rejects_unsupported_sample_counts() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let Some(mut renderer) = runtime.block_on(Renderer::new_headless(64, 64)) else {
+        eprintln!("Skipping sample count test: no graphics adapter available");
+        return;
+    };
+    let supported = renderer.supported_sample_counts();
+    assert!(supported.contains(&1) && supported.contains(&4), "{supported:?}");
+
+    let err = renderer.set_anti_aliasing(AntiAliasing::Msaa(3)).err().unwrap();
+    assert!(matches!(err, AntiAliasingError::UnsupportedSampleCount { requested: 3, .. }), "{err}");
+    assert_eq!(renderer.anti_aliasing(), AntiAliasing::None);
+}
+
+#[test]
+fn ssao() {
+    harness::check(&SSAO, Tolerance::default());
+}
+
+#[test]
+fn ssao_turns_off_cleanly() {
+    // Once SSAO is off again, nothing is occluded
+    harness::check(
+        &GoldenScene {
+            setup: |renderer| {
+                (CUBE_ROTATED.setup)(renderer);
+                renderer.set_ssao(Some(SsaoSettings::default()));
+                renderer.render();
+                renderer.set_ssao(None);
+            },
+            ..CUBE_ROTATED
+        },
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn many_lights() {
+    harness::check(&MANY_LIGHTS, Tolerance::default());
+}
+
+#[test]
+fn hundreds_of_lights() {
+    harness::check(&HUNDREDS_OF_LIGHTS, Tolerance::default());
+}
+
+#[test]
+fn deferred_many_lights() {
+    harness::check_with_path(&MANY_LIGHTS, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_hundreds_of_lights() {
+    // Clustered forward shading leaves out no light the deferred path adds
+    harness::check_with_path(&HUNDREDS_OF_LIGHTS, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_lights() {
+    harness::check_with_path(&LIGHTS, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_materials() {
+    // Blended meshes are drawn forward over the deferred ones. The G-buffer
+    // keeps roughness to 8 bits, which moves the sharpest highlights a little
+    let tolerance = Tolerance {
+        max_mismatched_pixels: 32,
+        ..Tolerance::default()
+    };
+    harness::check_with_path(&MATERIALS, RenderPath::Deferred, tolerance);
+}
+
+#[test]
+fn deferred_textures() {
+    harness::check_with_path(&TEXTURES, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_shadows() {
+    harness::check_with_path(&SHADOWS, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_point_shadows() {
+    harness::check_with_path(&POINT_SHADOWS, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_ssao() {
+    harness::check_with_path(&SSAO, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn deferred_taa() {
+    harness::check_with_path(&TAA, RenderPath::Deferred, Tolerance::default());
+}
+
+#[test]
+fn gbuffer_debug_view() {
+    let scene = GoldenScene {
+        setup: |renderer| {
+            (MANY_LIGHTS.setup)(renderer);
+            renderer.set_gbuffer_debug(Some(GBufferChannel::Normal));
+        },
+        ..MANY_LIGHTS
+    };
+    let Some(image) = harness::render_with_path(&scene, RenderPath::Deferred) else {
+        eprintln!("Skipping G-buffer debug test: no graphics adapter available");
+        return;
+    };
+
+    // The floor faces straight up, and nothing is stored behind it
+    let floor = image.get_pixel(80, 110).0;
+    assert!(floor[0].abs_diff(128) <= 2 && floor[1] >= 253 && floor[2].abs_diff(128) <= 2, "{floor:?}");
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+}
+
+#[test]
+fn deferred_path_rejects_msaa() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let renderer = Renderer::new_headless_with_render_path(64, 64, RenderPath::Deferred);
+    let Some(mut renderer) = runtime.block_on(renderer) else {
+        eprintln!("Skipping deferred MSAA test: no graphics adapter available");
+        return;
+    };
+    assert_eq!(renderer.render_path(), RenderPath::Deferred);
+
+    let err = renderer.set_anti_aliasing(AntiAliasing::Msaa(4)).err().unwrap();
+    assert!(matches!(err, AntiAliasingError::UnsupportedRenderPath { render_path: RenderPath::Deferred }), "{err}");
+    assert_eq!(renderer.anti_aliasing(), AntiAliasing::None);
+    renderer.set_anti_aliasing(AntiAliasing::Fxaa).unwrap();
+}
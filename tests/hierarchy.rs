@@ -0,0 +1,96 @@
+// Tests for the scene's parent/child hierarchy
+
+use glam::{Quat, Vec3};
+use intsar_3d::math::Transform;
+use intsar_3d::scene::{HierarchyError, KeepTransform, ObjectHandle, Scene, SceneObject};
+
+fn at(position: Vec3) -> Transform {
+    Transform::new(position, Quat::IDENTITY, Vec3::ONE)
+}
+
+fn world_position(scene: &Scene, handle: ObjectHandle) -> Vec3 {
+    scene.world_matrix(handle).transform_point3(Vec3::ZERO)
+}
+
+/// Builds `Root -> Arm -> Hand` plus a separate `Other` root.
+fn arm() -> (Scene, [ObjectHandle; 4]) {
+    let mut scene = Scene::new();
+    let turned = Transform::new(Vec3::X, Quat::from_rotation_z(std::f32::consts::FRAC_PI_2), Vec3::splat(2.0));
+    let root = scene.add_object(SceneObject::new("Root".to_string(), turned));
+    let arm = scene.add_object(SceneObject::new("Arm".to_string(), at(Vec3::X)).with_parent(root));
+    let hand = scene.add_object(SceneObject::new("Hand".to_string(), at(Vec3::X)).with_parent(arm));
+    let other = scene.add_object(SceneObject::new("Other".to_string(), at(Vec3::new(0.0, 0.0, 5.0))));
+    (scene, [root, arm, hand, other])
+}
+
+#[test]
+fn composes_world_transforms_up_the_chain() {
+    let (scene, [root, arm, hand, other]) = arm();
+
+    // Root turns +X into +Y and doubles lengths
+    assert!(world_position(&scene, root).abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-6));
+    assert!(world_position(&scene, arm).abs_diff_eq(Vec3::new(1.0, 2.0, 0.0), 1e-6));
+    assert!(world_position(&scene, hand).abs_diff_eq(Vec3::new(1.0, 4.0, 0.0), 1e-6));
+    assert_eq!(world_position(&scene, other), Vec3::new(0.0, 0.0, 5.0));
+
+    let matrices = scene.world_matrices();
+    assert_eq!(matrices.iter().map(|&(handle, _)| handle).collect::<Vec<_>>(), [root, arm, hand, other]);
+    for (handle, matrix) in matrices {
+        assert!(matrix.abs_diff_eq(scene.world_matrix(handle), 1e-6));
+    }
+}
+
+#[test]
+fn traverses_the_hierarchy() {
+    let (mut scene, [root, arm, hand, other]) = arm();
+    let thumb = scene.add_object(SceneObject::new("Thumb".to_string(), Transform::identity()).with_parent(arm));
+
+    assert_eq!(scene.roots().collect::<Vec<_>>(), [root, other]);
+    assert_eq!(scene.object(arm).unwrap().children(), [hand, thumb]);
+    assert_eq!(scene.object(hand).unwrap().parent(), Some(arm));
+    assert_eq!(scene.ancestors(hand).collect::<Vec<_>>(), [arm, root]);
+    assert_eq!(scene.descendants(root).collect::<Vec<_>>(), [arm, hand, thumb]);
+    assert_eq!(scene.depth_first().collect::<Vec<_>>(), [root, arm, hand, thumb, other]);
+}
+
+#[test]
+fn reparenting_can_keep_the_world_transform() {
+    let (mut scene, [_, arm, hand, other]) = arm();
+    let before = scene.world_matrix(hand);
+
+    scene.set_parent(hand, Some(other), KeepTransform::World).unwrap();
+    assert!(scene.world_matrix(hand).abs_diff_eq(before, 1e-5));
+    assert_eq!(scene.object(hand).unwrap().parent(), Some(other));
+    assert!(scene.object(arm).unwrap().children().is_empty());
+    assert_eq!(scene.object(other).unwrap().children(), [hand]);
+
+    scene.set_parent(hand, None, KeepTransform::World).unwrap();
+    assert!(scene.world_matrix(hand).abs_diff_eq(before, 1e-5));
+    assert!(scene.roots().any(|root| root == hand));
+}
+
+#[test]
+fn reparenting_can_keep_the_local_transform() {
+    let (mut scene, [_, _, hand, other]) = arm();
+
+    scene.set_parent(hand, Some(other), KeepTransform::Local).unwrap();
+    assert_eq!(scene.object(hand).unwrap().transform.position, Vec3::X);
+    assert_eq!(world_position(&scene, hand), Vec3::new(1.0, 0.0, 5.0));
+}
+
+#[test]
+fn rejects_cycles() {
+    let (mut scene, [root, arm, hand, _]) = arm();
+
+    assert_eq!(
+        scene.set_parent(root, Some(hand), KeepTransform::Local),
+        Err(HierarchyError::Cycle { child: root, parent: hand })
+    );
+    assert_eq!(
+        scene.set_parent(arm, Some(arm), KeepTransform::World),
+        Err(HierarchyError::Cycle { child: arm, parent: arm })
+    );
+    // Nothing changed
+    assert_eq!(scene.object(root).unwrap().parent(), None);
+    assert_eq!(scene.ancestors(hand).collect::<Vec<_>>(), [arm, root]);
+}
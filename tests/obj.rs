@@ -0,0 +1,223 @@
+// Tests for the Wavefront OBJ/MTL importer
+
+use glam::Vec3;
+use intsar_3d::import::obj::{self, ObjError};
+use intsar_3d::material::{AlphaMode, MaterialHandle, TextureSource};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::{Mesh, MeshHandle};
+use intsar_3d::scene::Scene;
+use std::path::Path;
+
+fn assets() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/assets"))
+}
+
+fn triangles(mesh: &Mesh) -> Vec<[Vec3; 3]> {
+    let indices: Vec<u32> = mesh.indices().iter().collect();
+    indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|i| Vec3::from(mesh.vertices()[triangle[i] as usize].position)))
+        .collect()
+}
+
+fn parse_error(source: &str) -> (usize, String) {
+    match obj::parse_obj(source) {
+        Err(ObjError::Parse { line, message, .. }) => (line, message),
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn loads_meshes_and_materials() {
+    let model = obj::load_obj(assets().join("props.obj")).unwrap();
+
+    let names: Vec<&str> = model.meshes.iter().map(|m| m.mesh.name.as_str()).collect();
+    assert_eq!(names, ["Floor", "Crate"]);
+    assert_eq!(model.materials.len(), 2);
+
+    let tiles = &model.materials[model.meshes[0].material.unwrap()];
+    assert_eq!(tiles.name, "Tiles");
+    assert_eq!(tiles.base_color_texture, Some(TextureSource::File(assets().join("textures/tiles.png"))));
+    assert!((tiles.roughness - (2.0f32 / 32.0).sqrt()).abs() < 1e-6);
+    assert_eq!(tiles.alpha_mode, AlphaMode::Opaque);
+
+    let wood = &model.materials[model.meshes[1].material.unwrap()];
+    assert_eq!(wood.name, "Wood");
+    assert_eq!(wood.base_color.to_array(), [0.6, 0.3, 0.1, 0.5]);
+    assert_eq!(wood.alpha_mode, AlphaMode::Blend);
+    assert_eq!(wood.roughness, 0.7);
+    assert_eq!(wood.emissive, Vec3::new(0.1, 0.0, 0.0));
+    assert_eq!(wood.normal_texture, Some(TextureSource::File(assets().join("textures/wood_normal.png"))));
+}
+
+#[test]
+fn adds_one_object_per_mesh_to_scene() {
+    let mut scene = Scene::new();
+    obj::load_obj(assets().join("props.obj")).unwrap().add_to_scene(&mut scene, Transform::identity());
+
+    let crate_object = scene.find_object("Crate").unwrap();
+    let &material = scene.component::<MaterialHandle>(crate_object).unwrap();
+    assert_eq!(scene.material(material).unwrap().name, "Wood");
+    let &mesh = scene.component::<MeshHandle>(crate_object).unwrap();
+    assert_eq!(scene.mesh(mesh).unwrap().name, "Crate");
+}
+
+#[test]
+fn triangulates_concave_polygons() {
+    let model = obj::load_obj(assets().join("props.obj")).unwrap();
+    let floor = &model.meshes[0].mesh;
+
+    let triangles = triangles(floor);
+    assert_eq!(triangles.len(), 4);
+
+    // The triangles exactly cover the L shape (area 3) and all face up
+    let area: f32 = triangles.iter().map(|[a, b, c]| (*b - *a).cross(*c - *a).y * 0.5).sum();
+    assert!((area - 3.0).abs() < 1e-5);
+    for [a, b, c] in triangles {
+        assert!((b - a).cross(c - a).y > 0.0);
+    }
+
+    // V is flipped so the image's top row is at v = 0
+    let corner = floor.vertices().iter().find(|v| v.position == [-1.0, 0.0, -1.0]).unwrap();
+    assert_eq!(corner.uv, [0.0, 1.0]);
+}
+
+#[test]
+fn flat_shaded_faces_do_not_share_vertices() {
+    let model = obj::load_obj(assets().join("props.obj")).unwrap();
+    let crate_mesh = &model.meshes[1].mesh;
+
+    // `s off`: every face gets its own corners with the face normal
+    assert_eq!(crate_mesh.vertices().len(), 24);
+    assert_eq!(crate_mesh.indices().len(), 36);
+    for vertex in crate_mesh.vertices() {
+        let normal = Vec3::from(vertex.normal);
+        assert!((normal.length() - 1.0).abs() < 1e-5);
+        assert_eq!(normal.abs().max_element(), 1.0);
+        assert_eq!(vertex.color, [0.8, 0.4, 0.1]);
+    }
+    for [a, b, c] in triangles(crate_mesh) {
+        // Outward facing: the normal points away from the crate's center
+        let center = (a + b + c) / 3.0 - Vec3::new(0.0, 0.5, 0.0);
+        assert!((b - a).cross(c - a).dot(center) > 0.0);
+    }
+}
+
+#[test]
+fn smoothing_groups_share_averaged_normals() {
+    let source = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 -1
+v 0 1 -1
+s 1
+f 1 2 3 4
+f 5 1 4 6
+";
+    let model = obj::parse_obj(source).unwrap();
+    let mesh = &model.meshes[0].mesh;
+
+    // Corners shared by both faces are merged and their normals averaged
+    assert_eq!(mesh.vertices().len(), 6);
+    let shared = mesh.vertices().iter().find(|v| v.position == [0.0, 0.0, 0.0]).unwrap();
+    let expected = Vec3::new(-1.0, 0.0, 1.0).normalize();
+    assert!(Vec3::from(shared.normal).abs_diff_eq(expected, 1e-5));
+
+    // Different smoothing groups split the shared corners again
+    let split = obj::parse_obj(&source.replace("f 5 1 4 6", "s 2\nf 5 1 4 6")).unwrap();
+    assert_eq!(split.meshes[0].mesh.vertices().len(), 8);
+}
+
+#[test]
+fn explicit_normals_are_deduplicated() {
+    let source = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+f 1//1 2//1 3//1
+f 1//1 3//1 4//1
+";
+    let mesh = &obj::parse_obj(source).unwrap().meshes[0].mesh;
+    assert_eq!(mesh.vertices().len(), 4);
+    assert_eq!(mesh.indices().len(), 6);
+}
+
+#[test]
+fn reports_malformed_lines() {
+    assert_eq!(
+        parse_error("v 0 0 0\nv 1 0\n"),
+        (2, "expected 3 to 7 numbers in `v` statement, found 2".to_string())
+    );
+    assert_eq!(
+        parse_error("v 0 0 0\nvt 0.5 nope\n"),
+        (2, "invalid number `nope` in `vt` statement".to_string())
+    );
+    assert_eq!(
+        parse_error("v 0 0 0\nv 1 0 0\nv 1 1 0\n\nf 1 2 4\n"),
+        (5, "vertex index 4 out of range (3 defined so far)".to_string())
+    );
+    assert_eq!(
+        parse_error("v 0 0 0\nf 1 1\n"),
+        (2, "face needs at least 3 vertices, found 2".to_string())
+    );
+    assert_eq!(
+        parse_error("v 0 0 0\nf 1/1/1/1 1 1\n"),
+        (2, "malformed face corner `1/1/1/1`".to_string())
+    );
+
+    let error = obj::parse_obj("# header\nvn 0 x 1\n").unwrap_err();
+    assert_eq!(error.to_string(), "<obj>:2: invalid number `x` in `vn` statement");
+}
+
+#[test]
+fn reports_malformed_material_lines() {
+    let error = obj::parse_mtl("newmtl A\nKd 1 0\n", "a.mtl", Path::new("")).unwrap_err();
+    assert_eq!(error.to_string(), "a.mtl:2: expected 1 or 3 numbers in `Kd` statement, found 2");
+
+    let error = obj::parse_mtl("Kd 1 0 0\n", "b.mtl", Path::new("")).unwrap_err();
+    assert_eq!(error.to_string(), "b.mtl:1: `Kd` statement before any `newmtl`");
+}
+
+#[test]
+fn accepts_common_exporter_forms() {
+    let model = obj::load_obj(assets().join("exported.obj")).unwrap();
+    let brick = &model.materials[model.meshes[0].material.unwrap()];
+
+    // Single values are grey levels
+    assert_eq!(brick.base_color.to_array(), [0.5, 0.5, 0.5, 1.0]);
+    assert_eq!(brick.emissive, Vec3::splat(0.25));
+
+    // Options come before file names, which may contain spaces
+    let texture = |name: &str| Some(TextureSource::File(assets().join(name)));
+    assert_eq!(brick.base_color_texture, texture("textures/brick wall.png"));
+    assert_eq!(brick.normal_texture, texture("textures/brick normal.png"));
+    assert_eq!(brick.normal_scale, 0.5);
+
+    // `s on` smooths the two quads together, and `s off` leaves the last
+    // triangle flat
+    let mesh = &model.meshes[0].mesh;
+    assert_eq!(mesh.vertices().len(), 9);
+    let shared = mesh.vertices().iter().find(|v| v.position == [0.0, 0.0, 0.0]).unwrap();
+    assert!(Vec3::from(shared.normal).abs_diff_eq(Vec3::new(-1.0, 0.0, 1.0).normalize(), 1e-5));
+}
+
+#[test]
+fn reports_malformed_texture_maps() {
+    let error = obj::parse_mtl("newmtl A\nmap_Kd -bm\n", "a.mtl", Path::new("")).unwrap_err();
+    assert_eq!(error.to_string(), "a.mtl:2: `-bm` option of `map_Kd` statement is missing its value");
+
+    let error = obj::parse_mtl("newmtl A\nmap_Kd -s 2 2\n", "b.mtl", Path::new("")).unwrap_err();
+    assert_eq!(error.to_string(), "b.mtl:2: `map_Kd` statement without a file name");
+}
+
+#[test]
+fn missing_files_are_io_errors() {
+    match obj::load_obj(assets().join("does_not_exist.obj")) {
+        Err(ObjError::Io { path, .. }) => assert!(path.ends_with("does_not_exist.obj")),
+        other => panic!("expected an I/O error, got {other:?}"),
+    }
+}
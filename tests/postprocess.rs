@@ -0,0 +1,87 @@
+// Tests for post-processing shader effects
+
+use intsar_3d::postprocess::{PostEffect, PostEffectError, PostStage, ShaderEffect};
+use intsar_3d::renderer::Renderer;
+
+const INVERT: &str = "
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(post_input, post_sampler, in.uv);
+    return vec4<f32>(1.0 - color.rgb, color.a);
+}
+";
+
+#[test]
+fn compiles_against_the_prelude() {
+    let mut effect = ShaderEffect::new("Invert", PostStage::Display, INVERT).unwrap();
+    assert_eq!(effect.name(), "Invert");
+    assert_eq!(effect.stage(), PostStage::Display);
+    assert!(effect.enabled());
+
+    effect.set_enabled(false);
+    assert!(!effect.enabled());
+}
+
+#[test]
+fn stores_parameters_as_bytes() {
+    let effect = ShaderEffect::new("Invert", PostStage::Hdr, INVERT)
+        .unwrap()
+        .with_parameters(&[0.5f32, 2.0]);
+    assert_eq!(effect.parameters(), bytemuck::bytes_of(&[0.5f32, 2.0]));
+}
+
+#[test]
+fn reports_invalid_shaders() {
+    let err = ShaderEffect::new("Broken", PostStage::Display, "fn fs_main( {").err().unwrap();
+    assert!(matches!(&err, PostEffectError::Shader { name, .. } if name == "Broken"), "{err}");
+
+    // Type errors are caught by validation rather than parsing
+    let source = "@fragment fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> { return 1.0; }";
+    let err = ShaderEffect::new("Mistyped", PostStage::Display, source).err().unwrap();
+    assert!(matches!(err, PostEffectError::Shader { .. }), "{err}");
+}
+
+#[test]
+fn requires_a_fragment_entry_point() {
+    let source = "@fragment fn fs_other(in: FullscreenOutput) -> @location(0) vec4<f32> { return vec4<f32>(1.0); }";
+    let err = ShaderEffect::new("Unnamed", PostStage::Display, source).err().unwrap();
+    assert!(matches!(err, PostEffectError::MissingEntryPoint { .. }), "{err}");
+}
+
+#[test]
+fn pads_parameters_to_the_declared_uniform() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let Some(mut renderer) = runtime.block_on(Renderer::new_headless(8, 8)) else {
+        eprintln!("Skipping post effect test: no graphics adapter available");
+        return;
+    };
+
+    // A 32-byte parameter block, larger than the 16-byte minimum
+    let source = "
+        struct Parameters { tint: vec4<f32>, strength: f32 };
+        @group(1) @binding(0) var<uniform> parameters: Parameters;
+
+        @fragment
+        fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+            return vec4<f32>(parameters.tint.rgb * parameters.strength, 1.0);
+        }
+    ";
+    let effect = ShaderEffect::new("Tint", PostStage::Display, source).unwrap();
+    let handle = renderer.add_post_effect(effect);
+    let first_pixel = |renderer: &mut Renderer| {
+        renderer.render();
+        renderer.read_pixels().expect("Failed to read pixels")[..4].to_vec()
+    };
+
+    // Missing parameters read as zero
+    assert_eq!(first_pixel(&mut renderer), [0, 0, 0, 255]);
+
+    // So do the ones past the end of a smaller block
+    let tint = renderer.post_effect_mut::<ShaderEffect>(handle).unwrap();
+    tint.set_parameters(&[1.0f32; 4]);
+    assert_eq!(first_pixel(&mut renderer), [0, 0, 0, 255]);
+
+    let tint = renderer.post_effect_mut::<ShaderEffect>(handle).unwrap();
+    tint.set_parameters(&[1.0f32; 5]);
+    assert_eq!(first_pixel(&mut renderer), [255, 255, 255, 255]);
+}
@@ -0,0 +1,117 @@
+// Consistency checks for the procedural primitive generators
+
+use glam::{Vec2, Vec3};
+use intsar_3d::mesh::Mesh;
+use intsar_3d::primitives;
+
+fn all_primitives() -> Vec<Mesh> {
+    vec![
+        primitives::cube(1.0, 3),
+        primitives::plane(2.0, 1.0, 4, 2),
+        primitives::uv_sphere(0.5, 16, 8),
+        primitives::icosphere(0.5, 2),
+        primitives::cylinder(0.5, 1.0, 12, 2),
+        primitives::cone(0.5, 1.0, 12, 2),
+        primitives::torus(0.5, 0.2, 16, 8),
+        primitives::capsule(0.3, 1.0, 12, 4),
+    ]
+}
+
+#[test]
+fn normals_and_tangents_are_orthonormal() {
+    for mesh in all_primitives() {
+        for vertex in mesh.vertices() {
+            let normal = Vec3::from(vertex.normal);
+            let tangent = Vec3::from_slice(&vertex.tangent[..3]);
+            assert!((normal.length() - 1.0).abs() < 1e-4, "{}: normal not unit length", mesh.name);
+            assert!((tangent.length() - 1.0).abs() < 1e-4, "{}: tangent not unit length", mesh.name);
+            assert!(normal.dot(tangent).abs() < 1e-3, "{}: tangent not perpendicular", mesh.name);
+            assert!(vertex.tangent[3].abs() == 1.0, "{}: bad tangent handedness", mesh.name);
+        }
+    }
+}
+
+#[test]
+fn bitangents_point_towards_decreasing_v() {
+    let mesh = primitives::plane(2.0, 1.0, 4, 2);
+    let vertices = mesh.vertices();
+    let indices: Vec<u32> = mesh.indices().iter().collect();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let (edge1, edge2) = (Vec3::from(b.position) - Vec3::from(a.position), Vec3::from(c.position) - Vec3::from(a.position));
+        let (uv1, uv2) = (Vec2::from(b.uv) - Vec2::from(a.uv), Vec2::from(c.uv) - Vec2::from(a.uv));
+        // Direction in which V increases across the triangle
+        let increasing_v = (edge2 * uv1.x - edge1 * uv2.x) / (uv1.x * uv2.y - uv2.x * uv1.y);
+        for vertex in [a, b, c] {
+            let bitangent = Vec3::from(vertex.normal).cross(Vec3::from_slice(&vertex.tangent[..3])) * vertex.tangent[3];
+            assert!(bitangent.dot(increasing_v) < 0.0, "bitangent {bitangent} points towards increasing V {increasing_v}");
+        }
+    }
+}
+
+#[test]
+fn triangles_face_along_their_normals() {
+    for mesh in all_primitives() {
+        let vertices = mesh.vertices();
+        let indices: Vec<u32> = mesh.indices().iter().collect();
+        assert!(!indices.is_empty() && indices.len().is_multiple_of(3), "{}: bad index count", mesh.name);
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+            let face = (Vec3::from(b.position) - Vec3::from(a.position))
+                .cross(Vec3::from(c.position) - Vec3::from(a.position));
+            let normal = Vec3::from(a.normal) + Vec3::from(b.normal) + Vec3::from(c.normal);
+            assert!(face.dot(normal) > 0.0, "{}: triangle {triangle:?} is wound backwards", mesh.name);
+        }
+    }
+}
+
+#[test]
+fn uvs_are_normalized() {
+    for mesh in all_primitives() {
+        for vertex in mesh.vertices() {
+            let uv = Vec2::from(vertex.uv);
+            // Icosphere seam vertices wrap just past u = 1
+            assert!(uv.x >= 0.0 && uv.x <= 1.5 && uv.y >= 0.0 && uv.y <= 1.0, "{}: uv {uv} out of range", mesh.name);
+        }
+    }
+}
+
+#[test]
+fn tangents_follow_increasing_u() {
+    // On a plane facing +Y with U along +X the tangent must point along +X
+    let plane = primitives::plane(1.0, 1.0, 1, 1);
+    for vertex in plane.vertices() {
+        assert!(Vec3::from_slice(&vertex.tangent[..3]).abs_diff_eq(Vec3::X, 1e-5));
+    }
+}
+
+#[test]
+fn subdivision_controls_vertex_count() {
+    assert_eq!(primitives::cube(1.0, 1).vertices().len(), 6 * 4);
+    assert_eq!(primitives::cube(1.0, 2).vertices().len(), 6 * 9);
+    assert_eq!(primitives::plane(1.0, 1.0, 3, 2).vertices().len(), 4 * 3);
+    assert_eq!(primitives::uv_sphere(1.0, 8, 4).vertices().len(), 9 * 5);
+    // Each icosphere level splits every triangle into four
+    assert_eq!(primitives::icosphere(1.0, 0).indices().len(), 20 * 3);
+    assert_eq!(primitives::icosphere(1.0, 1).indices().len(), 80 * 3);
+}
+
+#[test]
+fn shapes_have_requested_dimensions() {
+    let extent = |mesh: &Mesh| {
+        mesh.vertices().iter().fold((Vec3::MAX, Vec3::MIN), |(min, max), v| {
+            (min.min(v.position.into()), max.max(v.position.into()))
+        })
+    };
+
+    let (min, max) = extent(&primitives::capsule(0.25, 1.0, 8, 4));
+    assert!((max.y - min.y - 1.5).abs() < 1e-5);
+    assert!((max.x - 0.25).abs() < 1e-5);
+
+    let (min, max) = extent(&primitives::torus(1.0, 0.25, 16, 8));
+    assert!((max.x - 1.25).abs() < 1e-5 && (max.y - 0.25).abs() < 1e-5 && (min.y + 0.25).abs() < 1e-5);
+
+    let (min, max) = extent(&primitives::cone(0.5, 2.0, 16, 1));
+    assert!((max.y - 1.0).abs() < 1e-5 && (min.y + 1.0).abs() < 1e-5);
+}
@@ -0,0 +1,103 @@
+// Tests for object handles and name lookup in Scene
+
+use intsar_3d::math::Transform;
+use intsar_3d::scene::{Scene, SceneObject};
+
+fn object(name: &str) -> SceneObject {
+    SceneObject::new(name.to_string(), Transform::identity())
+}
+
+#[test]
+fn handles_survive_removal_of_other_objects() {
+    let mut scene = Scene::new();
+    let a = scene.add_object(object("A"));
+    let b = scene.add_object(object("B"));
+    let c = scene.add_object(object("C"));
+
+    assert_eq!(scene.remove_object(b).unwrap().name(), "B");
+    assert_eq!(scene.object(a).unwrap().name(), "A");
+    assert_eq!(scene.object(c).unwrap().name(), "C");
+    assert!(scene.object(b).is_none());
+    assert!(scene.remove_object(b).is_none());
+    assert_eq!(scene.object_count(), 2);
+}
+
+#[test]
+fn stale_handles_do_not_resolve_to_reused_slots() {
+    let mut scene = Scene::new();
+    let old = scene.add_object(object("Old"));
+    scene.remove_object(old);
+
+    let new = scene.add_object(object("New"));
+    assert_ne!(old, new);
+    assert!(scene.object(old).is_none());
+    assert!(scene.object_mut(old).is_none());
+    assert_eq!(scene.object(new).unwrap().name(), "New");
+}
+
+#[test]
+fn removing_an_object_removes_its_descendants() {
+    let mut scene = Scene::new();
+    let root = scene.add_object(object("Root"));
+    let child = scene.add_object(object("Child").with_parent(root));
+    let grandchild = scene.add_object(object("Grandchild").with_parent(child));
+    let sibling = scene.add_object(object("Sibling").with_parent(root));
+
+    scene.remove_object(child);
+    assert!(!scene.contains(child));
+    assert!(!scene.contains(grandchild));
+    assert_eq!(scene.object(root).unwrap().children(), [sibling]);
+    assert!(scene.objects_named("Grandchild").is_empty());
+    assert_eq!(scene.object_count(), 2);
+}
+
+#[test]
+fn removed_objects_can_be_added_again() {
+    let mut scene = Scene::new();
+    let root = scene.add_object(object("Root"));
+    let child = scene.add_object(object("Child").with_parent(root));
+    scene.add_object(object("Grandchild").with_parent(child));
+
+    // The removed object no longer refers to its parent or its freed children
+    let removed = scene.remove_object(child).unwrap();
+    assert_eq!(removed.parent(), None);
+    assert!(removed.children().is_empty());
+
+    let again = scene.add_object(removed);
+    assert_eq!(scene.object(again).unwrap().parent(), None);
+    assert!(scene.object(again).unwrap().children().is_empty());
+    assert!(scene.object(root).unwrap().children().is_empty());
+    assert_eq!(scene.roots().collect::<Vec<_>>(), [root, again]);
+    assert_eq!(scene.find_object("Child"), Some(again));
+}
+
+#[test]
+fn name_lookup_reports_duplicates() {
+    let mut scene = Scene::new();
+    let first = scene.add_object(object("Crate"));
+    let second = scene.add_object(object("Crate"));
+    let lamp = scene.add_object(object("Lamp"));
+
+    assert_eq!(scene.objects_named("Crate"), [first, second]);
+    assert_eq!(scene.find_object("Crate"), None);
+    assert!(scene.get_object("Crate").is_none());
+    assert_eq!(scene.find_object("Lamp"), Some(lamp));
+    assert!(scene.objects_named("Missing").is_empty());
+
+    scene.remove_object(first);
+    assert_eq!(scene.find_object("Crate"), Some(second));
+}
+
+#[test]
+fn renaming_updates_the_name_index() {
+    let mut scene = Scene::new();
+    let handle = scene.add_object(object("Before"));
+
+    scene.rename(handle, "After".to_string()).unwrap();
+    assert_eq!(scene.find_object("After"), Some(handle));
+    assert_eq!(scene.find_object("Before"), None);
+    assert_eq!(scene.get_object("After").unwrap().name(), "After");
+
+    scene.remove_object(handle);
+    assert!(scene.rename(handle, "Again".to_string()).is_err());
+}
@@ -0,0 +1,254 @@
+// Tests for saving and loading scene files
+
+use glam::{Quat, Vec3, Vec4};
+use intsar_3d::camera::Camera;
+use intsar_3d::light::{Light, LightKind};
+use intsar_3d::material::{Material, MaterialHandle, TextureSource};
+use intsar_3d::math::Transform;
+use intsar_3d::mesh::{Mesh, MeshHandle};
+use intsar_3d::primitives;
+use intsar_3d::scene::{KeepTransform, Scene, SceneObject};
+use intsar_3d::scene_file::{self, SceneFileError, FORMAT_VERSION};
+use intsar_3d::shadow::{ShadowFlags, Shadows};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn sample_scene() -> Scene {
+    let mut scene = Scene::new();
+    let cube = scene.add_mesh(Mesh::cube());
+    let sphere = scene.add_mesh(primitives::icosphere(0.5, 1));
+
+    let mut painted = Material::new("Painted".to_string());
+    painted.base_color = Vec4::new(1.0, 0.5, 0.25, 1.0);
+    painted.metallic = 0.25;
+    painted.base_color_texture = Some(TextureSource::File(PathBuf::from("textures/paint.png")));
+    painted.normal_texture = Some(TextureSource::Embedded {
+        mime_type: Some("image/png".to_string()),
+        data: Arc::from(&b"\x89PNG"[..]),
+    });
+    let painted = scene.add_material(painted);
+
+    let transform = Transform::new(Vec3::new(1.0, 2.0, 3.0), Quat::from_rotation_y(0.5), Vec3::splat(2.0));
+    scene.add_object(SceneObject::new("Crate".to_string(), transform).with_mesh(cube).with_material(painted));
+    let flags = ShadowFlags { cast: false, receive: true };
+    scene.add_object(SceneObject::new("Ball".to_string(), Transform::identity()).with_mesh(sphere).with_shadow_flags(flags));
+    let mut lamp = Light::new(
+        LightKind::Spot {
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.6,
+        },
+        500.0,
+    );
+    lamp.shadows = Some(Shadows { depth_bias: 0.001, ..Shadows::default() });
+    scene.add_object(SceneObject::new("Lamp".to_string(), Transform::identity()).with_light(lamp));
+    scene.add_object(
+        SceneObject::new("Camera".to_string(), Transform::identity()).with_camera(Camera::Perspective {
+            fov_y: 0.8,
+            aspect_ratio: None,
+            near: 0.1,
+            far: Some(100.0),
+        }),
+    );
+    scene
+}
+
+fn parse_error(source: &str) -> (usize, String) {
+    match scene_file::scene_from_str(source) {
+        Err(SceneFileError::Parse { line, message, .. }) => (line, message),
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn round_trips_scenes() {
+    let scene = sample_scene();
+    let text = scene_file::scene_to_string(&scene).unwrap();
+    let loaded = scene_file::scene_from_str(&text).unwrap();
+
+    assert_eq!(loaded.object_count(), scene.object_count());
+    for ((loaded_handle, loaded_object), (handle, original)) in loaded.objects().zip(scene.objects()) {
+        assert_eq!(loaded_object.name(), original.name());
+        assert_eq!(loaded_object.transform.position, original.transform.position);
+        assert_eq!(loaded_object.transform.rotation, original.transform.rotation);
+        assert_eq!(loaded_object.transform.scale, original.transform.scale);
+        assert_eq!(
+            loaded.component::<MeshHandle>(loaded_handle),
+            scene.component::<MeshHandle>(handle)
+        );
+        assert_eq!(
+            loaded.component::<MaterialHandle>(loaded_handle),
+            scene.component::<MaterialHandle>(handle)
+        );
+        assert_eq!(loaded.component::<Camera>(loaded_handle), scene.component::<Camera>(handle));
+        assert_eq!(loaded.component::<Light>(loaded_handle), scene.component::<Light>(handle));
+        assert_eq!(
+            loaded.component::<ShadowFlags>(loaded_handle),
+            scene.component::<ShadowFlags>(handle)
+        );
+    }
+
+    assert_eq!(loaded.meshes().len(), 2);
+    for (loaded, original) in loaded.meshes().iter().zip(scene.meshes()) {
+        assert_eq!(loaded.name, original.name);
+        assert_eq!(loaded.vertices(), original.vertices());
+        assert_eq!(loaded.indices(), original.indices());
+    }
+    assert_eq!(loaded.materials(), scene.materials());
+
+    // Saving again gives the same text, so files diff cleanly
+    assert_eq!(scene_file::scene_to_string(&loaded).unwrap(), text);
+}
+
+#[test]
+fn saves_and_loads_files() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("saved_scene.ron");
+    scene_file::save_scene(&sample_scene(), &path).unwrap();
+
+    let loaded = scene_file::load_scene(&path).unwrap();
+    let names: Vec<&str> = loaded.objects().map(|(_, object)| object.name()).collect();
+    assert_eq!(names, ["Crate", "Ball", "Lamp", "Camera"]);
+}
+
+#[test]
+fn loads_hand_written_files() {
+    let source = format!(
+        "// A level layout
+(
+    version: {FORMAT_VERSION},
+    meshes: [],
+    materials: [(
+        name: \"Red\",
+        base_color: (1.0, 0.0, 0.0, 1.0),
+        metallic: 0.0,
+        roughness: 0.5,
+        emissive: (0.0, 0.0, 0.0),
+        base_color_texture: None,
+        metallic_roughness_texture: None,
+        normal_texture: None,
+        occlusion_texture: None,
+        emissive_texture: None,
+    )],
+    objects: [
+        (
+            name: \"Empty\",
+            transform: (position: (0.0, 1.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)),
+            material: Some(0),
+        ),
+    ],
+)
+"
+    );
+    let scene = scene_file::scene_from_str(&source).unwrap();
+    let handle = scene.find_object("Empty").unwrap();
+    assert_eq!(scene.object(handle).unwrap().transform.position, Vec3::Y);
+    assert_eq!(scene.component::<MeshHandle>(handle), None);
+    let &material = scene.component::<MaterialHandle>(handle).unwrap();
+    assert_eq!(scene.material(material).unwrap().name, "Red");
+}
+
+#[test]
+fn reports_parse_errors_with_line_numbers() {
+    let mut text = scene_file::scene_to_string(&sample_scene()).unwrap();
+    let line = text.lines().position(|line| line.contains("\"Ball\"")).unwrap() + 1;
+    text = text.replace("\"Ball\"", "Ball");
+
+    let (error_line, _) = parse_error(&text);
+    assert_eq!(error_line, line);
+
+    let (error_line, message) = parse_error("(\n    version: \"one\",\n)");
+    assert_eq!(error_line, 2);
+    assert!(message.contains("integer"), "{message}");
+}
+
+#[test]
+fn round_trips_hierarchies() {
+    let mut scene = sample_scene();
+    let crate_object = scene.roots().next().unwrap();
+    let ball = scene.roots().nth(1).unwrap();
+    let lid = scene.add_object(SceneObject::new("Lid".to_string(), Transform::identity()).with_parent(crate_object));
+    // Parent stored after its child
+    scene.set_parent(crate_object, Some(ball), KeepTransform::Local).unwrap();
+
+    let loaded = scene_file::scene_from_str(&scene_file::scene_to_string(&scene).unwrap()).unwrap();
+    assert_eq!(loaded.object(lid).unwrap().parent(), Some(crate_object));
+    assert_eq!(loaded.object(crate_object).unwrap().parent(), Some(ball));
+    assert_eq!(loaded.object(ball).unwrap().children(), [crate_object]);
+    assert_eq!(loaded.world_matrices(), scene.world_matrices());
+}
+
+#[test]
+fn upgrades_version_1_files() {
+    // Written by the first version of the format
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/assets/scene_v1.ron");
+    let scene = scene_file::load_scene(path).unwrap();
+
+    let names: Vec<&str> = scene.roots().map(|handle| scene.object(handle).unwrap().name()).collect();
+    assert_eq!(names, ["Sign", "Sun", "Camera"]);
+    let sign = scene.find_object("Sign").unwrap();
+    assert_eq!(scene.object(sign).unwrap().transform.position, Vec3::new(1.0, 2.0, 3.0));
+    assert_eq!(scene.mesh(*scene.component::<MeshHandle>(sign).unwrap()).unwrap().name, "Triangle");
+    let material = scene.material(*scene.component::<MaterialHandle>(sign).unwrap()).unwrap();
+    assert_eq!(material.base_color, Vec4::new(1.0, 0.5, 0.25, 1.0));
+    assert_eq!(scene.component::<ShadowFlags>(sign), None);
+    let sun = scene.component::<Light>(scene.find_object("Sun").unwrap()).unwrap();
+    assert_eq!((sun.kind, sun.intensity), (LightKind::Directional, 3.0));
+    assert!(scene.component::<Camera>(scene.find_object("Camera").unwrap()).is_some());
+}
+
+#[test]
+fn reads_each_version_with_its_own_layout() {
+    // Parents only exist from version 2 on
+    let source = |version| {
+        format!(
+            "(version: {version}, meshes: [], materials: [], objects: [
+                (name: \"A\", transform: (position: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0))),
+                (name: \"B\", transform: (position: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)), parent: Some(0)),
+            ])"
+        )
+    };
+    let (_, message) = parse_error(&source(1));
+    assert!(message.contains("parent"), "{message}");
+
+    let scene = scene_file::scene_from_str(&source(2)).unwrap();
+    let [a, b] = ["A", "B"].map(|name| scene.find_object(name).unwrap());
+    assert_eq!(scene.object(b).unwrap().parent(), Some(a));
+}
+
+#[test]
+fn rejects_hierarchy_cycles() {
+    let source = format!(
+        "(version: {FORMAT_VERSION}, meshes: [], materials: [], objects: [
+            (name: \"A\", transform: (position: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)), parent: Some(1)),
+            (name: \"B\", transform: (position: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)), parent: Some(0)),
+        ])"
+    );
+    let error = scene_file::scene_from_str(&source).unwrap_err();
+    assert!(matches!(error, SceneFileError::Invalid { .. }));
+    assert!(error.to_string().contains("ancestor"), "{error}");
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    let source = "(version: 999, meshes: [], materials: [], objects: [])";
+    let error = scene_file::scene_from_str(source).unwrap_err();
+    assert!(matches!(error, SceneFileError::UnsupportedVersion { version: 999, .. }));
+}
+
+#[test]
+fn rejects_dangling_references() {
+    let source = format!(
+        "(version: {FORMAT_VERSION}, meshes: [], materials: [], objects: [
+            (name: \"Ghost\", transform: (position: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)), mesh: Some(3)),
+        ])"
+    );
+    let error = scene_file::scene_from_str(&source).unwrap_err();
+    assert!(matches!(error, SceneFileError::Invalid { .. }));
+    assert!(error.to_string().contains("Ghost"), "{error}");
+}
+
+#[test]
+fn missing_files_are_errors() {
+    let error = scene_file::load_scene("does_not_exist.ron").unwrap_err();
+    assert!(matches!(error, SceneFileError::Io { .. }));
+    assert!(error.to_string().contains("does_not_exist.ron"));
+}
@@ -0,0 +1,107 @@
+// Tests for shadow cascade placement
+
+use approx::assert_relative_eq;
+use glam::{Mat4, Vec3};
+use intsar_3d::shadow::{self, CascadeSplit, Cascades, Frustum};
+
+fn frustum(position: Vec3, target: Vec3) -> Frustum {
+    Frustum {
+        view: Mat4::look_at_rh(position, target, Vec3::Y),
+        fov_y: 45.0_f32.to_radians(),
+        aspect_ratio: 1.5,
+        near: 0.1,
+        far: 100.0,
+    }
+}
+
+fn cascades(split: CascadeSplit) -> Cascades {
+    Cascades {
+        count: 4,
+        split,
+        max_distance: 20.0,
+        ..Cascades::default()
+    }
+}
+
+#[test]
+fn splits_view_distance_by_scheme() {
+    let frustum = frustum(Vec3::ZERO, Vec3::NEG_Z);
+
+    let uniform = shadow::cascade_splits(&frustum, &cascades(CascadeSplit::Uniform));
+    assert_eq!(uniform.len(), 5);
+    for (split, expected) in uniform.iter().zip([0.1, 5.075, 10.05, 15.025, 20.0]) {
+        assert_relative_eq!(*split, expected, epsilon = 1e-4);
+    }
+
+    // Each logarithmic cascade is the same ratio longer than the last
+    let logarithmic = shadow::cascade_splits(&frustum, &cascades(CascadeSplit::Logarithmic));
+    let ratio = (200.0f32).powf(0.25);
+    for pair in logarithmic.windows(2) {
+        assert_relative_eq!(pair[1] / pair[0], ratio, epsilon = 1e-3);
+    }
+
+    let practical = shadow::cascade_splits(&frustum, &cascades(CascadeSplit::Practical { lambda: 0.5 }));
+    for ((split, uniform), logarithmic) in practical.iter().zip(&uniform).zip(&logarithmic) {
+        assert_relative_eq!(*split, (uniform + logarithmic) * 0.5, epsilon = 1e-4);
+    }
+    assert_eq!((practical[0], practical[4]), (0.1, 20.0));
+}
+
+#[test]
+fn limits_cascades_to_the_frustum() {
+    let mut frustum = frustum(Vec3::ZERO, Vec3::NEG_Z);
+    frustum.far = 8.0;
+    let settings = Cascades {
+        count: 9,
+        ..cascades(CascadeSplit::Uniform)
+    };
+    let splits = shadow::cascade_splits(&frustum, &settings);
+    assert_eq!(splits.len(), shadow::MAX_CASCADES + 1);
+    assert_eq!(splits.last(), Some(&8.0));
+}
+
+#[test]
+fn covers_the_frustum_slice() {
+    let frustum = frustum(Vec3::new(1.0, 2.0, 5.0), Vec3::ZERO);
+    let direction = Vec3::new(0.3, -1.0, 0.2).normalize();
+    let view_proj = shadow::cascade_view_proj(&frustum, 1.0, 6.0, direction, (Vec3::ZERO, 1.0), 1024, false);
+
+    // Points on the view axis inside the slice land inside the map
+    let to_world = frustum.view.inverse();
+    for distance in [1.0, 3.0, 6.0] {
+        let point = to_world.transform_point3(Vec3::new(0.0, 0.0, -distance));
+        let ndc = view_proj.project_point3(point);
+        assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "{ndc}");
+        assert!((0.0..=1.0).contains(&ndc.z), "{ndc}");
+    }
+
+    // Casters between the light and the slice are kept in front of the far plane
+    let caster = shadow::cascade_view_proj(&frustum, 1.0, 6.0, direction, (-direction * 30.0, 1.0), 1024, false)
+        .project_point3(-direction * 30.0);
+    assert!((0.0..=1.0).contains(&caster.z), "{caster}");
+}
+
+#[test]
+fn stabilized_cascades_move_in_whole_texels() {
+    let direction = Vec3::new(0.3, -1.0, 0.2).normalize();
+    let casters = (Vec3::ZERO, 10.0);
+    let map_size = 512;
+    let first = frustum(Vec3::new(0.0, 2.0, 5.0), Vec3::ZERO);
+    let moved = frustum(Vec3::new(0.013, 2.0, 5.0), Vec3::new(0.013, 0.0, 0.0));
+    let turned = frustum(Vec3::new(0.0, 2.0, 5.0), Vec3::new(1.0, 0.0, 0.0));
+
+    let a = shadow::cascade_view_proj(&first, 1.0, 6.0, direction, casters, map_size, true);
+    let b = shadow::cascade_view_proj(&moved, 1.0, 6.0, direction, casters, map_size, true);
+    let c = shadow::cascade_view_proj(&turned, 1.0, 6.0, direction, casters, map_size, true);
+
+    // Turning the camera keeps the texel size
+    assert_relative_eq!(a.row(0).truncate().length(), c.row(0).truncate().length(), epsilon = 1e-5);
+    assert_relative_eq!(a.row(1).truncate().length(), c.row(1).truncate().length(), epsilon = 1e-5);
+
+    // A world point shifts by a whole number of texels when the camera moves
+    let texel = 2.0 / map_size as f32;
+    let point = Vec3::new(0.5, 0.0, 0.5);
+    let shift = (b.project_point3(point) - a.project_point3(point)).truncate() / texel;
+    assert_relative_eq!(shift.x, shift.x.round(), epsilon = 1e-2);
+    assert_relative_eq!(shift.y, shift.y.round(), epsilon = 1e-2);
+}
@@ -0,0 +1,30 @@
+// Tests for the screen-space ambient occlusion kernel
+
+use intsar_3d::ssao::{self, MAX_SSAO_SAMPLES};
+
+#[test]
+fn kernel_fills_the_hemisphere() {
+    for count in [1, 8, 16, MAX_SSAO_SAMPLES] {
+        let kernel = ssao::hemisphere_kernel(count);
+        assert_eq!(kernel.len(), count);
+        for offset in &kernel {
+            assert!(offset.z > 0.0, "{offset} is below the surface");
+            assert!(offset.length() <= 1.0, "{offset} is outside the hemisphere");
+            assert!(offset.length() >= 0.1, "{offset} is too close to the center");
+        }
+    }
+}
+
+#[test]
+fn kernel_spreads_around_the_normal() {
+    // The samples lean no way in particular, so flat surfaces aren't
+    // occluded from one side
+    let kernel = ssao::hemisphere_kernel(32);
+    let lean = kernel.iter().map(|offset| offset.truncate().normalize()).sum::<glam::Vec2>() / 32.0;
+    assert!(lean.length() < 0.1, "kernel leans towards {lean}");
+}
+
+#[test]
+fn kernel_is_deterministic() {
+    assert_eq!(ssao::hemisphere_kernel(16), ssao::hemisphere_kernel(16));
+}
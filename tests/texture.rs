@@ -0,0 +1,112 @@
+// Tests for texture decoding and mipmap generation
+
+use image::{DynamicImage, Rgb32FImage, RgbaImage};
+use intsar_3d::import::gltf;
+use intsar_3d::material::{MaterialHandle, TextureSource};
+use intsar_3d::texture::{ColorSpace, TextureData, TextureError};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A 2x2 image with black and white texels on the diagonals.
+fn checker() -> RgbaImage {
+    RgbaImage::from_fn(2, 2, |x, y| {
+        let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+        image::Rgba([value, value, value, 255])
+    })
+}
+
+fn png(image: &RgbaImage) -> Arc<[u8]> {
+    let mut data = Cursor::new(Vec::new());
+    image.write_to(&mut data, image::ImageFormat::Png).unwrap();
+    Arc::from(data.into_inner())
+}
+
+#[test]
+fn averages_mip_levels_in_linear_space() {
+    let srgb = TextureData::from_image(checker().into(), ColorSpace::Srgb);
+    assert_eq!(srgb.format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(srgb.mip_level_count(), 2);
+    assert_eq!(srgb.level(0), checker().as_raw().as_slice());
+    // Half of linear white is 188 once sRGB encoded
+    assert_eq!(srgb.level(1), [188, 188, 188, 255]);
+
+    let linear = TextureData::from_image(checker().into(), ColorSpace::Linear);
+    assert_eq!(linear.format(), wgpu::TextureFormat::Rgba8Unorm);
+    assert_eq!(linear.level(1), [128, 128, 128, 255]);
+}
+
+#[test]
+fn generates_mip_chains_for_any_size() {
+    // Black but for a white last column
+    let image = RgbaImage::from_fn(5, 2, |x, _| {
+        let value = if x == 4 { 255 } else { 0 };
+        image::Rgba([value, value, value, 255])
+    });
+    let texture = TextureData::from_image(image.into(), ColorSpace::Linear);
+    assert_eq!((texture.width(), texture.height()), (5, 2));
+    // 5x2, 2x1, 1x1
+    assert_eq!(texture.mip_level_count(), 3);
+    // The odd column is shared out rather than dropped, and stays on the right
+    assert_eq!(texture.level(1), [0, 0, 0, 255, 102, 102, 102, 255]);
+    assert_eq!(texture.level(2), [51, 51, 51, 255]);
+}
+
+#[test]
+fn averages_every_texel_of_odd_levels() {
+    // A single white texel in the bottom right corner of a 3x3 image
+    let image = RgbaImage::from_fn(3, 3, |x, y| {
+        let value = if (x, y) == (2, 2) { 255 } else { 0 };
+        image::Rgba([value, value, value, 255])
+    });
+    let texture = TextureData::from_image(image.into(), ColorSpace::Linear);
+    assert_eq!(texture.mip_level_count(), 2);
+    assert_eq!(texture.level(1), [28, 28, 28, 255]);
+}
+
+#[test]
+fn keeps_hdr_images_as_half_floats() {
+    let image = Rgb32FImage::from_fn(2, 1, |x, _| image::Rgb([1.0 + x as f32, 0.5, 0.0]));
+    let texture = TextureData::from_image(DynamicImage::ImageRgb32F(image), ColorSpace::Srgb);
+    assert_eq!(texture.format(), wgpu::TextureFormat::Rgba16Float);
+
+    let halves: Vec<u16> = texture.level(0)
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+    // 1.0, 0.5, 0.0, 1.0 (alpha) then 2.0, 0.5, 0.0, 1.0
+    assert_eq!(halves, [0x3c00, 0x3800, 0, 0x3c00, 0x4000, 0x3800, 0, 0x3c00]);
+    // The average of 1.0 and 2.0 is 1.5
+    assert_eq!(&texture.level(1)[..2], 0x3e00u16.to_le_bytes());
+}
+
+#[test]
+fn loads_embedded_and_imported_images() {
+    let source = TextureSource::Embedded {
+        mime_type: Some("image/png".to_string()),
+        data: png(&checker()),
+    };
+    let texture = TextureData::load(&source, ColorSpace::Srgb).unwrap();
+    assert_eq!(texture.level(0), checker().as_raw().as_slice());
+
+    let path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/assets/scene.gltf"));
+    let scene = gltf::load_gltf(path).unwrap();
+    let &material = scene.component::<MaterialHandle>(scene.find_object("Child").unwrap()).unwrap();
+    let source = scene.material(material).unwrap().base_color_texture.as_ref().unwrap();
+    assert!(TextureData::load(source, ColorSpace::Srgb).unwrap().width() > 0);
+}
+
+#[test]
+fn reports_unreadable_images() {
+    let missing = TextureSource::File("does_not_exist.png".into());
+    let error = TextureData::load(&missing, ColorSpace::Srgb).unwrap_err();
+    assert!(matches!(error, TextureError::Io { .. }));
+    assert!(error.to_string().contains("does_not_exist.png"));
+
+    let garbage = TextureSource::Embedded {
+        mime_type: None,
+        data: Arc::from(&b"not an image"[..]),
+    };
+    let error = TextureData::load(&garbage, ColorSpace::Linear).unwrap_err();
+    assert!(matches!(error, TextureError::Decode { .. }));
+}